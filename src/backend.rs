@@ -0,0 +1,655 @@
+// SPDX-License-Identifier: MIT
+
+//! The interface to HID devices: enumeration, report descriptors and
+//! Feature Report requests.
+//!
+//! [HidrawBackend] talks to the kernel's hidraw devices on Linux,
+//! [crate::HidapiBackend] (with the `hidapi` feature) to the devices of
+//! any platform hidapi supports. [MockBackend] keeps devices in memory so
+//! the report handling can be exercised without hardware. [CachingBackend] wraps either to avoid fetching the
+//! same report over and over, e.g. for every redraw of an interactive view.
+
+#[cfg(target_os = "linux")]
+use crate::{hidraw_devices, report_descriptor_bytes, FeatureDevice};
+use crate::{pack_field, ReportType};
+use anyhow::{bail, Context, Result};
+use hidreport::Report;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Access to HID devices, identified by their path.
+///
+/// Report bytes always include the Report ID if the report has one, a
+/// report without a Report ID has the `report_id` `None`.
+pub trait HidBackend {
+    /// Returns the available devices
+    fn devices(&self) -> Result<Vec<crate::HidrawDevice>>;
+
+    /// Returns the device's report descriptor bytes
+    fn report_descriptor(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Fetches `size` bytes of the report
+    fn get_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>>;
+
+    /// Sends the report
+    fn set_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        bytes: &[u8],
+    ) -> Result<()>;
+
+    /// Fetches `size` bytes of the Feature Report
+    fn get_feature_report(
+        &self,
+        path: &Path,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        self.get_report(path, ReportType::Feature, report_id, size)
+    }
+
+    /// Sends the Feature Report
+    fn set_feature_report(&self, path: &Path, report_id: Option<u8>, bytes: &[u8]) -> Result<()> {
+        self.set_report(path, ReportType::Feature, report_id, bytes)
+    }
+
+    /// Returns a backend that keeps the device open for all requests sent
+    /// through it, opening some (e.g. Bluetooth) devices is slow. Requests
+    /// for other devices are passed on to this backend.
+    fn open_device<'a>(&'a self, _path: &Path) -> Result<Box<dyn HidBackend + 'a>> {
+        Ok(Box::new(self))
+    }
+}
+
+impl<B: HidBackend + ?Sized> HidBackend for &B {
+    fn devices(&self) -> Result<Vec<crate::HidrawDevice>> {
+        (**self).devices()
+    }
+
+    fn report_descriptor(&self, path: &Path) -> Result<Vec<u8>> {
+        (**self).report_descriptor(path)
+    }
+
+    fn get_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        (**self).get_report(path, report_type, report_id, size)
+    }
+
+    fn set_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        (**self).set_report(path, report_type, report_id, bytes)
+    }
+
+    fn open_device<'a>(&'a self, path: &Path) -> Result<Box<dyn HidBackend + 'a>> {
+        (**self).open_device(path)
+    }
+}
+
+impl<B: HidBackend + ?Sized> HidBackend for Box<B> {
+    fn devices(&self) -> Result<Vec<crate::HidrawDevice>> {
+        (**self).devices()
+    }
+
+    fn report_descriptor(&self, path: &Path) -> Result<Vec<u8>> {
+        (**self).report_descriptor(path)
+    }
+
+    fn get_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        (**self).get_report(path, report_type, report_id, size)
+    }
+
+    fn set_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        (**self).set_report(path, report_type, report_id, bytes)
+    }
+
+    fn open_device<'a>(&'a self, path: &Path) -> Result<Box<dyn HidBackend + 'a>> {
+        (**self).open_device(path)
+    }
+}
+
+/// The backend for the kernel's /dev/hidraw nodes. Feature Report
+/// requests go through [FeatureDevice], i.e. via the helper if one is set.
+#[cfg(target_os = "linux")]
+pub struct HidrawBackend;
+
+#[cfg(target_os = "linux")]
+impl HidBackend for HidrawBackend {
+    fn devices(&self) -> Result<Vec<crate::HidrawDevice>> {
+        hidraw_devices()
+    }
+
+    fn report_descriptor(&self, path: &Path) -> Result<Vec<u8>> {
+        report_descriptor_bytes(path)
+    }
+
+    fn get_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        get_hidraw_report(&FeatureDevice::open(path)?, report_type, report_id, size)
+    }
+
+    fn set_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        set_hidraw_report(&FeatureDevice::open(path)?, report_type, report_id, bytes)
+    }
+
+    fn open_device<'a>(&'a self, path: &Path) -> Result<Box<dyn HidBackend + 'a>> {
+        Ok(Box::new(OpenHidrawDevice {
+            path: path.to_path_buf(),
+            device: FeatureDevice::open(path)?,
+        }))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn get_hidraw_report(
+    device: &FeatureDevice,
+    report_type: ReportType,
+    report_id: Option<u8>,
+    size: usize,
+) -> Result<Vec<u8>> {
+    let fetch_size = match report_id {
+        Some(_) => size,
+        None => size + 1,
+    };
+    let mut r = device.get_report(report_type, report_id.unwrap_or(0), fetch_size)?;
+    r.truncate(size);
+    Ok(r)
+}
+
+#[cfg(target_os = "linux")]
+fn set_hidraw_report(
+    device: &FeatureDevice,
+    report_type: ReportType,
+    report_id: Option<u8>,
+    bytes: &[u8],
+) -> Result<()> {
+    // The ioctl needs the report ID as first byte, even if it is 0
+    let buf = match report_id {
+        Some(_) => bytes.to_vec(),
+        None => [&[0], bytes].concat(),
+    };
+    device.send_report(report_type, &buf)
+}
+
+/// A hidraw device kept open by [HidrawBackend::open_device]
+#[cfg(target_os = "linux")]
+struct OpenHidrawDevice {
+    path: PathBuf,
+    device: FeatureDevice,
+}
+
+#[cfg(target_os = "linux")]
+impl HidBackend for OpenHidrawDevice {
+    fn devices(&self) -> Result<Vec<crate::HidrawDevice>> {
+        HidrawBackend.devices()
+    }
+
+    fn report_descriptor(&self, path: &Path) -> Result<Vec<u8>> {
+        HidrawBackend.report_descriptor(path)
+    }
+
+    fn get_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        if path != self.path {
+            return HidrawBackend.get_report(path, report_type, report_id, size);
+        }
+        get_hidraw_report(&self.device, report_type, report_id, size)
+    }
+
+    fn set_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        if path != self.path {
+            return HidrawBackend.set_report(path, report_type, report_id, bytes);
+        }
+        set_hidraw_report(&self.device, report_type, report_id, bytes)
+    }
+}
+
+/// The default backend where no backend is available, e.g. without the
+/// `hidapi` feature off Linux. Every request fails with the reason.
+#[cfg(not(target_os = "linux"))]
+pub(crate) struct UnavailableBackend(pub String);
+
+#[cfg(not(target_os = "linux"))]
+impl HidBackend for UnavailableBackend {
+    fn devices(&self) -> Result<Vec<crate::HidrawDevice>> {
+        bail!("No device access: {}", self.0)
+    }
+
+    fn report_descriptor(&self, _path: &Path) -> Result<Vec<u8>> {
+        bail!("No device access: {}", self.0)
+    }
+
+    fn get_report(
+        &self,
+        _path: &Path,
+        _report_type: ReportType,
+        _report_id: Option<u8>,
+        _size: usize,
+    ) -> Result<Vec<u8>> {
+        bail!("No device access: {}", self.0)
+    }
+
+    fn set_report(
+        &self,
+        _path: &Path,
+        _report_type: ReportType,
+        _report_id: Option<u8>,
+        _bytes: &[u8],
+    ) -> Result<()> {
+        bail!("No device access: {}", self.0)
+    }
+}
+
+/// A device of the [MockBackend]
+pub struct MockDevice {
+    pub info: crate::HidrawDevice,
+    pub rdesc: Vec<u8>,
+    /// The current report values by type and Report ID, 0 for a report
+    /// without a Report ID
+    pub reports: HashMap<(ReportType, u8), Vec<u8>>,
+}
+
+/// An in-memory backend. Reports read back what was last written and
+/// every request is logged, see [MockBackend::requests].
+#[derive(Default)]
+pub struct MockBackend {
+    devices: Mutex<Vec<MockDevice>>,
+    requests: Mutex<Vec<MockRequest>>,
+}
+
+/// A report request as logged by the [MockBackend]
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockRequest {
+    Get {
+        path: PathBuf,
+        report_type: ReportType,
+        report_id: Option<u8>,
+    },
+    Set {
+        path: PathBuf,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        bytes: Vec<u8>,
+    },
+}
+
+impl MockBackend {
+    pub fn new() -> MockBackend {
+        MockBackend::default()
+    }
+
+    pub fn add_device(&self, device: MockDevice) {
+        self.devices.lock().unwrap().push(device);
+    }
+
+    /// Returns the requests sent so far, oldest first
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Returns the current value of the report
+    pub fn report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+    ) -> Option<Vec<u8>> {
+        self.with_device(path, |device| {
+            Ok(device
+                .reports
+                .get(&(report_type, report_id.unwrap_or(0)))
+                .cloned())
+        })
+        .ok()
+        .flatten()
+    }
+
+    /// Returns the current value of the Feature Report
+    pub fn feature_report(&self, path: &Path, report_id: Option<u8>) -> Option<Vec<u8>> {
+        self.report(path, ReportType::Feature, report_id)
+    }
+
+    fn with_device<T>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&mut MockDevice) -> Result<T>,
+    ) -> Result<T> {
+        let mut devices = self.devices.lock().unwrap();
+        let device = devices
+            .iter_mut()
+            .find(|d| d.info.path == path)
+            .with_context(|| format!("No such device {}", path.display()))?;
+        f(device)
+    }
+}
+
+impl HidBackend for MockBackend {
+    fn devices(&self) -> Result<Vec<crate::HidrawDevice>> {
+        Ok(self
+            .devices
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|d| d.info.clone())
+            .collect())
+    }
+
+    fn report_descriptor(&self, path: &Path) -> Result<Vec<u8>> {
+        self.with_device(path, |device| Ok(device.rdesc.clone()))
+    }
+
+    fn get_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        self.requests.lock().unwrap().push(MockRequest::Get {
+            path: path.to_path_buf(),
+            report_type,
+            report_id,
+        });
+        self.with_device(path, |device| {
+            let Some(bytes) = device.reports.get(&(report_type, report_id.unwrap_or(0))) else {
+                return Err(std::io::Error::from_raw_os_error(libc::EIO).into());
+            };
+            let mut bytes = bytes.clone();
+            bytes.resize(size, 0);
+            Ok(bytes)
+        })
+    }
+
+    fn set_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        self.requests.lock().unwrap().push(MockRequest::Set {
+            path: path.to_path_buf(),
+            report_type,
+            report_id,
+            bytes: bytes.to_vec(),
+        });
+        self.with_device(path, |device| {
+            device
+                .reports
+                .insert((report_type, report_id.unwrap_or(0)), bytes.to_vec());
+            Ok(())
+        })
+    }
+}
+
+/// A Feature Report as last fetched by the [CachingBackend]
+#[derive(Clone, Debug)]
+pub struct CachedReport {
+    pub bytes: Vec<u8>,
+    pub fetched: Instant,
+}
+
+impl CachedReport {
+    /// How long ago the report was fetched from the device
+    pub fn age(&self) -> Duration {
+        self.fetched.elapsed()
+    }
+}
+
+/// A report of a device: its path, type and Report ID
+type ReportKey = (PathBuf, ReportType, Option<u8>);
+
+/// A backend that caches the reports fetched through another
+/// backend. Some devices visibly stall their input stream during a
+/// GET_REPORT, so a cached report is only fetched again once it is older
+/// than the maximum age or after [CachingBackend::refresh] or
+/// [CachingBackend::invalidate]. Writing a report invalidates it since the
+/// device may not keep all bits as written.
+pub struct CachingBackend<B: HidBackend> {
+    backend: B,
+    max_age: Duration,
+    cache: Mutex<HashMap<ReportKey, CachedReport>>,
+}
+
+impl<B: HidBackend> CachingBackend<B> {
+    pub fn new(backend: B, max_age: Duration) -> CachingBackend<B> {
+        CachingBackend {
+            backend,
+            max_age,
+            cache: Mutex::default(),
+        }
+    }
+
+    /// The wrapped backend
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Returns the cached report regardless of its age, e.g. to show how
+    /// stale a displayed value is
+    pub fn cached(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+    ) -> Option<CachedReport> {
+        self.cache
+            .lock()
+            .unwrap()
+            .get(&(path.to_path_buf(), report_type, report_id))
+            .cloned()
+    }
+
+    /// Fetches the report from the device even if a cached report is
+    /// still fresh
+    pub fn refresh(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        let bytes = self
+            .backend
+            .get_report(path, report_type, report_id, size)?;
+        self.cache.lock().unwrap().insert(
+            (path.to_path_buf(), report_type, report_id),
+            CachedReport {
+                bytes: bytes.clone(),
+                fetched: Instant::now(),
+            },
+        );
+        Ok(bytes)
+    }
+
+    /// Drops the cached report so the next read fetches it
+    pub fn invalidate(&self, path: &Path, report_type: ReportType, report_id: Option<u8>) {
+        self.cache
+            .lock()
+            .unwrap()
+            .remove(&(path.to_path_buf(), report_type, report_id));
+    }
+
+    /// Drops all cached reports
+    pub fn invalidate_all(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+}
+
+impl<B: HidBackend> HidBackend for CachingBackend<B> {
+    fn devices(&self) -> Result<Vec<crate::HidrawDevice>> {
+        self.backend.devices()
+    }
+
+    fn report_descriptor(&self, path: &Path) -> Result<Vec<u8>> {
+        self.backend.report_descriptor(path)
+    }
+
+    fn get_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        match self.cached(path, report_type, report_id) {
+            Some(cached) if cached.age() <= self.max_age && cached.bytes.len() == size => {
+                Ok(cached.bytes)
+            }
+            _ => self.refresh(path, report_type, report_id, size),
+        }
+    }
+
+    fn set_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        self.invalidate(path, report_type, report_id);
+        self.backend.set_report(path, report_type, report_id, bytes)
+    }
+}
+
+/// A Feature Report of a device, for reading and writing the report's
+/// bytes. The bytes always include the Report ID if the report has one.
+/// See [FeatureReportHandle::with_report_type] for Input and Output
+/// Reports.
+pub struct FeatureReportHandle<'a> {
+    backend: &'a dyn HidBackend,
+    path: &'a Path,
+    report_type: ReportType,
+    report_id: Option<u8>,
+    size: usize,
+}
+
+impl<'a> FeatureReportHandle<'a> {
+    pub fn new<R: Report>(
+        backend: &'a dyn HidBackend,
+        path: &'a Path,
+        report: &R,
+    ) -> FeatureReportHandle<'a> {
+        FeatureReportHandle {
+            backend,
+            path,
+            report_type: ReportType::Feature,
+            report_id: report.report_id().map(u8::from),
+            size: report.size_in_bytes(),
+        }
+    }
+
+    /// Accesses the report as a report of this type, e.g. for a report
+    /// from `ReportDescriptor::input_reports()`
+    pub fn with_report_type(self, report_type: ReportType) -> FeatureReportHandle<'a> {
+        FeatureReportHandle {
+            report_type,
+            ..self
+        }
+    }
+
+    /// The Report ID or `None` if the device does not use Report IDs
+    pub fn report_id(&self) -> Option<u8> {
+        self.report_id
+    }
+
+    /// The size of the report in bytes, including the Report ID if any
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Fetches the report from the device
+    pub fn read(&self) -> Result<Vec<u8>> {
+        self.backend
+            .get_report(self.path, self.report_type, self.report_id, self.size)
+    }
+
+    /// Sends the report bytes as returned by [FeatureReportHandle::read]
+    pub fn write(&self, bytes: &[u8]) -> Result<()> {
+        if bytes.len() != self.size {
+            bail!(
+                "Expected {} bytes for this report but got {}",
+                self.size,
+                bytes.len()
+            );
+        }
+        self.backend
+            .set_report(self.path, self.report_type, self.report_id, bytes)
+    }
+
+    /// Fetches the report, applies `modify` to its bytes and sends the
+    /// report back if it changed. Returns true if the report was sent.
+    pub fn modify(&self, modify: impl FnOnce(&mut [u8])) -> Result<bool> {
+        let before = self.read()?;
+        let mut after = before.clone();
+        modify(&mut after);
+        if after == before {
+            return Ok(false);
+        }
+        self.write(&after)?;
+        Ok(true)
+    }
+
+    /// Sets a field to the value, see [pack_field], and sends the report
+    /// if this changed it. Field bits include the Report ID, as in hidreport.
+    pub fn set_field(&self, bits: &Range<usize>, value: u32) -> Result<bool> {
+        if bits.end > self.size * 8 {
+            bail!("Field bits {bits:?} are outside of the report");
+        }
+        self.modify(|bytes| pack_field(bytes, bits, value))
+    }
+}
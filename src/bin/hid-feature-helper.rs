@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: MIT
+
+//! The privileged helper for `hid-feature --helper`, Linux only.
+//!
+//! This binary only performs the feature report ioctls on /dev/hidraw nodes,
+//! all parsing and formatting is done by the unprivileged hid-feature. It
+//! may be installed setuid root or run via pkexec.
+//!
+//! The caller must be root (e.g. via pkexec, which does the polkit
+//! authorization) or a member of the [HELPER_GROUP] group, otherwise a
+//! setuid helper would give every local user access to every hidraw node.
+//!
+//! Usage:
+//!   hid-feature-helper get-feature /dev/hidrawN <report-id> <size>
+//!   hid-feature-helper set-feature /dev/hidrawN <hex bytes...>
+//!
+//! get-feature prints the report as space-separated hex bytes. On failure,
+//! the helper prints "errno=N message" (or "error: message") to stderr and
+//! exits with a nonzero status.
+
+#[cfg(target_os = "linux")]
+use hid_feature::{report_ioctl, ReportType, MAX_REPORT_SIZE};
+#[cfg(target_os = "linux")]
+use std::os::fd::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// The group whose members may use a setuid helper, set with the
+/// HID_FEATURE_HELPER_GROUP environment variable at build time
+#[cfg(target_os = "linux")]
+const HELPER_GROUP: &str = match option_env!("HID_FEATURE_HELPER_GROUP") {
+    Some(group) => group,
+    None => "hid-feature",
+};
+
+#[cfg(target_os = "linux")]
+enum Error {
+    Io(std::io::Error),
+    Usage(String),
+    Denied(String),
+}
+
+#[cfg(target_os = "linux")]
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Returns the canonical path if it is a hidraw node, this helper must not
+/// be usable to access any other file.
+#[cfg(target_os = "linux")]
+fn hidraw_path(path: &str) -> Result<PathBuf, Error> {
+    let path = Path::new(path).canonicalize()?;
+    let is_hidraw = path
+        .to_str()
+        .and_then(|p| p.strip_prefix("/dev/hidraw"))
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+    if !is_hidraw || !std::fs::metadata(&path)?.file_type().is_char_device() {
+        return Err(Error::Usage(format!(
+            "{} is not a hidraw device",
+            path.display()
+        )));
+    }
+    Ok(path)
+}
+
+/// Returns an error unless the real user is root or in the [HELPER_GROUP].
+/// The environment is not trusted, it is set by the caller.
+#[cfg(target_os = "linux")]
+fn authorize() -> Result<(), Error> {
+    if unsafe { libc::getuid() } == 0 {
+        return Ok(());
+    }
+    let name = std::ffi::CString::new(HELPER_GROUP).expect("group name without NUL");
+    let group = unsafe { libc::getgrnam(name.as_ptr()) };
+    if !group.is_null() {
+        let gid = unsafe { (*group).gr_gid };
+        let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+        let mut groups = vec![0; count.max(0) as usize];
+        let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+        groups.truncate(count.max(0) as usize);
+        if unsafe { libc::getgid() } == gid || groups.contains(&gid) {
+            return Ok(());
+        }
+    }
+    Err(Error::Denied(format!(
+        "Only root and members of the '{HELPER_GROUP}' group may use this helper"
+    )))
+}
+
+/// Opens the device after checking the caller with [authorize] and drops
+/// any privileges gained via setuid
+#[cfg(target_os = "linux")]
+fn open(path: &str) -> Result<hidraw::Device, Error> {
+    authorize()?;
+    let device = hidraw::Device::open(hidraw_path(path)?)?;
+    unsafe {
+        if libc::setgid(libc::getgid()) != 0 || libc::setuid(libc::getuid()) != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+    Ok(device)
+}
+
+#[cfg(target_os = "linux")]
+fn parse<T: std::str::FromStr>(value: Option<&String>, what: &str) -> Result<T, Error> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| Error::Usage(format!("Missing or invalid {what}")))
+}
+
+#[cfg(target_os = "linux")]
+fn run(args: &[String]) -> Result<(), Error> {
+    match args.first().map(String::as_str) {
+        Some("get-feature") => {
+            let path: String = parse(args.get(1), "device")?;
+            let report_id: u8 = parse(args.get(2), "report ID")?;
+            let size: usize = parse(args.get(3), "size")?;
+            if size > MAX_REPORT_SIZE {
+                return Err(Error::Usage(format!("Report size {size} is too large")));
+            }
+            let device = open(&path)?;
+            let mut report = vec![0; size.max(1)];
+            report[0] = report_id;
+            report_ioctl(device.as_raw_fd(), ReportType::Feature, false, &mut report)?;
+            let bytes: Vec<String> = report[..size].iter().map(|b| format!("{b:02x}")).collect();
+            println!("{}", bytes.join(" "));
+        }
+        Some("set-feature") => {
+            let path: String = parse(args.get(1), "device")?;
+            let bytes = &args[2.min(args.len())..];
+            if bytes.is_empty() || bytes.len() > MAX_REPORT_SIZE {
+                return Err(Error::Usage("Invalid number of bytes".into()));
+            }
+            let mut report = bytes
+                .iter()
+                .map(|byte| {
+                    u8::from_str_radix(byte, 16)
+                        .map_err(|_| Error::Usage(format!("Invalid byte '{byte}'")))
+                })
+                .collect::<Result<Vec<u8>, Error>>()?;
+            let device = open(&path)?;
+            report_ioctl(device.as_raw_fd(), ReportType::Feature, true, &mut report)?;
+        }
+        _ => return Err(Error::Usage("Unknown command".into())),
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Error::Io(e)) => {
+            eprintln!("errno={} {e}", e.raw_os_error().unwrap_or(0));
+            ExitCode::FAILURE
+        }
+        Err(Error::Usage(message)) => {
+            eprintln!("error: {message}");
+            ExitCode::from(2)
+        }
+        Err(Error::Denied(message)) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() -> ExitCode {
+    eprintln!("error: hid-feature-helper is only available on Linux");
+    ExitCode::FAILURE
+}
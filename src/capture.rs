@@ -0,0 +1,521 @@
+// SPDX-License-Identifier: MIT
+
+//! Importing Wireshark captures of USB HID devices as recordings.
+//!
+//! The capture must be exported as JSON, e.g. with
+//! `tshark -r capture.pcapng -T json -x > capture.json`. Both USBPcap
+//! (Windows) and usbmon (Linux) captures are supported. The import
+//! extracts:
+//!
+//! - the report descriptor from the response to GET_DESCRIPTOR(Report)
+//! - Feature Reports from the responses to HID GET_REPORT and from
+//!   HID SET_REPORT requests
+//! - Input Reports from interrupt IN transfers
+//!
+//! Packet fields are looked up by their Wireshark field name anywhere in
+//! the packet's layers. Captures made after the device was plugged in do
+//! not contain the report descriptor, it must then be supplied separately.
+
+use crate::record::{Event, Recording};
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::Duration;
+
+const GET_DESCRIPTOR: u8 = 0x06;
+const HID_GET_REPORT: u8 = 0x01;
+const HID_SET_REPORT: u8 = 0x09;
+const HID_REPORT_DESCRIPTOR: u8 = 0x22;
+const HID_FEATURE_REPORT: u8 = 0x03;
+const TRANSFER_INTERRUPT: u8 = 0x01;
+
+/// The fields that may contain a packet's payload, in order of preference.
+/// The `_raw` fields are only present with `tshark -x`.
+const DATA_FIELDS: &[&str] = &[
+    "usb.capdata",
+    "usbhid.data",
+    "usb.data_fragment",
+    "usbhid_raw",
+];
+
+/// A control request as found in the setup stage of a transfer
+#[derive(Clone, Copy, Debug)]
+enum Setup {
+    GetDescriptor { descriptor_type: u8 },
+    GetReport { report_type: u8, report_id: u8 },
+    SetReport { report_type: u8, report_id: u8 },
+    Other,
+}
+
+/// A packet's fields by name, collected from all nested layers. Where a
+/// name occurs more than once, the first occurrence is used.
+struct Packet {
+    fields: HashMap<String, String>,
+}
+
+fn collect_fields(value: &Value, fields: &mut HashMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::String(s) => {
+                        fields.entry(key.clone()).or_insert_with(|| s.clone());
+                    }
+                    // tshark -x writes raw fields as [hex, offset, length, ...]
+                    Value::Array(values) if key.ends_with("_raw") => {
+                        if let Some(Value::String(s)) = values.first() {
+                            fields.entry(key.clone()).or_insert_with(|| s.clone());
+                        }
+                    }
+                    _ => collect_fields(value, fields),
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|v| collect_fields(v, fields)),
+        _ => {}
+    }
+}
+
+/// Parses a Wireshark number, either decimal or hex with a "0x" prefix
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Parses Wireshark bytes, either colon-separated ("01:02") or plain hex
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let hex: String = s.chars().filter(|c| *c != ':').collect();
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+impl Packet {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+
+    fn number(&self, name: &str) -> Option<u64> {
+        self.field(name).and_then(parse_number)
+    }
+
+    fn time(&self) -> Option<Duration> {
+        let secs: f64 = self.field("frame.time_relative")?.parse().ok()?;
+        Duration::try_from_secs_f64(secs.max(0.0)).ok()
+    }
+
+    fn data(&self) -> Option<Vec<u8>> {
+        DATA_FIELDS
+            .iter()
+            .find_map(|name| self.field(name).and_then(parse_hex))
+            .filter(|data| !data.is_empty())
+    }
+
+    fn setup(&self) -> Option<Setup> {
+        let value = self
+            .number("usbhid.setup.wValue")
+            .or_else(|| self.number("usb.setup.wValue"));
+        let high = |field: &str| self.number(field).or(value.map(|v| v >> 8));
+        let low = |field: &str| self.number(field).or(value.map(|v| v & 0xff));
+
+        if let Some(request) = self.number("usbhid.setup.bRequest") {
+            let report_type = high("usbhid.setup.ReportType")? as u8;
+            let report_id = low("usbhid.setup.ReportID")? as u8;
+            return Some(match request as u8 {
+                HID_GET_REPORT => Setup::GetReport {
+                    report_type,
+                    report_id,
+                },
+                HID_SET_REPORT => Setup::SetReport {
+                    report_type,
+                    report_id,
+                },
+                _ => Setup::Other,
+            });
+        }
+        let request = self.number("usb.setup.bRequest")? as u8;
+        let is_standard = self
+            .number("usb.bmRequestType")
+            .is_none_or(|t| t & 0x60 == 0);
+        Some(match request {
+            GET_DESCRIPTOR if is_standard => Setup::GetDescriptor {
+                descriptor_type: high("usb.bDescriptorType")? as u8,
+            },
+            _ => Setup::Other,
+        })
+    }
+
+    fn is_interrupt_in(&self) -> bool {
+        self.number("usb.transfer_type") == Some(TRANSFER_INTERRUPT.into())
+            && self
+                .number("usb.endpoint_address")
+                .is_some_and(|ep| ep & 0x80 != 0)
+    }
+}
+
+/// What a packet contributes to the recording
+enum Record {
+    Descriptor(Vec<u8>),
+    Event(Event),
+}
+
+fn packet_record(packet: &Packet, setups: &HashMap<u64, Setup>) -> Option<Record> {
+    let data = packet.data()?;
+    let time = packet.time()?;
+    // A response refers to the frame with its request's setup stage,
+    // a request with a data stage carries the setup itself
+    let setup = match packet.number("usb.request_in") {
+        Some(request) => setups.get(&request).copied(),
+        None => packet.setup(),
+    };
+    match setup {
+        Some(Setup::GetDescriptor {
+            descriptor_type: HID_REPORT_DESCRIPTOR,
+        }) => Some(Record::Descriptor(data)),
+        Some(
+            Setup::GetReport {
+                report_type: HID_FEATURE_REPORT,
+                report_id,
+            }
+            | Setup::SetReport {
+                report_type: HID_FEATURE_REPORT,
+                report_id,
+            },
+        ) => Some(Record::Event(Event::Feature {
+            time,
+            report_id,
+            bytes: data,
+        })),
+        Some(_) => None,
+        None if packet.is_interrupt_in() => Some(Record::Event(Event::Input { time, bytes: data })),
+        None => None,
+    }
+}
+
+/// Converts a Wireshark JSON export into a recording of the device with
+/// the given USB address. If the capture contains only one device, the
+/// address may be omitted. `rdesc` replaces any report descriptor in the
+/// capture and is required if the capture does not contain one.
+pub fn import_wireshark(
+    json: &str,
+    address: Option<u8>,
+    rdesc: Option<Vec<u8>>,
+) -> Result<Recording> {
+    let packets: Value = serde_json::from_str(json).context("Invalid JSON")?;
+    let Value::Array(packets) = packets else {
+        bail!("Expected a JSON array of packets as written by 'tshark -T json'");
+    };
+
+    let mut setups: HashMap<u64, Setup> = HashMap::new();
+    let mut records: BTreeMap<u8, Vec<Record>> = BTreeMap::new();
+    for packet in &packets {
+        let mut fields = HashMap::new();
+        collect_fields(packet, &mut fields);
+        let packet = Packet { fields };
+
+        if let (Some(frame), Some(setup)) = (packet.number("frame.number"), packet.setup()) {
+            setups.insert(frame, setup);
+        }
+        let Some(device) = packet.number("usb.device_address") else {
+            continue;
+        };
+        if let Some(record) = packet_record(&packet, &setups) {
+            records.entry(device as u8).or_default().push(record);
+        }
+    }
+
+    let address = match address {
+        Some(address) => address,
+        None => match records.keys().collect::<Vec<_>>().as_slice() {
+            [] => bail!("The capture does not contain any HID transfers"),
+            [address] => **address,
+            addresses => bail!(
+                "The capture contains multiple devices, select one with --address: {}",
+                addresses
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        },
+    };
+    let Some(records) = records.remove(&address) else {
+        bail!("The capture does not contain any HID transfers for device address {address}");
+    };
+
+    let mut descriptors = BTreeSet::new();
+    let mut events = Vec::new();
+    for record in records {
+        match record {
+            Record::Descriptor(bytes) => {
+                descriptors.insert(bytes);
+            }
+            Record::Event(event) => events.push(event),
+        }
+    }
+    let rdesc = match rdesc {
+        Some(rdesc) => rdesc,
+        None => match descriptors.len() {
+            0 => bail!("The capture does not contain the report descriptor, use --rdesc"),
+            1 => descriptors.pop_first().unwrap(),
+            n => bail!("The capture contains {n} different report descriptors for device address {address}, use --rdesc"),
+        },
+    };
+
+    // The recording starts with the first event
+    let start = events.first().map(Event::time).unwrap_or_default();
+    for event in &mut events {
+        match event {
+            Event::Feature { time, .. } | Event::Input { time, .. } => {
+                *time = time.saturating_sub(start)
+            }
+        }
+    }
+
+    Ok(Recording { rdesc, events })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Returns a packet as exported by tshark, each field in the layer
+    /// named by its prefix
+    fn packet(fields: &[(&str, &str)]) -> Value {
+        let mut layers = serde_json::Map::new();
+        for (name, value) in fields {
+            let layer = name.split(['.', '_']).next().unwrap();
+            layers
+                .entry(layer)
+                .or_insert_with(|| json!({}))
+                .as_object_mut()
+                .unwrap()
+                .insert(name.to_string(), json!(value));
+        }
+        json!({ "_source": { "layers": layers } })
+    }
+
+    /// A usbmon capture of device 3 with the report descriptor, a Feature
+    /// Report fetched and set, an Input and an Output Report and an
+    /// Input Report of device 5
+    fn capture() -> Vec<Value> {
+        vec![
+            // GET_DESCRIPTOR(Report) and its response
+            packet(&[
+                ("frame.number", "1"),
+                ("frame.time_relative", "0.000000"),
+                ("usb.device_address", "3"),
+                ("usb.bmRequestType", "0x81"),
+                ("usb.setup.bRequest", "6"),
+                ("usb.setup.wValue", "0x2200"),
+            ]),
+            packet(&[
+                ("frame.number", "2"),
+                ("frame.time_relative", "0.100000"),
+                ("usb.device_address", "3"),
+                ("usb.request_in", "1"),
+                ("usb.capdata", "05:01:c0"),
+            ]),
+            // GET_REPORT(Feature, 4) and its response
+            packet(&[
+                ("frame.number", "3"),
+                ("frame.time_relative", "1.000000"),
+                ("usb.device_address", "3"),
+                ("usbhid.setup.bRequest", "0x01"),
+                ("usbhid.setup.ReportType", "3"),
+                ("usbhid.setup.ReportID", "4"),
+            ]),
+            packet(&[
+                ("frame.number", "4"),
+                ("frame.time_relative", "1.500000"),
+                ("usb.device_address", "3"),
+                ("usb.request_in", "3"),
+                ("usb.capdata", "04:01:78"),
+            ]),
+            // SET_REPORT(Feature, 4) with its data
+            packet(&[
+                ("frame.number", "5"),
+                ("frame.time_relative", "2.000000"),
+                ("usb.device_address", "3"),
+                ("usbhid.setup.bRequest", "9"),
+                ("usbhid.setup.wValue", "0x0304"),
+                ("usbhid.data", "040078"),
+            ]),
+            // GET_REPORT(Output, 1) and its response are skipped
+            packet(&[
+                ("frame.number", "6"),
+                ("frame.time_relative", "2.100000"),
+                ("usb.device_address", "3"),
+                ("usbhid.setup.bRequest", "1"),
+                ("usbhid.setup.wValue", "0x0201"),
+            ]),
+            packet(&[
+                ("frame.number", "7"),
+                ("frame.time_relative", "2.200000"),
+                ("usb.device_address", "3"),
+                ("usb.request_in", "6"),
+                ("usb.capdata", "01:ff"),
+            ]),
+            // Interrupt IN and OUT
+            packet(&[
+                ("frame.number", "8"),
+                ("frame.time_relative", "2.500000"),
+                ("usb.device_address", "3"),
+                ("usb.transfer_type", "0x01"),
+                ("usb.endpoint_address", "0x81"),
+                ("usb.capdata", "01:00:02:ff"),
+            ]),
+            packet(&[
+                ("frame.number", "9"),
+                ("frame.time_relative", "2.600000"),
+                ("usb.device_address", "3"),
+                ("usb.transfer_type", "0x01"),
+                ("usb.endpoint_address", "0x01"),
+                ("usb.capdata", "02:01"),
+            ]),
+            packet(&[
+                ("frame.number", "10"),
+                ("frame.time_relative", "3.000000"),
+                ("usb.device_address", "5"),
+                ("usb.transfer_type", "0x01"),
+                ("usb.endpoint_address", "0x82"),
+                ("usb.capdata", "aa"),
+            ]),
+        ]
+    }
+
+    fn import(packets: &[Value], address: Option<u8>, rdesc: Option<&[u8]>) -> Result<Recording> {
+        let json = serde_json::to_string(packets).unwrap();
+        import_wireshark(&json, address, rdesc.map(<[u8]>::to_vec))
+    }
+
+    fn error(packets: &[Value], address: Option<u8>) -> String {
+        format!("{:#}", import(packets, address, None).unwrap_err())
+    }
+
+    /// Returns the events as (time in ms, Report ID or None for an Input
+    /// Report, bytes)
+    fn events(recording: &Recording) -> Vec<(u128, Option<u8>, Vec<u8>)> {
+        recording
+            .events
+            .iter()
+            .map(|event| match event {
+                Event::Feature {
+                    time,
+                    report_id,
+                    bytes,
+                } => (time.as_millis(), Some(*report_id), bytes.clone()),
+                Event::Input { time, bytes } => (time.as_millis(), None, bytes.clone()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_import() {
+        let recording = import(&capture(), Some(3), None).unwrap();
+        assert_eq!(recording.rdesc, [0x05, 0x01, 0xc0]);
+        // The times are relative to the first event
+        assert_eq!(
+            events(&recording),
+            [
+                (0, Some(4), vec![0x04, 0x01, 0x78]),
+                (500, Some(4), vec![0x04, 0x00, 0x78]),
+                (1000, None, vec![0x01, 0x00, 0x02, 0xff]),
+            ]
+        );
+
+        // The only device is selected without an address
+        let packets: Vec<Value> = capture().into_iter().take(9).collect();
+        let recording = import(&packets, None, None).unwrap();
+        assert_eq!(recording.events.len(), 3);
+
+        // --rdesc replaces the captured report descriptor
+        let recording = import(&capture(), Some(3), Some(&[0xc0])).unwrap();
+        assert_eq!(recording.rdesc, [0xc0]);
+        let recording = import(&capture(), Some(5), Some(&[0xc0])).unwrap();
+        assert_eq!(events(&recording), [(0, None, vec![0xaa])]);
+    }
+
+    #[test]
+    fn test_import_raw() {
+        // With tshark -x, the payload may only be in a raw field
+        let packets = [json!({
+            "_source": { "layers": {
+                "frame": { "frame.number": "1", "frame.time_relative": "0.5" },
+                "usb": {
+                    "usb.device_address": "2",
+                    "usb.transfer_type": "0x01",
+                    "usb.endpoint_address": "0x81",
+                },
+                "usbhid_raw": ["0102", 64, 2, 0, 1],
+            }}
+        })];
+        let recording = import(&packets, None, Some(&[0xc0])).unwrap();
+        assert_eq!(events(&recording), [(0, None, vec![0x01, 0x02])]);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        assert!(
+            format!("{:#}", import_wireshark("{", None, None).unwrap_err())
+                .contains("Invalid JSON")
+        );
+        assert!(
+            format!("{:#}", import_wireshark("{}", None, None).unwrap_err())
+                .contains("Expected a JSON array")
+        );
+        assert!(error(&[], None).contains("does not contain any HID transfers"));
+        assert!(error(&capture(), None)
+            .contains("contains multiple devices, select one with --address: 3, 5"));
+        assert!(error(&capture(), Some(7)).contains("for device address 7"));
+        assert!(error(&capture(), Some(5)).contains("does not contain the report descriptor"));
+
+        // A second, different report descriptor
+        let mut packets = capture();
+        packets.push(packet(&[
+            ("frame.number", "11"),
+            ("frame.time_relative", "4.000000"),
+            ("usb.device_address", "3"),
+            ("usb.request_in", "1"),
+            ("usb.capdata", "05:0d:c0"),
+        ]));
+        assert!(error(&packets, Some(3)).contains("2 different report descriptors"));
+        // The same one again is fine
+        packets.last_mut().unwrap()["_source"]["layers"]["usb"]["usb.capdata"] = json!("05:01:c0");
+        assert!(import(&packets, Some(3), None).is_ok());
+
+        // Packets with an invalid time or payload are skipped
+        for (field, value) in [
+            ("frame.time_relative", "inf"),
+            ("frame.time_relative", "x"),
+            ("usb.capdata", "0"),
+            ("usb.capdata", "zz"),
+        ] {
+            let mut packets = capture();
+            packets[7]["_source"]["layers"][field.split('.').next().unwrap()][field] = json!(value);
+            let recording = import(&packets, Some(3), None).unwrap();
+            assert_eq!(recording.events.len(), 2, "{field}: {value}");
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_number("10"), Some(10));
+        assert_eq!(parse_number("0x10"), Some(16));
+        assert_eq!(parse_number("0xzz"), None);
+        assert_eq!(parse_number("-1"), None);
+        assert_eq!(parse_hex("01:ab"), Some(vec![0x01, 0xab]));
+        assert_eq!(parse_hex("01ab"), Some(vec![0x01, 0xab]));
+        assert_eq!(parse_hex(""), Some(vec![]));
+        assert_eq!(parse_hex("1:ab"), None);
+        assert_eq!(parse_hex("0g"), None);
+        assert_eq!(parse_hex("é0"), None);
+    }
+}
@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: MIT
+
+//! The configuration file, by default `$XDG_CONFIG_HOME/hid-feature/config.json`.
+//!
+//! ```json
+//! {
+//!   "scenes": {
+//!     "gaming": [
+//!       { "device": "046d:c52b", "report_id": 4, "bytes": ["xx", "01"] },
+//!       { "device": "1-3.2:1.0", "report_id": 2, "offset": 1, "bytes": ["00"] }
+//!     ]
+//!   },
+//!   "defaults": [
+//!     { "device": "046d:c52b", "report_id": 4, "offset": 1 }
+//!   ],
+//!   "protected": [
+//!     { "device": "046d:c52b", "report_id": 7, "reason": "triggers DFU mode" }
+//!   ],
+//!   "hooks": {
+//!     "pre_write": ["systemctl --user stop vendor-daemon"],
+//!     "post_write": ["notify-send \"Wrote report $HID_FEATURE_REPORT_ID\""]
+//!   }
+//! }
+//! ```
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Named sets of writes, applied with `hid-feature scene <name>`
+    #[serde(default)]
+    pub scenes: BTreeMap<String, Vec<SceneEntry>>,
+
+    /// Per-device defaults for `hid-feature set`
+    #[serde(default)]
+    pub defaults: Vec<DeviceDefaults>,
+
+    /// Fields and reports that `hid-feature set` only writes with --force
+    #[serde(default)]
+    pub protected: Vec<Protected>,
+
+    #[serde(default)]
+    pub hooks: Hooks,
+}
+
+/// A write-protected report or field. A write that changes any byte of
+/// the report (or, if a field is given, any byte the field occupies)
+/// requires `set --force`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Protected {
+    /// The device as hidraw node, `VID:PID[:serial]`, USB port path or
+    /// report descriptor fingerprint (`rdesc:<hash>`).
+    /// If omitted, the entry applies to all devices.
+    pub device: Option<String>,
+    /// The Report ID, 0 for a report without Report ID. If omitted, the
+    /// entry applies to all reports.
+    pub report_id: Option<u8>,
+    /// The usage name of the field, e.g. "Vendor Usage 0xff08"
+    pub field: Option<String>,
+    /// Why the field is protected, printed when a write is refused
+    pub reason: Option<String>,
+}
+
+/// The default `--report-id` and `--offset` for a device and whether
+/// `list` fetches its values. The first entry matching a device applies.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceDefaults {
+    /// The device as hidraw node, `VID:PID[:serial]`, USB port path or
+    /// report descriptor fingerprint (`rdesc:<hash>`)
+    pub device: String,
+    pub report_id: Option<u8>,
+    pub offset: Option<usize>,
+    /// False to only list the layout, see `list --no-values`
+    pub values: Option<bool>,
+}
+
+/// Shell commands run before and after each write to a device.
+///
+/// The commands are run with `sh -c` and have HID_FEATURE_DEVICE,
+/// HID_FEATURE_REPORT_ID and HID_FEATURE_BYTES (the hex bytes to be
+/// written) set in their environment. A failing `pre_write` command
+/// cancels the write.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    #[serde(default)]
+    pub pre_write: Vec<String>,
+    #[serde(default)]
+    pub post_write: Vec<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.pre_write.is_empty() && self.post_write.is_empty()
+    }
+}
+
+/// Runs each command with `sh -c` and the given environment, stopping at
+/// the first command that fails.
+pub fn run_hooks(commands: &[String], env: &[(&str, String)]) -> Result<()> {
+    for command in commands {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(env.iter().map(|(k, v)| (k, v)))
+            .status()
+            .with_context(|| format!("Unable to run hook '{command}'"))?;
+        if !status.success() {
+            bail!("Hook '{command}' failed with {status}");
+        }
+    }
+    Ok(())
+}
+
+/// A single write within a scene, equivalent to one `hid-feature set` invocation
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneEntry {
+    /// The device as hidraw node, `VID:PID[:serial]`, USB port path or
+    /// report descriptor fingerprint (`rdesc:<hash>`)
+    pub device: String,
+    pub report_id: Option<u8>,
+    /// The usage of the report's Application Collection, see `set --collection`
+    pub collection: Option<String>,
+    #[serde(default)]
+    pub offset: usize,
+    /// Use the first matching Feature Report if there is more than one
+    #[serde(default)]
+    pub first: bool,
+    /// The bytes as given to `set`, hexadecimal unless prefixed
+    pub bytes: Vec<String>,
+}
+
+/// Returns the default path of the configuration file
+pub fn default_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("hid-feature").join("config.json"))
+}
+
+impl Config {
+    /// Loads the configuration from the given file or, if no file is given,
+    /// from the [default_path]. A missing default file is an empty configuration.
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid configuration in {}", path.display()))
+    }
+}
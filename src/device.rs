@@ -0,0 +1,326 @@
+// SPDX-License-Identifier: MIT
+
+//! Feature Report requests on a hidraw device, either directly via the
+//! hidraw ioctls or through the privileged hid-feature-helper, and writing
+//! Output Reports. The hidraw parts are only available on Linux.
+
+#[cfg(target_os = "linux")]
+use crate::IOCTL_TIMEOUT;
+use crate::{HidBackend, HELPER};
+use anyhow::{anyhow, bail, Context, Result};
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The largest report the kernel supports (HID_MAX_BUFFER_SIZE)
+pub const MAX_REPORT_SIZE: usize = 16384;
+
+/// The type of a report. Besides Feature Reports, the current Input and
+/// Output Reports can be fetched and sent with the hidraw ioctls too
+/// (Linux 5.11 and later), not all devices support this.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ReportType {
+    Input,
+    Output,
+    #[default]
+    Feature,
+}
+
+impl ReportType {
+    /// The name as used in messages, e.g. "Feature"
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportType::Input => "Input",
+            ReportType::Output => "Output",
+            ReportType::Feature => "Feature",
+        }
+    }
+
+    /// The ioctl numbers of HIDIOCG* and HIDIOCS* for this report type
+    #[cfg(target_os = "linux")]
+    fn ioctl_nrs(&self) -> (u8, u8) {
+        match self {
+            ReportType::Feature => (0x07, 0x06),
+            ReportType::Input => (0x0a, 0x09),
+            ReportType::Output => (0x0c, 0x0b),
+        }
+    }
+}
+
+impl std::fmt::Display for ReportType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name().to_lowercase())
+    }
+}
+
+impl std::str::FromStr for ReportType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "input" => Ok(ReportType::Input),
+            "output" => Ok(ReportType::Output),
+            "feature" => Ok(ReportType::Feature),
+            _ => bail!("Invalid report type '{s}', must be one of feature, input, output"),
+        }
+    }
+}
+
+/// Runs HIDIOCG<type>(len) (`set` false) or HIDIOCS<type>(len) on the
+/// hidraw fd with a buffer of exactly the report's size. The buffer starts
+/// with the Report ID or 0, on success a get fills in the report and the
+/// number of bytes is returned.
+#[cfg(target_os = "linux")]
+pub fn report_ioctl(
+    fd: RawFd,
+    report_type: ReportType,
+    set: bool,
+    buffer: &mut [u8],
+) -> std::io::Result<usize> {
+    // The size in the request is 14 bits wide, see _IOC() in
+    // linux/ioctl.h
+    if buffer.is_empty() || buffer.len() >= 1 << 14 {
+        return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+    }
+    let (get_nr, set_nr) = report_type.ioctl_nrs();
+    let nr = if set { set_nr } else { get_nr };
+    // _IOC(_IOC_WRITE|_IOC_READ, 'H', nr, len)
+    let request = (3 << 30) | (buffer.len() << 16) | ((b'H' as usize) << 8) | nr as usize;
+    let rc = unsafe { libc::ioctl(fd, request as _, buffer.as_mut_ptr()) };
+    if rc < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(rc as usize)
+}
+
+/// Runs a report ioctl on the device's fd. If a timeout is set, see
+/// [crate::set_ioctl_timeout], the ioctl runs on a worker thread using a
+/// duplicate of the device's fd and an error is returned if it does not
+/// complete within the timeout.
+#[cfg(target_os = "linux")]
+fn feature_ioctl<T: Send + 'static>(
+    device: &hidraw::Device,
+    what: &str,
+    ioctl: impl FnOnce(RawFd) -> std::io::Result<T> + Send + 'static,
+) -> Result<T> {
+    let fd = device.as_fd().try_clone_to_owned()?;
+    let Some(timeout) = IOCTL_TIMEOUT.get().copied() else {
+        return Ok(ioctl(fd.as_raw_fd())?);
+    };
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(ioctl(fd.as_raw_fd()));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => Ok(result?),
+        Err(_) => bail!("{what} did not complete within {timeout:?}, the device may not respond"),
+    }
+}
+
+/// A device to send feature report requests to: either the hidraw node
+/// or, if a helper is set (see [crate::set_helper]), the hid-feature-helper
+/// for that node.
+#[cfg(target_os = "linux")]
+pub enum FeatureDevice {
+    Hidraw(hidraw::Device),
+    Helper(PathBuf),
+}
+
+#[cfg(target_os = "linux")]
+impl FeatureDevice {
+    pub fn open(path: &Path) -> Result<FeatureDevice> {
+        match HELPER.get() {
+            Some(_) => Ok(FeatureDevice::Helper(path.to_path_buf())),
+            None => Ok(FeatureDevice::Hidraw(hidraw::Device::open(path)?)),
+        }
+    }
+
+    /// Fetches a feature report, see [feature_ioctl]
+    pub fn get_feature_report(&self, report_id: u8, size: usize) -> Result<Vec<u8>> {
+        self.get_report(ReportType::Feature, report_id, size)
+    }
+
+    /// Sends a feature report, see [feature_ioctl]
+    pub fn send_feature_report(&self, report: &[u8]) -> Result<()> {
+        self.send_report(ReportType::Feature, report)
+    }
+
+    /// Fetches `size` bytes of a report of the given type, see
+    /// [feature_ioctl]. The helper only supports Feature Reports.
+    pub fn get_report(
+        &self,
+        report_type: ReportType,
+        report_id: u8,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        if size > MAX_REPORT_SIZE {
+            bail!("A report of {size} bytes exceeds the maximum of {MAX_REPORT_SIZE} bytes");
+        }
+        match self {
+            FeatureDevice::Hidraw(device) => {
+                let what = format!("Get{}", report_type.name());
+                feature_ioctl(device, &what, move |fd| {
+                    let mut report = vec![0; size];
+                    report[0] = report_id;
+                    report_ioctl(fd, report_type, false, &mut report)?;
+                    Ok(report)
+                })
+            }
+            FeatureDevice::Helper(_) if report_type != ReportType::Feature => {
+                bail!("The helper only supports Feature Reports")
+            }
+            FeatureDevice::Helper(path) => {
+                let output = run_helper(&[
+                    "get-feature".into(),
+                    path.display().to_string(),
+                    report_id.to_string(),
+                    size.to_string(),
+                ])?;
+                let mut report = vec![0; size];
+                for (dst, byte) in report.iter_mut().zip(output.split_whitespace()) {
+                    *dst = u8::from_str_radix(byte, 16)
+                        .context("Invalid output from hid-feature-helper")?;
+                }
+                Ok(report)
+            }
+        }
+    }
+
+    /// Sends a report of the given type, see [feature_ioctl]. The bytes
+    /// start with the Report ID or, for a report without one, with 0. The
+    /// helper only supports Feature Reports.
+    pub fn send_report(&self, report_type: ReportType, report: &[u8]) -> Result<()> {
+        let size = report.len();
+        if size > MAX_REPORT_SIZE {
+            bail!("A report of {size} bytes exceeds the maximum of {MAX_REPORT_SIZE} bytes");
+        }
+        match self {
+            FeatureDevice::Hidraw(device) => {
+                let what = format!("Set{}", report_type.name());
+                let mut buffer = report.to_vec();
+                feature_ioctl(device, &what, move |fd| {
+                    report_ioctl(fd, report_type, true, &mut buffer).map(|_| ())
+                })
+            }
+            FeatureDevice::Helper(_) if report_type != ReportType::Feature => {
+                bail!("The helper only supports Feature Reports")
+            }
+            FeatureDevice::Helper(path) => {
+                let mut args = vec!["set-feature".into(), path.display().to_string()];
+                args.extend(report.iter().map(|b| format!("{b:02x}")));
+                run_helper(&args).map(|_| ())
+            }
+        }
+    }
+}
+
+/// Runs the helper with the given arguments and returns its output.
+///
+/// The helper prints "errno=N message" on failure which is converted back
+/// into the [std::io::Error] so that callers can check the error code.
+#[cfg(target_os = "linux")]
+fn run_helper(args: &[String]) -> Result<String> {
+    let command = HELPER.get().context("No helper configured")?;
+    let mut child = std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .args(args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Unable to run {}", command.join(" ")))?;
+
+    if let Some(timeout) = IOCTL_TIMEOUT.get() {
+        let deadline = std::time::Instant::now() + *timeout;
+        while child.try_wait()?.is_none() {
+            if std::time::Instant::now() >= deadline {
+                let _ = child.kill();
+                bail!(
+                    "{} did not complete within {timeout:?}, the device may not respond",
+                    args[0]
+                );
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        if let Some((errno, _)) = stderr
+            .strip_prefix("errno=")
+            .and_then(|rest| rest.split_once(' '))
+        {
+            if let Ok(errno) = errno.parse() {
+                return Err(std::io::Error::from_raw_os_error(errno).into());
+            }
+        }
+        bail!("hid-feature-helper failed: {stderr}");
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Writes an Output Report to the hidraw node. The bytes start with the
+/// Report ID or, for a device without Report IDs, with 0. The helper does
+/// not support Output Reports, the node must be writable.
+pub fn send_output_report(path: &Path, bytes: &[u8]) -> Result<()> {
+    if HELPER.get().is_some() {
+        bail!("Output Reports cannot be sent via the helper");
+    }
+    let mut device = std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Unable to open {} for writing", path.display()))?;
+    let written = std::io::Write::write(&mut device, bytes)?;
+    if written != bytes.len() {
+        bail!("Only {written} of {} bytes were written", bytes.len());
+    }
+    Ok(())
+}
+
+/// Returns true if the error is caused by the device disappearing
+#[cfg(target_os = "linux")]
+pub fn is_disconnect(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .and_then(|e| e.raw_os_error())
+        .is_some_and(|errno| {
+            [libc::ENODEV, libc::EIO, libc::ENXIO, libc::ESHUTDOWN].contains(&errno)
+        })
+}
+
+/// Returns true if the error is caused by the device disappearing
+#[cfg(not(target_os = "linux"))]
+pub fn is_disconnect(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Opens the device again through the backend, see
+/// [HidBackend::open_device], waiting up to `wait` for the node to reappear
+pub fn reopen<'a>(
+    backend: &'a dyn HidBackend,
+    path: &Path,
+    wait: Duration,
+) -> Result<Box<dyn HidBackend + 'a>> {
+    let deadline = std::time::Instant::now() + wait;
+    loop {
+        // Only hidraw paths are filesystem paths
+        let result = if path.exists() || !cfg!(target_os = "linux") {
+            backend.open_device(path)
+        } else {
+            Err(anyhow!("{} does not exist", path.display()))
+        };
+        match result {
+            Ok(device) => return Ok(device),
+            Err(e) if std::time::Instant::now() >= deadline => {
+                return Err(e).with_context(|| format!("Unable to reopen {}", path.display()))
+            }
+            Err(_) => std::thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
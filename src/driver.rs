@@ -0,0 +1,97 @@
+// SPDX-License-Identifier: MIT
+
+//! Binding and unbinding HID kernel drivers via sysfs.
+//!
+//! A HID device is identified by its sysfs name, e.g. "0003:046D:C52B.000F".
+//! Unbinding the driver also removes the device's hidraw node, so the
+//! device can only be rebound by that name.
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+const HID_BUS: &str = "/sys/bus/hid";
+
+/// Returns the HID device name of a hidraw node, e.g. "0003:046D:C52B.000F"
+pub fn hid_device_name(hidraw: &Path) -> Result<String> {
+    let file = hidraw
+        .file_name()
+        .context("Invalid device path")?
+        .to_string_lossy();
+    let device = PathBuf::from(format!("/sys/class/hidraw/{file}/device"));
+    let device = device
+        .canonicalize()
+        .with_context(|| format!("Unable to resolve {}", device.display()))?;
+    Ok(device
+        .file_name()
+        .context("Invalid sysfs device path")?
+        .to_string_lossy()
+        .to_string())
+}
+
+/// Returns true if the name has the "BBBB:VVVV:PPPP.NNNN" format of a HID
+/// device, this also ensures the name cannot escape the sysfs directory.
+fn is_hid_device_name(name: &str) -> bool {
+    let Some((ids, instance)) = name.split_once('.') else {
+        return false;
+    };
+    let hex = |s: &str, len: usize| s.len() == len && s.chars().all(|c| c.is_ascii_hexdigit());
+    let ids: Vec<&str> = ids.split(':').collect();
+    ids.len() == 3 && ids.iter().all(|id| hex(id, 4)) && hex(instance, 4)
+}
+
+fn device_dir(name: &str) -> Result<PathBuf> {
+    if !is_hid_device_name(name) {
+        bail!("'{name}' is not a HID device name, e.g. 0003:046D:C52B.000F");
+    }
+    let dir = Path::new(HID_BUS).join("devices").join(name);
+    if !dir.exists() {
+        bail!("No HID device named {name}");
+    }
+    Ok(dir)
+}
+
+/// Returns the name of the driver bound to the HID device, if any
+pub fn bound_driver(name: &str) -> Result<Option<String>> {
+    let link = device_dir(name)?.join("driver");
+    Ok(std::fs::read_link(link)
+        .ok()
+        .and_then(|driver| driver.file_name().map(|d| d.to_string_lossy().to_string())))
+}
+
+fn write_sysfs(path: &Path, value: &str) -> Result<()> {
+    std::fs::write(path, value).with_context(|| format!("Unable to write to {}", path.display()))
+}
+
+/// Unbinds the HID device from its driver, returning the driver's name
+pub fn unbind(name: &str) -> Result<String> {
+    let Some(driver) = bound_driver(name)? else {
+        bail!("{name} is not bound to a driver");
+    };
+    let unbind = Path::new(HID_BUS)
+        .join("drivers")
+        .join(&driver)
+        .join("unbind");
+    write_sysfs(&unbind, name)?;
+    Ok(driver)
+}
+
+/// Binds the HID device to the given driver or, if none is given, lets
+/// the kernel pick the best matching driver
+pub fn bind(name: &str, driver: Option<&str>) -> Result<()> {
+    if let Some(current) = bound_driver(name)? {
+        bail!("{name} is already bound to {current}");
+    }
+    match driver {
+        Some(driver) => {
+            if driver.contains('/') || driver.starts_with('.') {
+                bail!("Invalid driver name '{driver}'");
+            }
+            let dir = Path::new(HID_BUS).join("drivers").join(driver);
+            if !dir.exists() {
+                bail!("No HID driver named {driver}");
+            }
+            write_sysfs(&dir.join("bind"), name)
+        }
+        None => write_sysfs(&Path::new(HID_BUS).join("drivers_probe"), name),
+    }
+}
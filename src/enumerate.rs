@@ -0,0 +1,490 @@
+// SPDX-License-Identifier: MIT
+
+//! Enumerating hidraw devices and fetching their report descriptors.
+//!
+//! Device information is taken from the hidraw ioctls with sysfs as
+//! fallback, see [crate::set_use_sysfs]. Off Linux, devices are only
+//! available through the [crate::backend].
+
+#[cfg(target_os = "linux")]
+use crate::use_sysfs;
+#[cfg(target_os = "linux")]
+use anyhow::anyhow;
+use anyhow::{bail, Context, Result};
+use hidreport::ReportDescriptor;
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Returns the key/value pairs in the device's uevent file
+#[cfg(target_os = "linux")]
+pub fn hidraw_uevent(file: &String) -> Result<HashMap<String, String>> {
+    if !use_sysfs() {
+        bail!("sysfs access is disabled");
+    }
+    let uevent_path = PathBuf::from(format!("/sys/class/hidraw/{}/device/uevent", file));
+    let uevent = std::fs::read_to_string(uevent_path)?;
+    Ok(uevent
+        .lines()
+        .filter_map(|l| l.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect())
+}
+
+/// Parses the uevent's HID_ID, e.g. "0003:0000046D:0000C52B",
+/// into bus, vendor ID and product ID.
+pub fn parse_hid_id(hid_id: &str) -> Result<(u16, u16, u16)> {
+    let parts: Vec<u32> = hid_id
+        .split(':')
+        .map(|p| u32::from_str_radix(p, 16))
+        .collect::<std::result::Result<_, _>>()
+        .with_context(|| format!("Unexpected HID_ID={hid_id} format"))?;
+    match parts[..] {
+        [bus, vid, pid] => Ok((bus as u16, vid as u16, pid as u16)),
+        _ => bail!("Unexpected HID_ID={hid_id} format"),
+    }
+}
+
+/// Returns the sysfs path of the USB interface of the given hidraw device
+/// or `None` if the device is not a USB device.
+///
+/// For example, for a hidraw device at
+/// `/sys/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3.2/1-3.2:1.0/0003:046D:C52B.0001`
+/// the interface is `/sys/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3.2/1-3.2:1.0`.
+#[cfg(target_os = "linux")]
+pub fn hidraw_usb_interface(file: &String) -> Option<PathBuf> {
+    if !use_sysfs() {
+        return None;
+    }
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    // USB interfaces are named "<bus>-<port>[.<port>...]:<config>.<interface>"
+    let is_usb_interface = |name: &str| {
+        let Some((bus, rest)) = name.split_once('-') else {
+            return false;
+        };
+        let Some((ports, rest)) = rest.split_once(':') else {
+            return false;
+        };
+        let Some((config, iface)) = rest.split_once('.') else {
+            return false;
+        };
+        is_number(bus) && ports.split('.').all(is_number) && is_number(config) && is_number(iface)
+    };
+
+    let sysfs = std::fs::canonicalize(format!("/sys/class/hidraw/{file}/device")).ok()?;
+    sysfs
+        .ancestors()
+        .find(|p| {
+            p.file_name()
+                .is_some_and(|f| is_usb_interface(&f.to_string_lossy()))
+        })
+        .map(Path::to_path_buf)
+}
+
+/// Returns the USB port path (e.g. "1-3.2:1.0") of the given hidraw device
+/// or `None` if the device is not a USB device.
+///
+/// The port path is the name of the USB interface in the device's sysfs path,
+/// see [hidraw_usb_interface].
+#[cfg(target_os = "linux")]
+pub fn hidraw_port(file: &String) -> Option<String> {
+    hidraw_usb_interface(file).and_then(|p| p.file_name().map(|f| f.to_string_lossy().to_string()))
+}
+
+/// Returns the USB speed in Mbit/s of the given hidraw device
+/// or `None` if the device is not a USB device.
+#[cfg(target_os = "linux")]
+pub fn hidraw_usb_speed(file: &String) -> Option<f32> {
+    let usb_device = hidraw_usb_interface(file)?.parent()?.to_path_buf();
+    let speed = std::fs::read_to_string(usb_device.join("speed")).ok()?;
+    speed.trim().parse().ok()
+}
+
+/// Returns the (bus, vendor ID, product ID) of the device via the HIDIOCGRAWINFO ioctl
+#[cfg(target_os = "linux")]
+pub fn hidraw_info(device: &mut hidraw::Device) -> Result<(u16, u16, u16)> {
+    let info = device.get_raw_info()?;
+    // RawInfo::bus_type() converts into an enum that only knows some
+    // bus types (e.g. not I2C) so we read the raw bustype instead.
+    // RawInfo is a transparent wrapper around struct hidraw_devinfo.
+    let raw: [u8; 8] = unsafe { std::mem::transmute(info) };
+    let bus = u32::from_ne_bytes([raw[0], raw[1], raw[2], raw[3]]);
+    Ok((bus as u16, info.vendor() as u16, info.product() as u16))
+}
+
+/// Returns a human-readable name for the given bus type as used in HID_ID
+/// and struct hidraw_devinfo, see linux/input.h
+pub fn bus_name(bus: u16) -> String {
+    match bus {
+        0x03 => "USB".into(),
+        0x05 => "Bluetooth".into(),
+        0x06 => "Virtual".into(),
+        0x18 => "I2C".into(),
+        0x19 => "Host".into(),
+        0x1c => "SPI".into(),
+        0x1f => "ISHTP".into(),
+        0x20 => "AMD SFH".into(),
+        _ => format!("Bus {bus:#04x}"),
+    }
+}
+
+/// A hidraw node and the identity of its HID device. Other backends use
+/// this for their devices too, e.g. with a hidapi device path.
+#[derive(Clone, Debug)]
+pub struct HidrawDevice {
+    /// The path to the /dev/hidraw node or the backend's device path
+    pub path: PathBuf,
+    pub name: String,
+    pub bus: u16,
+    pub vid: u16,
+    pub pid: u16,
+    /// The device's physical location (HID_PHYS), if any
+    pub phys: Option<String>,
+    /// The device's unique identifier (HID_UNIQ), typically the serial number, if any
+    pub serial: Option<String>,
+    /// The USB port path, see [hidraw_port]
+    pub port: Option<String>,
+}
+
+impl HidrawDevice {
+    /// Returns true if this device is at the given USB port path.
+    /// The port path may omit the interface suffix, in which case
+    /// any interface of the USB device at this port path matches.
+    pub fn matches_port(&self, port: &str) -> bool {
+        self.port.as_deref().is_some_and(|p| {
+            p == port
+                || p.strip_prefix(port)
+                    .is_some_and(|suffix| suffix.starts_with(':'))
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn hidraw_devices() -> Result<Vec<HidrawDevice>> {
+    let mut hidraws: Vec<String> = std::fs::read_dir("/dev/")?
+        .flatten()
+        .flat_map(|f| f.file_name().into_string())
+        .filter(|name| name.starts_with("hidraw"))
+        .collect();
+
+    hidraws.sort_by(|a, b| human_sort::compare(a, b));
+    let devices = hidraws
+        .into_iter()
+        .filter_map(|file| match hidraw_device(&file) {
+            Ok(device) => Some(device),
+            Err(e) => {
+                eprintln!("Warning: Unable to identify /dev/{file}: {e:#}");
+                None
+            }
+        })
+        .collect();
+    Ok(devices)
+}
+
+/// Returns the device for the given hidraw node name (e.g. "hidraw0").
+///
+/// The device is identified via the HIDIOCGRAW* ioctls on the device node.
+/// Where the device node cannot be opened, the information is taken from
+/// the device's uevent file instead.
+#[cfg(target_os = "linux")]
+pub fn hidraw_device(file: &String) -> Result<HidrawDevice> {
+    let path = PathBuf::from("/dev/").join(file);
+    let uevent = hidraw_uevent(file).unwrap_or_default();
+    let uevent_value = |key: &str| uevent.get(key).filter(|v| !v.is_empty()).cloned();
+
+    let mut device = hidraw::Device::open(&path);
+    let mut ioctl_string = |f: fn(&mut hidraw::Device) -> hidraw::Result<String>| {
+        device
+            .as_mut()
+            .ok()
+            .and_then(|d| f(d).ok())
+            .filter(|v| !v.is_empty())
+    };
+    let name = ioctl_string(hidraw::Device::get_raw_name);
+    let phys = ioctl_string(hidraw::Device::get_physical_address);
+    let uniq = ioctl_string(hidraw::Device::get_raw_unique);
+
+    let ids = match device.as_mut() {
+        Ok(device) => hidraw_info(device),
+        Err(e) => Err(anyhow!("Unable to open {}: {e}", path.display())),
+    };
+    let (bus, vid, pid) = ids.or_else(|ioctl_error| {
+        uevent
+            .get("HID_ID")
+            .context(ioctl_error)
+            .and_then(|hid_id| parse_hid_id(hid_id))
+    })?;
+
+    Ok(HidrawDevice {
+        name: name
+            .or_else(|| uevent_value("HID_NAME"))
+            .unwrap_or_else(|| "<unknown>".into()),
+        bus,
+        vid,
+        pid,
+        phys: phys.or_else(|| uevent_value("HID_PHYS")),
+        serial: uniq.or_else(|| uevent_value("HID_UNIQ")),
+        port: hidraw_port(file),
+        path,
+    })
+}
+
+/// The identity of a device that, unlike the hidraw node, remains stable across
+/// reboots and device renumbering. See [DeviceIdentity::resolve].
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceIdentity {
+    /// Vendor and product ID, optionally with the device's serial number
+    Id {
+        vid: u16,
+        pid: u16,
+        serial: Option<String>,
+    },
+    /// The USB port path, see [hidraw_port]
+    Port(String),
+    /// The report descriptor fingerprint, see [rdesc_fingerprint]
+    Fingerprint(String),
+}
+
+/// Returns a stable fingerprint of the report descriptor, the 64-bit
+/// FNV-1a hash of its bytes in hex. Unlike the VID:PID, this changes
+/// with a firmware update that changes the report layout.
+pub fn rdesc_fingerprint(rdesc: &[u8]) -> String {
+    let hash = rdesc.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+impl std::str::FromStr for DeviceIdentity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(fingerprint) = s.strip_prefix("rdesc:") {
+            if fingerprint.len() != 16 || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!("Invalid report descriptor fingerprint '{fingerprint}'");
+            }
+            return Ok(DeviceIdentity::Fingerprint(fingerprint.to_lowercase()));
+        }
+
+        // A USB port path starts with "<bus>-", VID:PID never contain a dash
+        let first = s.split(':').next().unwrap_or_default();
+        if first.contains('-') {
+            return Ok(DeviceIdentity::Port(s.to_string()));
+        }
+
+        let mut parts = s.splitn(3, ':');
+        let (Some(vid), Some(pid)) = (parts.next(), parts.next()) else {
+            bail!("Invalid device identity '{s}', expected VID:PID[:serial] or a port path");
+        };
+        let vid =
+            u16::from_str_radix(vid, 16).with_context(|| format!("Invalid vendor ID '{vid}'"))?;
+        let pid =
+            u16::from_str_radix(pid, 16).with_context(|| format!("Invalid product ID '{pid}'"))?;
+        let serial = parts.next().map(String::from);
+        Ok(DeviceIdentity::Id { vid, pid, serial })
+    }
+}
+
+impl std::fmt::Display for DeviceIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DeviceIdentity::Id {
+                vid,
+                pid,
+                serial: None,
+            } => write!(f, "{vid:04x}:{pid:04x}"),
+            DeviceIdentity::Id {
+                vid,
+                pid,
+                serial: Some(serial),
+            } => write!(f, "{vid:04x}:{pid:04x}:{serial}"),
+            DeviceIdentity::Port(port) => write!(f, "{port}"),
+            DeviceIdentity::Fingerprint(fingerprint) => write!(f, "rdesc:{fingerprint}"),
+        }
+    }
+}
+
+impl DeviceIdentity {
+    pub fn matches(&self, device: &HidrawDevice) -> bool {
+        match self {
+            DeviceIdentity::Id { vid, pid, serial } => {
+                device.vid == *vid
+                    && device.pid == *pid
+                    && (serial.is_none() || device.serial == *serial)
+            }
+            DeviceIdentity::Port(port) => device.matches_port(port),
+            DeviceIdentity::Fingerprint(fingerprint) => crate::backend()
+                .report_descriptor(&device.path)
+                .is_ok_and(|rdesc| rdesc_fingerprint(&rdesc) == *fingerprint),
+        }
+    }
+
+    /// Returns the currently connected hidraw devices with this identity,
+    /// or an error if there are none.
+    pub fn resolve(&self) -> Result<Vec<HidrawDevice>> {
+        let devices: Vec<HidrawDevice> = crate::backend()
+            .devices()?
+            .into_iter()
+            .filter(|d| self.matches(d))
+            .collect();
+        if devices.is_empty() {
+            bail!("No hidraw device found for {self}");
+        }
+        Ok(devices)
+    }
+
+    /// Returns the hidraw node of the single device with this identity
+    pub fn resolve_one(&self) -> Result<PathBuf> {
+        let devices = self.resolve()?;
+        match devices.as_slice() {
+            [device] => Ok(device.path.clone()),
+            _ => bail!(
+                "Multiple hidraw devices found for {self}: {}",
+                devices
+                    .iter()
+                    .map(|d| d.path.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Returns the sysfs path of the physical device the hidraw node belongs
+/// to: the USB device for USB devices or the parent of the HID device for
+/// other buses.
+#[cfg(target_os = "linux")]
+pub fn hidraw_physical_device(file: &String) -> Option<PathBuf> {
+    if let Some(interface) = hidraw_usb_interface(file) {
+        return interface.parent().map(Path::to_path_buf);
+    }
+    if !use_sysfs() {
+        return None;
+    }
+    std::fs::canonicalize(format!("/sys/class/hidraw/{file}/device"))
+        .ok()?
+        .parent()
+        .map(Path::to_path_buf)
+}
+
+/// Returns the report descriptor bytes from sysfs or, if sysfs is not
+/// available (e.g. in a container), via the HIDIOCGRDESC ioctl.
+#[cfg(target_os = "linux")]
+pub fn report_descriptor_bytes(path: &Path) -> Result<Vec<u8>> {
+    let filename = path.file_name().unwrap().to_string_lossy();
+    let rdesc_path = PathBuf::from(format!(
+        "/sys/class/hidraw/{filename}/device/report_descriptor"
+    ));
+
+    let sysfs_result = if use_sysfs() {
+        std::fs::read(&rdesc_path)
+            .with_context(|| format!("Unable to read {}", rdesc_path.display()))
+    } else {
+        Err(anyhow!("sysfs access is disabled"))
+    };
+    match sysfs_result {
+        Ok(bytes) => Ok(bytes),
+        Err(sysfs_error) => {
+            let mut device = hidraw::Device::open(path)
+                .with_context(|| format!("{sysfs_error:#} and unable to open device"))?;
+            device
+                .get_report_descriptor()
+                .context("Unable to fetch the report descriptor via HIDIOCGRDESC")
+        }
+    }
+}
+
+/// Returns the parsed report descriptor of the device, see
+/// [crate::backend]
+pub fn report_descriptor(path: &Path) -> Result<ReportDescriptor> {
+    let bytes = crate::backend().report_descriptor(path)?;
+    Ok(ReportDescriptor::try_from(&bytes)?)
+}
+
+/// Returns the report descriptor in a file's contents: either the binary
+/// descriptor or its bytes as hex text, e.g. "05 01 09 02" or "0x05, 0x01".
+/// The "R: <size> <bytes>" line of a recording (see [crate::record]) or of
+/// hid-recorder's output is also accepted.
+pub fn parse_report_descriptor(contents: &[u8]) -> Result<Vec<u8>> {
+    let Ok(text) = std::str::from_utf8(contents) else {
+        return Ok(contents.to_vec());
+    };
+    let parse_hex = |tokens: &[&str]| -> Result<Vec<u8>> {
+        tokens
+            .iter()
+            .map(|t| {
+                let hex = t
+                    .strip_prefix("0x")
+                    .or_else(|| t.strip_prefix("0X"))
+                    .unwrap_or(t);
+                u8::from_str_radix(hex, 16).with_context(|| format!("Invalid hex byte '{t}'"))
+            })
+            .collect()
+    };
+
+    if let Some(line) = text.lines().find_map(|l| l.trim().strip_prefix("R:")) {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((size, bytes)) = tokens.split_first() else {
+            bail!("Missing report descriptor size");
+        };
+        let size: usize = size
+            .parse()
+            .with_context(|| format!("Invalid report descriptor size '{size}'"))?;
+        let bytes = parse_hex(bytes)?;
+        if bytes.len() != size {
+            bail!(
+                "Expected {size} report descriptor bytes but got {}",
+                bytes.len()
+            );
+        }
+        return Ok(bytes);
+    }
+
+    let tokens: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+        .collect();
+    let is_hex = |t: &&str| {
+        let hex = t
+            .strip_prefix("0x")
+            .or_else(|| t.strip_prefix("0X"))
+            .unwrap_or(t);
+        (1..=2).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    };
+    if tokens.is_empty() || !tokens.iter().all(is_hex) {
+        return Ok(contents.to_vec());
+    }
+    parse_hex(&tokens)
+}
+
+/// Returns the report descriptor of a hidraw node or of a file containing
+/// the report descriptor, see [parse_report_descriptor]
+pub fn report_descriptor_from(path: &Path) -> Result<ReportDescriptor> {
+    if is_device_path(path)? {
+        report_descriptor(path)
+    } else {
+        let bytes = parse_report_descriptor(&std::fs::read(path)?)?;
+        Ok(ReportDescriptor::try_from(&bytes)?)
+    }
+}
+
+/// Returns true if the path is a device rather than a file, e.g. a report
+/// descriptor file. Off Linux, device paths (e.g. hidapi's) are not
+/// filesystem paths, so any path that is not a file is a device.
+#[cfg(target_os = "linux")]
+pub fn is_device_path(path: &Path) -> Result<bool> {
+    use std::os::unix::fs::FileTypeExt;
+
+    Ok(std::fs::metadata(path)
+        .with_context(|| format!("Unable to access {}", path.display()))?
+        .file_type()
+        .is_char_device())
+}
+
+/// Returns true if the path is a device rather than a file, e.g. a report
+/// descriptor file. Off Linux, device paths (e.g. hidapi's) are not
+/// filesystem paths, so any path that is not a file is a device.
+#[cfg(not(target_os = "linux"))]
+pub fn is_device_path(path: &Path) -> Result<bool> {
+    Ok(!path.is_file())
+}
@@ -0,0 +1,324 @@
+// SPDX-License-Identifier: MIT
+
+//! Integer expressions for relative writes, e.g. `cur*2` or `cur|0x04`.
+//!
+//! An expression consists of decimal, hexadecimal (`0x`) and binary
+//! (`0b`) numbers, the variable `cur` for the current value, parentheses
+//! and the operators below, from lowest to highest precedence:
+//!
+//! - `|`
+//! - `^`
+//! - `&`
+//! - `<<`, `>>`
+//! - `+`, `-`
+//! - `*`, `/`, `%`
+//! - unary `-`, `~`
+//!
+//! All arithmetic is on 64-bit signed integers, an overflow or a division
+//! by zero is an error.
+
+use anyhow::{bail, Context, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Or,
+    Xor,
+    And,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Op {
+    /// The binding strength, higher binds tighter
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Or => 1,
+            Op::Xor => 2,
+            Op::And => 3,
+            Op::Shl | Op::Shr => 4,
+            Op::Add | Op::Sub => 5,
+            Op::Mul | Op::Div | Op::Rem => 6,
+        }
+    }
+
+    fn apply(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Op::Or => Some(a | b),
+            Op::Xor => Some(a ^ b),
+            Op::And => Some(a & b),
+            Op::Shl => u32::try_from(b).ok().and_then(|b| a.checked_shl(b)),
+            Op::Shr => u32::try_from(b).ok().and_then(|b| a.checked_shr(b)),
+            Op::Add => a.checked_add(b),
+            Op::Sub => a.checked_sub(b),
+            Op::Mul => a.checked_mul(b),
+            Op::Div => a.checked_div(b),
+            Op::Rem => a.checked_rem(b),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Number(i64),
+    Cur,
+    Neg(Box<Node>),
+    Not(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Cur,
+    Op(Op),
+    Minus,
+    Tilde,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+        i += 1;
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '0'..='9' => {
+                let start = i - 1;
+                while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                    i += 1;
+                }
+                let literal: String = chars[start..i].iter().collect();
+                let value = if let Some(hex) = literal.strip_prefix("0x") {
+                    i64::from_str_radix(hex, 16)
+                } else if let Some(bin) = literal.strip_prefix("0b") {
+                    i64::from_str_radix(bin, 2)
+                } else {
+                    literal.parse()
+                };
+                Token::Number(value.with_context(|| format!("Invalid number '{literal}'"))?)
+            }
+            'a'..='z' | 'A'..='Z' | '_' => {
+                let start = i - 1;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().collect();
+                if name != "cur" {
+                    bail!("Unknown variable '{name}', only 'cur' is supported");
+                }
+                Token::Cur
+            }
+            '<' | '>' if two == "<<" || two == ">>" => {
+                i += 1;
+                Token::Op(if c == '<' { Op::Shl } else { Op::Shr })
+            }
+            '|' => Token::Op(Op::Or),
+            '^' => Token::Op(Op::Xor),
+            '&' => Token::Op(Op::And),
+            '+' => Token::Op(Op::Add),
+            '*' => Token::Op(Op::Mul),
+            '/' => Token::Op(Op::Div),
+            '%' => Token::Op(Op::Rem),
+            '-' => Token::Minus,
+            '~' => Token::Tilde,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            _ => bail!("Unexpected character '{c}'"),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Parses binary operators with at least the given precedence
+    fn binary(&mut self, min_precedence: u8) -> Result<Node> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op)) => *op,
+                Some(Token::Minus) => Op::Sub,
+                _ => break,
+            };
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(op.precedence() + 1)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Node::Number(n)),
+            Some(Token::Cur) => Ok(Node::Cur),
+            Some(Token::Minus) => Ok(Node::Neg(Box::new(self.unary()?))),
+            Some(Token::Tilde) => Ok(Node::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let node = self.binary(0)?;
+                if self.next() != Some(Token::Close) {
+                    bail!("Missing ')'");
+                }
+                Ok(node)
+            }
+            Some(token) => bail!("Unexpected {token:?}"),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+}
+
+/// A parsed expression, see the module documentation
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    root: Node,
+}
+
+impl std::str::FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Expr> {
+        let parse = || {
+            let mut parser = Parser {
+                tokens: tokenize(s)?,
+                pos: 0,
+            };
+            let root = parser.binary(0)?;
+            if let Some(token) = parser.peek() {
+                bail!("Unexpected {token:?}");
+            }
+            Ok(Expr { root })
+        };
+        parse().with_context(|| format!("Invalid expression '{s}'"))
+    }
+}
+
+fn eval(node: &Node, cur: i64) -> Option<i64> {
+    match node {
+        Node::Number(n) => Some(*n),
+        Node::Cur => Some(cur),
+        Node::Neg(node) => eval(node, cur)?.checked_neg(),
+        Node::Not(node) => Some(!eval(node, cur)?),
+        Node::Binary(op, lhs, rhs) => op.apply(eval(lhs, cur)?, eval(rhs, cur)?),
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression with `cur` as the current value
+    pub fn eval(&self, cur: i64) -> Result<i64> {
+        eval(&self.root, cur).context("Arithmetic overflow or division by zero")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(s: &str, cur: i64) -> Result<i64> {
+        s.parse::<Expr>()?.eval(cur)
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(eval("42", 0).unwrap(), 42);
+        assert_eq!(eval("0x2a", 0).unwrap(), 42);
+        assert_eq!(eval("0x2A", 0).unwrap(), 42);
+        assert_eq!(eval("0b101010", 0).unwrap(), 42);
+        assert_eq!(eval("  42 ", 0).unwrap(), 42);
+        assert_eq!(eval("9223372036854775807", 0).unwrap(), i64::MAX);
+
+        for s in ["0x", "0xg", "0b2", "12ab", "9223372036854775808"] {
+            let error = format!("{:#}", s.parse::<Expr>().unwrap_err());
+            assert!(error.contains("Invalid number"), "{s}: {error}");
+        }
+    }
+
+    #[test]
+    fn test_cur() {
+        assert_eq!(eval("cur", 7).unwrap(), 7);
+        assert_eq!(eval("cur*2", 21).unwrap(), 42);
+        assert_eq!(eval("cur|0x04", 1).unwrap(), 5);
+        assert_eq!(eval("cur & ~0x04", 0xff).unwrap(), 0xfb);
+        assert_eq!(eval("cur ^ cur", 99).unwrap(), 0);
+        assert_eq!(eval("-cur", -7).unwrap(), 7);
+
+        let error = format!("{:#}", "cursor+1".parse::<Expr>().unwrap_err());
+        assert!(error.contains("Unknown variable 'cursor'"), "{error}");
+        assert!("value".parse::<Expr>().is_err());
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("1+2*3", 0).unwrap(), 7);
+        assert_eq!(eval("(1+2)*3", 0).unwrap(), 9);
+        assert_eq!(eval("1|2^3&4<<1+1", 0).unwrap(), 3);
+        assert_eq!(eval("1 << 4 | 1", 0).unwrap(), 17);
+        assert_eq!(eval("256 >> 4 >> 2", 0).unwrap(), 4);
+        // Left-associative
+        assert_eq!(eval("10-2-3", 0).unwrap(), 5);
+        assert_eq!(eval("64/4/2", 0).unwrap(), 8);
+        assert_eq!(eval("-7 % 3", 0).unwrap(), -1);
+        // Unary operators bind tightest
+        assert_eq!(eval("-2*3", 0).unwrap(), -6);
+        assert_eq!(eval("--1", 0).unwrap(), 1);
+        assert_eq!(eval("~0", 0).unwrap(), -1);
+        assert_eq!(eval("~(1|2)", 0).unwrap(), -4);
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for s in [
+            "", "1 +", "(1", "1)", "1 2", "cur cur", "*2", "1 $ 2", "1 < 2", "()",
+        ] {
+            let error = format!("{:#}", s.parse::<Expr>().unwrap_err());
+            assert!(
+                error.starts_with(&format!("Invalid expression '{s}'")),
+                "{s}: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_errors() {
+        for (s, cur) in [
+            ("1/0", 0),
+            ("cur%0", 1),
+            ("cur/cur", 0),
+            ("1<<64", 0),
+            ("1>>-1", 0),
+            ("9223372036854775807+1", 0),
+            ("cur*2", i64::MAX),
+            ("-cur", i64::MIN),
+            ("cur/-1", i64::MIN),
+        ] {
+            let expr: Expr = s.parse().unwrap();
+            assert!(expr.eval(cur).is_err(), "{s} with cur={cur}");
+        }
+    }
+}
@@ -0,0 +1,398 @@
+// SPDX-License-Identifier: MIT
+
+//! Looking up report fields by usage name and packing field values.
+
+use anyhow::{bail, Result};
+use hidreport::*;
+
+/// Returns the usage name of the field's innermost Application Collection
+pub fn application_collection(field: &Field) -> Option<String> {
+    field
+        .collections()
+        .iter()
+        .rev()
+        .find(|c| c.collection_type() == CollectionType::Application)
+        .and_then(|c| c.usages().first())
+        .map(usage_name)
+}
+
+/// Returns the Application Collection of the report's first field, see
+/// [application_collection]
+pub fn report_collection<R: Report>(report: &R) -> Option<String> {
+    report.fields().first().and_then(application_collection)
+}
+
+/// Returns true if the same Report ID is used by more than one report,
+/// which is possible where the reports are in different collections
+pub fn has_duplicate_report_ids<R: Report>(reports: &[R]) -> bool {
+    let mut ids = std::collections::HashSet::new();
+    reports
+        .iter()
+        .filter_map(|r| *r.report_id())
+        .any(|id| !ids.insert(u8::from(id)))
+}
+
+/// Returns true if any field of the report is in an Application Collection
+/// whose usage matches the name, see [usage_matches]
+pub fn report_in_collection<R: Report>(report: &R, name: &str) -> bool {
+    report.fields().iter().any(|f| {
+        f.collections().iter().any(|c| {
+            c.collection_type() == CollectionType::Application
+                && c.usages().iter().any(|u| usage_matches(u, name))
+        })
+    })
+}
+
+/// Returns the name of the usage as "Usage Page / Usage", e.g.
+/// "Generic Desktop / Resolution Multiplier"
+pub fn usage_name(usage: &Usage) -> String {
+    match hut::Usage::new_from_page_and_id(u16::from(usage.usage_page), u16::from(usage.usage_id)) {
+        Err(_) => "<unknown>".into(),
+        Ok(u) => format!("{} / {}", hut::UsagePage::from(&u), u),
+    }
+}
+
+/// Returns the unit for humans, e.g. "cm" or "g·cm/s²", or `None` if the
+/// unit has no dimensions. Units with a negative exponent are printed
+/// after a '/'.
+pub fn unit_name(unit: &Unit) -> Option<String> {
+    fn superscript(exponent: i8) -> String {
+        const DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+        match exponent {
+            1 => String::new(),
+            e => e
+                .to_string()
+                .chars()
+                .map(|c| match c {
+                    '-' => '⁻',
+                    c => DIGITS[c.to_digit(10).unwrap_or(0) as usize],
+                })
+                .collect(),
+        }
+    }
+
+    let units = unit.units()?;
+    let (mut numerator, mut denominator) = (Vec::new(), Vec::new());
+    for u in units {
+        let (symbol, exponent) = match u {
+            Units::None => continue,
+            Units::Centimeter { exponent } => ("cm", exponent),
+            Units::Radians { exponent } => ("rad", exponent),
+            Units::Inch { exponent } => ("in", exponent),
+            Units::Degrees { exponent } => ("deg", exponent),
+            Units::Gram { exponent } => ("g", exponent),
+            Units::Slug { exponent } => ("slug", exponent),
+            Units::Seconds { exponent } => ("s", exponent),
+            Units::Kelvin { exponent } => ("K", exponent),
+            Units::Fahrenheit { exponent } => ("°F", exponent),
+            Units::Ampere { exponent } => ("A", exponent),
+            Units::Candela { exponent } => ("cd", exponent),
+        };
+        match exponent {
+            0 => {}
+            e if e > 0 => numerator.push(format!("{symbol}{}", superscript(e))),
+            e => denominator.push(format!("{symbol}{}", superscript(-e))),
+        }
+    }
+    let name = match (numerator.is_empty(), denominator.is_empty()) {
+        (true, true) => return None,
+        (false, true) => numerator.join("·"),
+        (true, false) => format!("1/{}", denominator.join("·")),
+        (false, false) => format!("{}/{}", numerator.join("·"), denominator.join("·")),
+    };
+    Some(name)
+}
+
+/// Returns true if the given name matches the usage's full name as
+/// returned by [usage_name] (the spaces around the '/' are optional) or
+/// just the name of the usage itself, e.g. "Resolution Multiplier".
+///
+/// A name with wildcards is matched case-insensitively, see [glob_matches].
+pub fn usage_matches(usage: &Usage, name: &str) -> bool {
+    let full_name = usage_name(usage);
+    let Some((page_name, usage_name)) = full_name.split_once(" / ") else {
+        return false;
+    };
+    if is_glob(name) {
+        return glob_matches(name, &full_name) || glob_matches(name, usage_name);
+    }
+    match name.split_once('/') {
+        Some((page, usage)) => page.trim() == page_name && usage.trim() == usage_name,
+        None => name == usage_name,
+    }
+}
+
+/// Returns true if the pattern contains wildcards
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+/// Returns true if the text matches the shell-style pattern where '*'
+/// matches any number of characters and '?' exactly one. The match is
+/// case-insensitive.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // The position of the last '*' in the pattern and the text position it
+    // matched up to, for backtracking
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Returns the indices of the usages matching the name, trying in order:
+/// exact matches (see [usage_matches]), case-insensitive matches and
+/// case-insensitive substring matches of the full usage name.
+pub fn find_usages(usages: &[&Usage], name: &str) -> Vec<usize> {
+    let find = |pred: &dyn Fn(&Usage) -> bool| -> Vec<usize> {
+        (0..usages.len()).filter(|idx| pred(usages[*idx])).collect()
+    };
+    let lowercase = name.to_lowercase();
+    let by_lowercase = |u: &Usage| {
+        let full_name = usage_name(u).to_lowercase();
+        full_name == lowercase
+            || full_name
+                .split_once(" / ")
+                .is_some_and(|(_, usage)| usage == lowercase)
+    };
+    let by_substring = |u: &Usage| usage_name(u).to_lowercase().contains(&lowercase);
+
+    [
+        find(&|u: &Usage| usage_matches(u, name)),
+        find(&by_lowercase),
+        find(&by_substring),
+    ]
+    .into_iter()
+    .find(|matches| !matches.is_empty())
+    .unwrap_or_default()
+}
+
+/// Returns the usage name closest to the given name, if any is close enough
+/// to likely be a typo
+pub fn suggest_usage(usages: &[&Usage], name: &str) -> Option<String> {
+    let lowercase = name.to_lowercase();
+    usages
+        .iter()
+        .map(|u| usage_name(u))
+        .filter_map(|full_name| {
+            let short_name = full_name
+                .split_once(" / ")
+                .map_or(full_name.as_str(), |(_, usage)| usage)
+                .to_string();
+            let distance = edit_distance(&short_name.to_lowercase(), &lowercase)
+                .min(edit_distance(&full_name.to_lowercase(), &lowercase));
+            (distance <= lowercase.chars().count() / 3 + 1).then_some((distance, short_name))
+        })
+        .min()
+        .map(|(_, short_name)| short_name)
+}
+
+/// Returns the Levenshtein distance between the two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Writes the value into the given bits of the report, least significant bit first
+pub fn pack_field(bytes: &mut [u8], bits: &std::ops::Range<usize>, value: u32) {
+    for (i, bit) in bits.clone().enumerate() {
+        let mask = 1 << (bit % 8);
+        if i < 32 && (value >> i) & 1 == 1 {
+            bytes[bit / 8] |= mask;
+        } else {
+            bytes[bit / 8] &= !mask;
+        }
+    }
+}
+
+/// Reads the value in the given bits of the report, least significant bit
+/// first. If `signed` is true the value is sign-extended.
+pub fn unpack_field(bytes: &[u8], bits: &std::ops::Range<usize>, signed: bool) -> i64 {
+    let mut value: i64 = 0;
+    for (i, bit) in bits.clone().take(63).enumerate() {
+        if (bytes[bit / 8] >> (bit % 8)) & 1 == 1 {
+            value |= 1 << i;
+        }
+    }
+    let len = bits.len().min(63);
+    if signed && len > 0 && value & (1 << (len - 1)) != 0 {
+        value -= 1 << len;
+    }
+    value
+}
+
+/// Returns the bits and values of each slot of the Array field in the
+/// report that has all the given usages. Slots without a usage are set to
+/// a value outside the logical range, i.e. no usage.
+pub fn array_selection<R: Report>(
+    report: &R,
+    names: &[String],
+) -> Result<Vec<(std::ops::Range<usize>, u32)>> {
+    let arrays: Vec<_> = report
+        .fields()
+        .iter()
+        .filter_map(|f| match f {
+            Field::Array(arr) => Some(arr),
+            _ => None,
+        })
+        .collect();
+    if arrays.is_empty() {
+        bail!("This Feature Report does not have any Array fields");
+    }
+
+    let mut unknown = &names[0];
+    let found = arrays.iter().find_map(|arr| {
+        let usages: Vec<&Usage> = arr.usages().iter().collect();
+        let mut indices = Vec::new();
+        for name in names {
+            match find_usages(&usages, name).as_slice() {
+                [idx] => indices.push(*idx),
+                _ => {
+                    unknown = name;
+                    return None;
+                }
+            }
+        }
+        Some((arr, indices))
+    });
+    let Some((arr, indices)) = found else {
+        let usages: Vec<&Usage> = arrays.iter().flat_map(|arr| arr.usages()).collect();
+        match suggest_usage(&usages, unknown) {
+            Some(suggestion) => {
+                bail!(
+                    "Unable to find an Array usage named '{unknown}', did you mean '{suggestion}'?"
+                )
+            }
+            None => bail!("Unable to find an Array usage named '{unknown}'"),
+        }
+    };
+
+    let count = usize::from(arr.report_count);
+    if indices.len() > count {
+        bail!("Too many usages selected, this Array field has only {count} slot(s)");
+    }
+    let slot_bits = arr.bits.len() / count.max(1);
+    let minimum = i32::from(arr.logical_minimum);
+    let maximum = i32::from(arr.logical_maximum);
+    let none = if minimum > 0 { 0 } else { maximum as i64 + 1 };
+    if slot_bits < 32 && none >= 1 << slot_bits {
+        bail!("This Array field has no value for an empty slot");
+    }
+
+    Ok((0..count)
+        .map(|slot| {
+            let start = arr.bits.start + slot * slot_bits;
+            let value = match indices.get(slot) {
+                Some(idx) => (minimum as i64 + *idx as i64) as u32,
+                None => none as u32,
+            };
+            (start..start + slot_bits, value)
+        })
+        .collect())
+}
+
+/// Returns a name for any field: the usage name for variable fields, the
+/// first usage for array fields and "<padding>" for constant fields
+pub fn field_name(field: &Field) -> String {
+    match field {
+        Field::Variable(var) => usage_name(&var.usage),
+        Field::Array(arr) => match arr.usages().first() {
+            Some(usage) => format!("{} (array)", usage_name(usage)),
+            None => "<array>".into(),
+        },
+        Field::Constant(_) => "<padding>".into(),
+    }
+}
+
+/// Returns the Variable field whose usage matches the name, see
+/// [find_usages], in the reports for which `report_matches` is true
+pub fn find_variable_field<'a, R: Report>(
+    reports: &'a [R],
+    name: &str,
+    report_matches: impl Fn(&R) -> bool,
+) -> Result<(&'a R, &'a VariableField)> {
+    let candidates: Vec<_> = reports
+        .iter()
+        .filter(|r| report_matches(r))
+        .flat_map(|r| r.fields().iter().map(move |f| (r, f)))
+        .filter_map(|(r, f)| match f {
+            Field::Variable(var) => Some((r, var)),
+            _ => None,
+        })
+        .collect();
+    let usages: Vec<&Usage> = candidates.iter().map(|(_, var)| &var.usage).collect();
+    let matches: Vec<_> = find_usages(&usages, name)
+        .into_iter()
+        .map(|idx| candidates[idx])
+        .collect();
+    match matches.as_slice() {
+        [] => match suggest_usage(&usages, name) {
+            Some(suggestion) => {
+                bail!("Unable to find a Feature field named '{name}', did you mean '{suggestion}'?")
+            }
+            None => bail!("Unable to find a Feature field named '{name}'"),
+        },
+        [m] => Ok(*m),
+        _ => bail!(
+            "Multiple Feature fields match '{name}', use the full name or --report-id to select one of: {}",
+            matches
+                .iter()
+                .map(|(r, var)| format!(
+                    "{} (report {})",
+                    usage_name(&var.usage),
+                    r.report_id().map_or(0, u8::from)
+                ))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_name() {
+        // SI Linear, centimeters
+        assert_eq!(unit_name(&Unit::from(0x11u32)).as_deref(), Some("cm"));
+        // SI Linear, cm·g/s²
+        assert_eq!(
+            unit_name(&Unit::from(0xe111u32)).as_deref(),
+            Some("cm·g/s²")
+        );
+        // SI Linear, 1/s
+        assert_eq!(unit_name(&Unit::from(0xf001u32)).as_deref(), Some("1/s"));
+        assert_eq!(unit_name(&Unit::from(0u32)), None);
+    }
+}
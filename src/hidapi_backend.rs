@@ -0,0 +1,246 @@
+// SPDX-License-Identifier: MIT
+
+//! A [HidBackend] on top of hidapi, for platforms without hidraw (Windows,
+//! macOS) or to compare against the hidraw backend on Linux.
+//!
+//! Devices are identified by hidapi's device path, e.g. a `\\?\HID#...`
+//! path on Windows or `DevSrvsID:4294971173` on macOS. Those are not
+//! filesystem paths but are used like a hidraw node everywhere else.
+//! hidapi has no way to fetch Output Reports (or, on Windows, Input
+//! Reports) and Output Reports are sent on the interrupt pipe rather than
+//! with SET_REPORT.
+
+use crate::{HidBackend, HidrawDevice, ReportType};
+use anyhow::{bail, Context, Result};
+use hidapi::{BusType, HidApi, HidDevice, HidError, MAX_REPORT_DESCRIPTOR_SIZE};
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Converts the error, keeping an I/O error as [std::io::Error] so
+/// [crate::is_disconnect] can check it
+fn hid_error(error: HidError) -> anyhow::Error {
+    match error {
+        HidError::IoError { error } => error.into(),
+        error => error.into(),
+    }
+}
+
+/// The bus type as in struct hidraw_devinfo, see [crate::bus_name]
+fn bus(bus_type: BusType) -> u16 {
+    match bus_type {
+        BusType::Usb => 0x03,
+        BusType::Bluetooth => 0x05,
+        BusType::I2c => 0x18,
+        BusType::Spi => 0x1c,
+        BusType::Unknown => 0x00,
+    }
+}
+
+/// The backend for the devices hidapi finds
+pub struct HidapiBackend {
+    api: Mutex<HidApi>,
+}
+
+impl HidapiBackend {
+    pub fn new() -> Result<HidapiBackend> {
+        let api = HidApi::new()
+            .map_err(hid_error)
+            .context("Unable to initialize hidapi")?;
+        Ok(HidapiBackend {
+            api: Mutex::new(api),
+        })
+    }
+
+    fn open(&self, path: &Path) -> Result<HidDevice> {
+        let cpath = CString::new(path.to_string_lossy().as_bytes())
+            .with_context(|| format!("Invalid device path {}", path.display()))?;
+        self.api
+            .lock()
+            .unwrap()
+            .open_path(&cpath)
+            .map_err(hid_error)
+            .with_context(|| format!("Unable to open {}", path.display()))
+    }
+}
+
+impl HidBackend for HidapiBackend {
+    fn devices(&self) -> Result<Vec<HidrawDevice>> {
+        let mut api = self.api.lock().unwrap();
+        api.refresh_devices().map_err(hid_error)?;
+        let mut devices: Vec<HidrawDevice> = Vec::new();
+        // On macOS, every top-level collection of a device is listed with
+        // the same path
+        for info in api.device_list() {
+            let path = PathBuf::from(info.path().to_string_lossy().into_owned());
+            if devices.iter().any(|d| d.path == path) {
+                continue;
+            }
+            let name = match (info.manufacturer_string(), info.product_string()) {
+                (Some(manufacturer), Some(product))
+                    if !manufacturer.is_empty() && !product.starts_with(manufacturer) =>
+                {
+                    format!("{manufacturer} {product}")
+                }
+                (_, Some(product)) if !product.is_empty() => product.to_string(),
+                _ => "<unknown>".into(),
+            };
+            devices.push(HidrawDevice {
+                path,
+                name,
+                bus: bus(info.bus_type()),
+                vid: info.vendor_id(),
+                pid: info.product_id(),
+                phys: None,
+                serial: info
+                    .serial_number()
+                    .filter(|s| !s.is_empty())
+                    .map(String::from),
+                port: None,
+            });
+        }
+        devices.sort_by(|a, b| {
+            human_sort::compare(&a.path.to_string_lossy(), &b.path.to_string_lossy())
+        });
+        Ok(devices)
+    }
+
+    fn report_descriptor(&self, path: &Path) -> Result<Vec<u8>> {
+        get_report_descriptor(&self.open(path)?)
+    }
+
+    fn get_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        get_hidapi_report(&self.open(path)?, report_type, report_id, size)
+    }
+
+    fn set_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        set_hidapi_report(&self.open(path)?, report_type, report_id, bytes)
+    }
+
+    fn open_device<'a>(&'a self, path: &Path) -> Result<Box<dyn HidBackend + 'a>> {
+        Ok(Box::new(OpenHidapiDevice {
+            backend: self,
+            path: path.to_path_buf(),
+            device: self.open(path)?,
+        }))
+    }
+}
+
+/// Fetches the report descriptor, on Windows hidapi reconstructs it from
+/// the device's preparsed data
+fn get_report_descriptor(device: &HidDevice) -> Result<Vec<u8>> {
+    let mut buf = vec![0; MAX_REPORT_DESCRIPTOR_SIZE];
+    let size = device
+        .get_report_descriptor(&mut buf)
+        .map_err(hid_error)
+        .context("Unable to fetch the report descriptor")?;
+    buf.truncate(size);
+    Ok(buf)
+}
+
+/// Like the hidraw ioctls, hidapi's buffer starts with the Report ID or 0
+fn get_hidapi_report(
+    device: &HidDevice,
+    report_type: ReportType,
+    report_id: Option<u8>,
+    size: usize,
+) -> Result<Vec<u8>> {
+    let fetch_size = match report_id {
+        Some(_) => size,
+        None => size + 1,
+    };
+    let mut r = vec![0; fetch_size];
+    r[0] = report_id.unwrap_or(0);
+    match report_type {
+        ReportType::Feature => device.get_feature_report(&mut r),
+        #[cfg(not(windows))]
+        ReportType::Input => device.get_input_report(&mut r),
+        #[cfg(windows)]
+        ReportType::Input => bail!("hidapi cannot fetch Input Reports on Windows"),
+        ReportType::Output => bail!("hidapi cannot fetch Output Reports"),
+    }
+    .map_err(hid_error)?;
+    r.truncate(size);
+    Ok(r)
+}
+
+fn set_hidapi_report(
+    device: &HidDevice,
+    report_type: ReportType,
+    report_id: Option<u8>,
+    bytes: &[u8],
+) -> Result<()> {
+    let buf = match report_id {
+        Some(_) => bytes.to_vec(),
+        None => [&[0], bytes].concat(),
+    };
+    match report_type {
+        ReportType::Feature => device.send_feature_report(&buf).map_err(hid_error),
+        ReportType::Output => {
+            let written = device.write(&buf).map_err(hid_error)?;
+            if written < buf.len() {
+                bail!("Only {written} of {} bytes were written", buf.len());
+            }
+            Ok(())
+        }
+        ReportType::Input => bail!("hidapi cannot send Input Reports"),
+    }
+}
+
+/// A device kept open by [HidapiBackend::open_device]
+struct OpenHidapiDevice<'a> {
+    backend: &'a HidapiBackend,
+    path: PathBuf,
+    device: HidDevice,
+}
+
+impl HidBackend for OpenHidapiDevice<'_> {
+    fn devices(&self) -> Result<Vec<HidrawDevice>> {
+        self.backend.devices()
+    }
+
+    fn report_descriptor(&self, path: &Path) -> Result<Vec<u8>> {
+        if path != self.path {
+            return self.backend.report_descriptor(path);
+        }
+        get_report_descriptor(&self.device)
+    }
+
+    fn get_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        size: usize,
+    ) -> Result<Vec<u8>> {
+        if path != self.path {
+            return self.backend.get_report(path, report_type, report_id, size);
+        }
+        get_hidapi_report(&self.device, report_type, report_id, size)
+    }
+
+    fn set_report(
+        &self,
+        path: &Path,
+        report_type: ReportType,
+        report_id: Option<u8>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        if path != self.path {
+            return self.backend.set_report(path, report_type, report_id, bytes);
+        }
+        set_hidapi_report(&self.device, report_type, report_id, bytes)
+    }
+}
@@ -0,0 +1,197 @@
+// SPDX-License-Identifier: MIT
+
+//! A minimal parser for the raw items of a report descriptor, used to show
+//! which items produced a field (see --debug).
+//!
+//! This is deliberately independent of hidreport so that what the firmware
+//! declares can be compared with what hidreport makes of it.
+
+use anyhow::{bail, Result};
+use std::ops::Range;
+
+const MAIN_FEATURE: u8 = 0xb;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ItemType {
+    Main,
+    Global,
+    Local,
+    Reserved,
+}
+
+/// A single short item of a report descriptor
+#[derive(Clone, Debug)]
+pub struct Item {
+    /// The offset of the item in the report descriptor
+    pub offset: usize,
+    /// The raw bytes of the item, including the prefix byte
+    pub bytes: Vec<u8>,
+    item_type: ItemType,
+    tag: u8,
+}
+
+impl Item {
+    fn data(&self) -> u32 {
+        self.bytes[1..]
+            .iter()
+            .rev()
+            .fold(0, |acc, b| (acc << 8) | *b as u32)
+    }
+
+    fn signed_data(&self) -> i32 {
+        match self.bytes.len() - 1 {
+            1 => self.data() as u8 as i8 as i32,
+            2 => self.data() as u16 as i16 as i32,
+            _ => self.data() as i32,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match (self.item_type, self.tag) {
+            (ItemType::Main, 0x8) => "Input",
+            (ItemType::Main, 0x9) => "Output",
+            (ItemType::Main, 0xa) => "Collection",
+            (ItemType::Main, 0xb) => "Feature",
+            (ItemType::Main, 0xc) => "End Collection",
+            (ItemType::Global, 0x0) => "Usage Page",
+            (ItemType::Global, 0x1) => "Logical Minimum",
+            (ItemType::Global, 0x2) => "Logical Maximum",
+            (ItemType::Global, 0x3) => "Physical Minimum",
+            (ItemType::Global, 0x4) => "Physical Maximum",
+            (ItemType::Global, 0x5) => "Unit Exponent",
+            (ItemType::Global, 0x6) => "Unit",
+            (ItemType::Global, 0x7) => "Report Size",
+            (ItemType::Global, 0x8) => "Report ID",
+            (ItemType::Global, 0x9) => "Report Count",
+            (ItemType::Global, 0xa) => "Push",
+            (ItemType::Global, 0xb) => "Pop",
+            (ItemType::Local, 0x0) => "Usage",
+            (ItemType::Local, 0x1) => "Usage Minimum",
+            (ItemType::Local, 0x2) => "Usage Maximum",
+            (ItemType::Local, 0x3) => "Designator Index",
+            (ItemType::Local, 0x4) => "Designator Minimum",
+            (ItemType::Local, 0x5) => "Designator Maximum",
+            (ItemType::Local, 0x7) => "String Index",
+            (ItemType::Local, 0x8) => "String Minimum",
+            (ItemType::Local, 0x9) => "String Maximum",
+            (ItemType::Local, 0xa) => "Delimiter",
+            _ => "Reserved",
+        }
+    }
+}
+
+impl std::fmt::Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let bytes = self
+            .bytes
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<String>>()
+            .join(" ");
+        write!(f, "0x{:04x}: {bytes:14} {}", self.offset, self.name())?;
+        if self.bytes.len() == 1 {
+            return Ok(());
+        }
+        match (self.item_type, self.tag) {
+            (ItemType::Global, 0x1..=0x5) => write!(f, " ({})", self.signed_data()),
+            (ItemType::Global, 0x0) | (ItemType::Local, _) | (ItemType::Main, _) => {
+                write!(f, " (0x{:x})", self.data())
+            }
+            _ => write!(f, " ({})", self.data()),
+        }
+    }
+}
+
+/// Splits the report descriptor into its items. Long items are skipped.
+pub fn parse(rdesc: &[u8]) -> Result<Vec<Item>> {
+    let mut items = Vec::new();
+    let mut offset = 0;
+    while offset < rdesc.len() {
+        let prefix = rdesc[offset];
+        // Long item: 0xfe, data size, long item tag, data
+        if prefix == 0xfe {
+            let Some(size) = rdesc.get(offset + 1) else {
+                bail!("Truncated long item at offset {offset}");
+            };
+            offset += 3 + *size as usize;
+            continue;
+        }
+        let size = match prefix & 0x3 {
+            3 => 4,
+            n => n as usize,
+        };
+        let Some(bytes) = rdesc.get(offset..offset + 1 + size) else {
+            bail!("Truncated item at offset {offset}");
+        };
+        let item_type = match (prefix >> 2) & 0x3 {
+            0 => ItemType::Main,
+            1 => ItemType::Global,
+            2 => ItemType::Local,
+            _ => ItemType::Reserved,
+        };
+        items.push(Item {
+            offset,
+            bytes: bytes.to_vec(),
+            item_type,
+            tag: prefix >> 4,
+        });
+        offset += 1 + size;
+    }
+    Ok(items)
+}
+
+/// A Feature main item and the items that led up to it since the previous
+/// main item
+#[derive(Debug)]
+pub struct FeatureItem {
+    pub report_id: Option<u8>,
+    /// The bits the fields of this main item occupy in the report,
+    /// including the Report ID byte if any
+    pub bits: Range<usize>,
+    pub items: Vec<Item>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Globals {
+    report_id: Option<u8>,
+    report_size: usize,
+    report_count: usize,
+}
+
+/// Returns the Feature main items of the report descriptor with their
+/// position in the report.
+pub fn feature_items(rdesc: &[u8]) -> Result<Vec<FeatureItem>> {
+    let mut result = Vec::new();
+    let mut globals = Globals::default();
+    let mut stack = Vec::new();
+    let mut pending = Vec::new();
+    // The current size in bits of each feature report
+    let mut sizes: std::collections::HashMap<Option<u8>, usize> = Default::default();
+
+    for item in parse(rdesc)? {
+        pending.push(item.clone());
+        match (item.item_type, item.tag) {
+            (ItemType::Global, 0x7) => globals.report_size = item.data() as usize,
+            (ItemType::Global, 0x8) => globals.report_id = Some(item.data() as u8),
+            (ItemType::Global, 0x9) => globals.report_count = item.data() as usize,
+            (ItemType::Global, 0xa) => stack.push(globals),
+            (ItemType::Global, 0xb) => globals = stack.pop().unwrap_or_default(),
+            (ItemType::Main, tag) => {
+                if tag == MAIN_FEATURE {
+                    let id = globals.report_id;
+                    let start = sizes.entry(id).or_insert(if id.is_some() { 8 } else { 0 });
+                    let end = *start + globals.report_size * globals.report_count;
+                    result.push(FeatureItem {
+                        report_id: id,
+                        bits: *start..end,
+                        items: std::mem::take(&mut pending),
+                    });
+                    *start = end;
+                }
+                pending.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: MIT
+
+//! The device and report handling behind the hid-feature tool.
+//!
+//! This crate enumerates HID devices ([HidBackend::devices]), fetches and
+//! parses their report descriptors ([report_descriptor]) and reads and
+//! writes Feature Reports ([FeatureReportHandle]). Devices are accessed
+//! through a [HidBackend]: [HidrawBackend] on Linux or, with the `hidapi`
+//! feature, [HidapiBackend] on any platform. [MockBackend] can stand in for
+//! the hardware. Fields are looked up by usage name with
+//! [find_variable_field] and written with [pack_field] or
+//! [FeatureReportHandle::set_field].
+//!
+//! ```no_run
+//! use hid_feature::*;
+//! use hidreport::Report;
+//! use std::path::Path;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let path = Path::new("/dev/hidraw0");
+//! let rdesc = report_descriptor(path)?;
+//! let (report, field) =
+//!     find_variable_field(rdesc.feature_reports(), "Resolution Multiplier", |_| true)?;
+//! FeatureReportHandle::new(backend(), path, report).set_field(&field.bits, 1)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+mod backend;
+pub mod capture;
+mod device;
+#[cfg(target_os = "linux")]
+pub mod driver;
+mod enumerate;
+pub mod expr;
+mod fields;
+#[cfg(feature = "hidapi")]
+mod hidapi_backend;
+pub mod items;
+pub mod rdesc_edit;
+pub mod record;
+pub mod snapshot;
+#[cfg(target_os = "linux")]
+pub mod uhid;
+
+pub use backend::*;
+pub use device::*;
+pub use enumerate::*;
+pub use fields::*;
+#[cfg(feature = "hidapi")]
+pub use hidapi_backend::*;
+
+/// See [set_backend]
+static BACKEND: OnceLock<Box<dyn HidBackend + Send + Sync>> = OnceLock::new();
+
+/// Sets the backend returned by [backend]. This can only be set once and
+/// must be set before the first call to [backend].
+pub fn set_backend(backend: Box<dyn HidBackend + Send + Sync>) {
+    let _ = BACKEND.set(backend);
+}
+
+/// Returns the backend for the devices given by path, by default
+/// [HidrawBackend] on Linux and [HidapiBackend] elsewhere
+pub fn backend() -> &'static (dyn HidBackend + Sync) {
+    BACKEND.get_or_init(default_backend).as_ref()
+}
+
+#[cfg(target_os = "linux")]
+fn default_backend() -> Box<dyn HidBackend + Send + Sync> {
+    Box::new(HidrawBackend)
+}
+
+#[cfg(all(not(target_os = "linux"), feature = "hidapi"))]
+fn default_backend() -> Box<dyn HidBackend + Send + Sync> {
+    match HidapiBackend::new() {
+        Ok(backend) => Box::new(backend),
+        Err(e) => Box::new(UnavailableBackend(format!("{e:#}"))),
+    }
+}
+
+#[cfg(all(not(target_os = "linux"), not(feature = "hidapi")))]
+fn default_backend() -> Box<dyn HidBackend + Send + Sync> {
+    Box::new(UnavailableBackend(
+        "hid-feature was built without the hidapi feature".into(),
+    ))
+}
+
+/// See [set_use_sysfs]
+static NO_SYSFS: AtomicBool = AtomicBool::new(false);
+
+/// Returns false if sysfs must not be accessed
+pub fn use_sysfs() -> bool {
+    !NO_SYSFS.load(Ordering::Relaxed)
+}
+
+/// Enables or disables sysfs access, e.g. in a container where sysfs is
+/// not available. Without sysfs, device information and report descriptors
+/// are taken from the hidraw ioctls only.
+pub fn set_use_sysfs(enabled: bool) {
+    NO_SYSFS.store(!enabled, Ordering::Relaxed);
+}
+
+/// See [set_ioctl_timeout]
+static IOCTL_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Sets the time after which a feature report request fails if the device
+/// does not respond. This can only be set once.
+pub fn set_ioctl_timeout(timeout: Duration) {
+    let _ = IOCTL_TIMEOUT.set(timeout);
+}
+
+/// See [set_helper]
+static HELPER: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Sets the command (program and arguments) to run the privileged
+/// hid-feature-helper with, feature report requests are then sent via the
+/// helper instead of opening the hidraw node. This can only be set once.
+pub fn set_helper(command: Vec<String>) {
+    let _ = HELPER.set(command);
+}
+
+/// Formats the bytes as space-separated hex values
+pub fn print_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
@@ -1,26 +1,241 @@
 // SPDX-License-Identifier: MIT
 
-use anyhow::{bail, Context, Result};
-use clap::{ColorChoice, Parser, Subcommand};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, ColorChoice, Parser, Subcommand, ValueEnum};
 use hidreport::*;
 use owo_colors::{OwoColorize, Stream::Stdout, Style};
+use std::collections::{BTreeMap, HashMap};
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-type FeatureReport = [u8; 1024];
+use hid_feature::*;
 
-fn print_bytes(bytes: &[u8]) -> String {
-    bytes
-        .iter()
-        .map(|b| format!("{b:02x}"))
-        .collect::<Vec<String>>()
-        .join(" ")
+mod config;
+mod mqtt;
+#[cfg(target_os = "linux")]
+mod sandbox;
+#[cfg(unix)]
+mod tui;
+
+#[cfg(not(target_os = "linux"))]
+mod sandbox {
+    pub fn restrict(_device: &std::path::Path) -> anyhow::Result<()> {
+        anyhow::bail!("--sandbox is only available on Linux")
+    }
+}
+
+/// Without termios there is no editor and `watch` does not read keys
+#[cfg(not(unix))]
+mod tui {
+    use anyhow::{bail, Result};
+    use std::path::Path;
+    use std::time::Duration;
+
+    // Matched by watch but never read
+    #[allow(dead_code)]
+    pub(crate) enum Key {
+        Char(char),
+        Quit,
+    }
+
+    pub(crate) struct KeyInput;
+
+    impl KeyInput {
+        pub(crate) fn enable() -> Result<Option<KeyInput>> {
+            Ok(None)
+        }
+
+        pub(crate) fn wait(&self, _timeout: Duration) -> Result<Option<Key>> {
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn run(_path: &Path, _options: &super::WriteOptions) -> Result<()> {
+        bail!("The editor is not available on this platform, use --field")
+    }
+}
+
+/// Set by --debug, see [debug]
+static DEBUG: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if debugging information should be printed
+fn debug() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+/// Set by --format json, errors are then printed as JSON, see [json_error]
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// An error with the device and Report ID it happened with, for the JSON
+/// errors. It displays as the error it wraps.
+#[derive(Debug)]
+struct DeviceError {
+    device: PathBuf,
+    report_id: Option<u8>,
+    error: anyhow::Error,
+}
+
+impl std::fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for DeviceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+trait DeviceContext<T> {
+    /// Attaches the device and Report ID to the error unless it already
+    /// has them, see [DeviceError]
+    fn device_context(self, device: &Path, report_id: Option<u8>) -> Result<T>;
+}
+
+impl<T> DeviceContext<T> for Result<T> {
+    fn device_context(self, device: &Path, report_id: Option<u8>) -> Result<T> {
+        self.map_err(|error| {
+            if error.chain().any(|e| e.is::<DeviceError>()) {
+                return error;
+            }
+            DeviceError {
+                device: device.into(),
+                report_id,
+                error,
+            }
+            .into()
+        })
+    }
+}
+
+/// Returns the error as printed with --format json: the exit code, the
+/// message and its causes and, if known, the device and Report ID
+fn json_error(code: u8, error: &anyhow::Error) -> serde_json::Value {
+    let context = error.chain().find_map(|e| e.downcast_ref::<DeviceError>());
+    serde_json::json!({
+        "error": {
+            "code": code,
+            "message": format!("{error:#}"),
+            "causes": error.chain().map(|c| c.to_string()).collect::<Vec<_>>(),
+            "device": context.map(|c| &c.device),
+            "report_id": context.and_then(|c| c.report_id),
+        }
+    })
+}
+
+/// Parses a duration like "2s", "500ms" or "1m". A plain number is in seconds.
+fn parse_duration(s: &str) -> Result<Duration> {
+    let (number, unit) = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .map_or((s, "s"), |idx| s.split_at(idx));
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration '{s}'"))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        _ => bail!("Invalid duration unit '{unit}', must be one of ms, s, m"),
+    };
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// Redirects stdout into a temporary file next to the --output file that
+/// atomically replaces the --output file in [OutputFile::commit]. If
+/// dropped without a commit, the temporary file is removed.
+struct OutputFile {
+    path: PathBuf,
+    tmp: PathBuf,
+    committed: bool,
+}
+
+/// Returns the temporary file next to the file that atomically replaces it
+fn temporary_path(path: &Path) -> Result<PathBuf> {
+    let name = path.file_name().context("Invalid output file name")?;
+    Ok(path.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    )))
+}
+
+/// Writes the file through a temporary file that atomically replaces it,
+/// an existing file is left untouched on failure
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp = temporary_path(path)?;
+    let result = std::fs::File::create(&tmp)
+        .and_then(|mut file| {
+            std::io::Write::write_all(&mut file, contents)?;
+            file.sync_all()
+        })
+        .with_context(|| format!("Unable to write {}", tmp.display()))
+        .and_then(|_| {
+            std::fs::rename(&tmp, path)
+                .with_context(|| format!("Unable to replace {}", path.display()))
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+impl OutputFile {
+    #[cfg(not(unix))]
+    fn redirect(_path: &Path) -> Result<OutputFile> {
+        bail!("--output is not available on this platform, redirect stdout instead")
+    }
+
+    #[cfg(unix)]
+    fn redirect(path: &Path) -> Result<OutputFile> {
+        let tmp = temporary_path(path)?;
+        let file = std::fs::File::create(&tmp)
+            .with_context(|| format!("Unable to create {}", tmp.display()))?;
+        let output = OutputFile {
+            path: path.to_path_buf(),
+            tmp,
+            committed: false,
+        };
+        std::io::Write::flush(&mut std::io::stdout())?;
+        if unsafe { libc::dup2(file.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+            return Err(std::io::Error::last_os_error()).context("Unable to redirect stdout");
+        }
+        Ok(output)
+    }
+
+    fn commit(mut self) -> Result<()> {
+        std::io::Write::flush(&mut std::io::stdout())?;
+        #[cfg(unix)]
+        if unsafe { libc::fsync(libc::STDOUT_FILENO) } < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Unable to write {}", self.tmp.display()));
+        }
+        std::fs::rename(&self.tmp, &self.path)
+            .with_context(|| format!("Unable to replace {}", self.path.display()))?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.tmp);
+        }
+    }
 }
 
 #[allow(unused)]
 enum Styles {
     None,
     Header,
+    Success,
+    Failure,
+    Changed,
 }
 
 impl Styles {
@@ -28,6 +243,9 @@ impl Styles {
         match self {
             Styles::None => Style::new(),
             Styles::Header => Style::new().bold(),
+            Styles::Success => Style::new().green(),
+            Styles::Failure => Style::new().red().bold(),
+            Styles::Changed => Style::new().yellow().bold(),
         }
     }
 }
@@ -48,316 +266,6783 @@ macro_rules! cprint {
     }};
 }
 
+/// The device access, see --backend
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum BackendKind {
+    /// The kernel's /dev/hidraw nodes, Linux only
+    Hidraw,
+    /// hidapi's device paths
+    Hidapi,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    /// Print debugging information
+    /// Print debugging information.
+    ///
+    /// In the 'list' table, the report descriptor items that declare
+    /// each field are printed before the field.
     #[arg(short, long, default_value_t = false)]
     debug: bool,
 
     #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
     color: ColorChoice,
 
-    #[command(subcommand)]
-    command: Commands,
-}
-
-#[derive(Subcommand, Debug)]
-enum Commands {
-    /// List available hidraw devices
-    ListDevices {},
-    /// List available Feature Reports on a device.
-    ///
-    /// The output lists the Report ID (see --report-id), the Feature's usage,
-    /// the number of bits and their position in the report as well as the logical
-    /// value range and the Report Count for the respective field.
+    /// Do not access sysfs, use ioctls on the device node only.
     ///
-    /// A Report ID of -1 indicates the report has no ID.
+    /// This is useful in containers or sandboxes (e.g. Flatpak) where only
+    /// the /dev/hidraw node is available. In this mode, the USB port path
+    /// of a device is not available.
+    #[arg(long, default_value_t = false)]
+    no_sysfs: bool,
+
+    /// Restrict the process to the target device once it has been resolved.
     ///
-    /// If the device can be opened, the current values for each feature report
-    /// are fetched from the device and printed, together with the full byte(s) at
-    /// the field's position.
+    /// Filesystem access is restricted to the device node and sysfs (using
+    /// Landlock) and ioctls to those for hidraw devices (using seccomp).
+    /// This is recommended when running as root. Requires Linux 5.13 or later.
+    #[arg(long, default_value_t = false)]
+    sandbox: bool,
+
+    /// Fail feature report requests that take longer than this, e.g. "2s".
     ///
-    /// The byte value can be used with the 'set' command provided by this tool.
-    List {
-        /// Filter by the given Report ID
-        #[arg(long)]
-        report_id: Option<u8>,
+    /// Some (mostly Bluetooth) devices never respond to some requests,
+    /// without a timeout hid-feature waits forever.
+    #[arg(long, value_parser = parse_duration)]
+    ioctl_timeout: Option<Duration>,
 
-        /// Path to the /dev/hidraw node
-        path: PathBuf,
-    },
+    /// Abort the command once it has run for this long, e.g. "30s".
+    ///
+    /// This includes waiting for devices, retries and polling. On expiry,
+    /// hid-feature exits with status 124, like timeout(1).
+    #[arg(long, value_parser = parse_duration)]
+    timeout: Option<Duration>,
 
-    Set {
-        /// Path to the /dev/hidraw node
-        path: PathBuf,
+    /// Send feature report requests through the privileged helper.
+    ///
+    /// Only the helper needs access to the /dev/hidraw nodes (e.g. as setuid
+    /// binary or via polkit), hid-feature itself can run unprivileged. The
+    /// value is the command to run the helper, e.g.
+    /// --helper="pkexec /usr/libexec/hid-feature-helper".
+    #[arg(long, num_args = 0..=1, default_missing_value = "hid-feature-helper", require_equals = true)]
+    helper: Option<String>,
 
-        /// Specifies the Report ID
-        ///
-        /// If the device uses Report IDs and has more
-        /// than one Feature Report, this option is required.
-        #[arg(long)]
-        report_id: Option<u8>,
+    /// How to access the devices, by default hidraw on Linux and hidapi
+    /// elsewhere.
+    ///
+    /// hidapi requires hid-feature to be built with the hidapi feature.
+    /// Its device paths are those listed by 'list-devices', e.g. a
+    /// "\\?\HID#..." path on Windows. --helper and --sandbox require
+    /// hidraw.
+    #[arg(long, value_enum)]
+    backend: Option<BackendKind>,
 
-        /// Sets the offset (in bytes) for the byte argument.
-        #[arg(long, default_value_t = 0)]
-        offset: usize,
+    /// The configuration file to use instead of
+    /// $XDG_CONFIG_HOME/hid-feature/config.json
+    #[arg(long)]
+    config: Option<PathBuf>,
 
-        /// The set of bytes in hexadecimal values to set for this report.
-        ///
-        /// Values may be literal 'xx' or a hexadecimal 1-byte value
-        /// without a 0x prefix (e.g. "0a"). Any 'xx' is ignored
-        /// all other values overwrite the fetched value
-        /// from the report.
-        ///
-        /// For example:
-        ///    hid-feature set xx xx 4a xx 6c
-        /// set the third and fifth byte only. The same behaviour
-        /// be achieved with an offset:
-        ///    hid-feature set --offset=2 4a xx 6c
-        ///
-        /// The values exclude the Report ID, use --report-id if required.
-        bytes: Vec<String>,
-    },
-}
+    /// Write the output to this file instead of stdout, without colors.
+    ///
+    /// The file is only replaced once the command succeeded, a failing
+    /// command leaves an existing file untouched.
+    #[arg(long)]
+    output: Option<PathBuf>,
 
-fn hidraw_name(file: &String) -> Result<String> {
-    let uevent_path = PathBuf::from(format!("/sys/class/hidraw/{}/device/uevent", file));
-    let uevent = std::fs::read_to_string(uevent_path)?;
-    let name = uevent
-        .lines()
-        .find(|l| l.starts_with("HID_NAME"))
-        .context("Unable to find HID_NAME in uevent")?;
-    let (_, name) = name
-        .split_once('=')
-        .context("Unexpected HID_NAME= format")?;
-    Ok(name.to_string())
+    #[command(subcommand)]
+    command: Commands,
 }
 
-fn list_devices() -> Result<()> {
-    println!("Available HID devices:");
+#[derive(Args, Debug)]
+struct DeviceArgs {
+    /// Path to the /dev/hidraw node.
+    ///
+    /// For 'list' and 'set' the path may be followed by ':' and the usage
+    /// of an Application Collection to address the reports of one
+    /// top-level collection, e.g. "/dev/hidraw3:Consumer Control". This is
+    /// the same as --collection.
+    path: Option<PathBuf>,
 
-    let mut hidraws: Vec<String> = std::fs::read_dir("/dev/")?
-        .flatten()
-        .flat_map(|f| f.file_name().into_string())
-        .filter(|name| name.starts_with("hidraw"))
-        .collect();
+    /// Select the device by its USB port path instead of the hidraw node.
+    ///
+    /// The port path is the one shown by 'list-devices', e.g. "1-3.2" or,
+    /// to select one specific interface of the device, "1-3.2:1.0".
+    #[arg(long)]
+    port: Option<String>,
 
-    hidraws.sort_by(|a, b| human_sort::compare(a, b));
-    for path in hidraws.iter() {
-        let name = hidraw_name(path)?;
-        println!("{path:13} - {name}");
-    }
-    Ok(())
-}
+    /// Select the device by its identity instead of the hidraw node, e.g.
+    /// "046d:c52b", "046d:c52b:<serial>" or "rdesc:<fingerprint>".
+    ///
+    /// Unlike the hidraw node, the identity does not change across
+    /// reboots. If more than one device matches, e.g. the interfaces of a
+    /// receiver, --index selects one of them.
+    #[arg(long = "device", conflicts_with = "port")]
+    identity: Option<DeviceIdentity>,
 
-fn report_descriptor(path: &Path) -> Result<ReportDescriptor> {
-    let filename = path.file_name().unwrap().to_string_lossy();
-    let rdesc_path = PathBuf::from(format!(
-        "/sys/class/hidraw/{filename}/device/report_descriptor"
-    ));
+    /// Select the device by its name instead of the hidraw node, e.g.
+    /// "Logitech*Receiver". The pattern is matched case-insensitively,
+    /// see 'list-devices --name'.
+    #[arg(long, conflicts_with_all = ["port", "identity"])]
+    name: Option<String>,
 
-    let bytes = std::fs::read(rdesc_path)?;
-    Ok(ReportDescriptor::try_from(&bytes)?)
+    /// Use the device with this index (starting at 0, in hidraw order) if
+    /// more than one device matches the --device or --name
+    #[arg(long, conflicts_with = "port")]
+    index: Option<usize>,
 }
 
-fn list(path: &Path, filter_id: &Option<u8>) -> Result<()> {
-    let rdesc = report_descriptor(path)?;
+impl DeviceArgs {
+    fn has_selector(&self) -> bool {
+        self.port.is_some() || self.identity.is_some() || self.name.is_some()
+    }
 
-    let reports = rdesc.feature_reports();
-    if reports.is_empty() {
-        println!("This device does not have any Feature Reports");
-        return Ok(());
+    /// Removes a ":collection" suffix from the path and returns the
+    /// collection, see [DeviceArgs::path]
+    fn take_collection(&mut self) -> Option<String> {
+        let path = self.path.as_ref()?;
+        if path.exists() {
+            return None;
+        }
+        // hidapi paths are not filesystem paths and may contain ':',
+        // e.g. "DevSrvsID:4294971173" on macOS
+        if !cfg!(target_os = "linux") && path_device(path).is_some() {
+            return None;
+        }
+        let (node, collection) = path.to_str()?.rsplit_once(':')?;
+        let collection = collection.to_string();
+        self.path = Some(PathBuf::from(node));
+        Some(collection)
     }
-    let usage_header = format!("{:^48}", "Usage");
-    let headers: Vec<&str> = vec![
-        "Report",
-        usage_header.as_str(),
-        "Bits",
-        "Bit Range",
-        "Value Range",
-        "Count",
-        "Value",
-        "Bytes",
-    ];
 
-    cprintln!(Styles::Header, "{}", headers.join(" ┃ "));
-    cprintln!(
-        Styles::Header,
-        "{}",
-        headers
-            .iter()
-            .map(|h| str::repeat("━", h.len()))
-            .collect::<Vec<String>>()
-            .join("━╇━")
-    );
+    /// Merges a collection given with the path (see
+    /// [DeviceArgs::take_collection]) with the one given by --collection
+    fn merge_collection(&mut self, collection: Option<String>) -> Result<Option<String>> {
+        match (self.take_collection(), collection) {
+            (Some(_), Some(_)) => {
+                bail!("Cannot combine a device:collection path with --collection")
+            }
+            (from_path, from_option) => Ok(from_path.or(from_option)),
+        }
+    }
 
-    for report in reports {
-        let report_id: u8 = match report.report_id() {
-            None => 0xff,
-            Some(id) => u8::from(id),
-        };
-        if let Some(filter_id) = filter_id {
-            if report_id == *filter_id {
-                continue;
+    fn resolve(&self) -> Result<PathBuf> {
+        if let Some(port) = &self.port {
+            if let Some(path) = &self.path {
+                bail!("Cannot combine {} with --port", path.display());
             }
+            let candidates = DeviceIdentity::Port(port.clone()).resolve()?;
+            return match candidates.as_slice() {
+                [device] => Ok(device.path.clone()),
+                _ => bail!(
+                    "Multiple hidraw devices found at port {port}, use one of: {}",
+                    candidates
+                        .iter()
+                        .map(|d| d.port.as_deref().unwrap_or_default())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                ),
+            };
         }
 
-        // Our report's length only includes the report ID if there is one but the ioctl
-        // always needs the first byte to be the report ID.
-        //
-        // The return value is properly sized, the report ID is not returned.
-        let report_size = report.size_in_bytes();
-        let fetch_size = match report.report_id() {
-            Some(_) => report_size,
-            None => report_size + 1,
+        let (what, devices) = match (&self.identity, &self.name) {
+            (Some(identity), _) => (identity.to_string(), identity.resolve()?),
+            (None, Some(pattern)) => {
+                let devices: Vec<HidrawDevice> = backend()
+                    .devices()?
+                    .into_iter()
+                    .filter(|d| glob_matches(pattern, &d.name))
+                    .collect();
+                if devices.is_empty() {
+                    bail!("No hidraw device found with a name matching '{pattern}'");
+                }
+                (format!("name '{pattern}'"), devices)
+            }
+            (None, None) if self.index.is_some() => bail!("--index requires --device or --name"),
+            (None, None) => {
+                return self
+                    .path
+                    .clone()
+                    .context("A path to the /dev/hidraw node is required")
+            }
         };
-        let rid = report.report_id().map_or(0, u8::from);
-        let mut device = hidraw::Device::open(path)?;
-        let r = unsafe { device.get_feature_report_with_size::<FeatureReport>(rid, fetch_size) }?;
-        let values = r[..report_size].to_vec();
-        for field in report.fields() {
-            let min: i32;
-            let max: u32;
-            let count: usize;
-            let hutstr: String;
-
-            let offset = field.bits().start / 8;
-            let end = (field.bits().end - 1) / 8;
-
-            let value: i32;
+        if let Some(path) = &self.path {
+            bail!("Cannot combine {} with --device or --name", path.display());
+        }
+        match (self.index, devices.as_slice()) {
+            (Some(index), _) => devices.get(index).map(|d| d.path.clone()).with_context(|| {
+                format!(
+                    "Only {} hidraw device(s) found for {what}, there is no index {index}",
+                    devices.len()
+                )
+            }),
+            (None, [device]) => Ok(device.path.clone()),
+            (None, _) => bail!(
+                "Multiple hidraw devices found for {what}, use --index with one of: {}",
+                devices
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, d)| format!("{idx} ({})", d.path.display()))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
 
-            match field {
-                Field::Variable(var) => {
-                    min = i32::from(var.logical_minimum);
-                    max = i32::from(var.logical_maximum) as u32;
-                    count = 1;
-                    value = var.extract(&values)?.into();
-                    hutstr = match hut::Usage::new_from_page_and_id(
-                        u16::from(var.usage.usage_page),
-                        u16::from(var.usage.usage_id),
-                    ) {
-                        Err(_) => "<unknown>".into(),
-                        Ok(u) => format!("{} / {}", hut::UsagePage::from(&u), u),
-                    };
-                }
-                Field::Array(arr) => {
-                    min = i32::from(arr.logical_minimum);
-                    max = i32::from(arr.logical_maximum) as u32;
-                    count = usize::from(arr.report_count);
-                    value = arr.extract_one(&values, 0)?.into();
-                    hutstr = "<not implemented>".into();
-                }
-                _ => continue,
-            };
+    /// Like [DeviceArgs::resolve] but if no device was selected and the
+    /// command runs in a terminal, the user picks one, see [pick_device]
+    fn resolve_or_pick(&self) -> Result<PathBuf> {
+        use std::io::IsTerminal;
 
-            println!(
-                "{:^6} │ {hutstr:48} │ {:^4} │ {:3}..={:<3} │ {min:4}..={max:<4} │ {count:^5} │ {value:5} │ {}",
-                report_id as i8,
-                field.bits().end - field.bits().start,
-                field.bits().start,
-                field.bits().end - 1,
-                print_bytes(&values[offset..=end])
-            );
+        let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+        if self.path.is_none() && !self.has_selector() && interactive {
+            pick_device()
+        } else {
+            self.resolve()
         }
     }
 
-    Ok(())
+    /// Returns the selected device or, if no device was selected, all hidraw devices
+    fn resolve_or_all(&self) -> Result<Vec<PathBuf>> {
+        if self.path.is_none() && !self.has_selector() {
+            Ok(backend().devices()?.into_iter().map(|d| d.path).collect())
+        } else {
+            Ok(vec![self.resolve()?])
+        }
+    }
 }
 
-fn set(path: &Path, filter_id: &Option<u8>, bytes: &[String], offset: usize) -> Result<()> {
-    let rdesc = report_descriptor(path)?;
+/// Returns true if all characters of the pattern appear in the text in
+/// the same order, ignoring case, e.g. "logrec" matches "Logitech Receiver"
+fn fuzzy_matches(pattern: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    pattern
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+        .all(|c| text.any(|t| t == c))
+}
 
-    let reports = rdesc.feature_reports();
-    if reports.is_empty() {
-        bail!("This device does not have any Feature Reports");
+/// Lets the user pick a hidraw device by its number in the list or by
+/// searching for its path, VID:PID or name. The list is printed to stderr
+/// so the command's output can still be redirected.
+fn pick_device() -> Result<PathBuf> {
+    let all = backend().devices()?;
+    if all.is_empty() {
+        bail!("No hidraw devices found");
     }
-
-    for v in bytes.iter().filter(|v| v != &"xx") {
-        u8::from_str_radix(v, 16).context("Invalid value, must be 'xx' or 1-byte hex")?;
+    let describe = |d: &HidrawDevice| {
+        format!(
+            "{}  {:04x}:{:04x}  {}",
+            d.path.display(),
+            d.vid,
+            d.pid,
+            d.name
+        )
+    };
+    let mut devices: Vec<&HidrawDevice> = all.iter().collect();
+    loop {
+        for (idx, device) in devices.iter().enumerate() {
+            eprintln!("{:>3}) {}", idx + 1, describe(device));
+        }
+        eprint!("Select a device (number or search, empty to cancel): ");
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 || line.trim().is_empty() {
+            bail!("No device selected");
+        }
+        let line = line.trim();
+        let picked = line
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| devices.get(n.wrapping_sub(1)));
+        if let Some(device) = picked {
+            return Ok(device.path.clone());
+        }
+        let matches: Vec<&HidrawDevice> = all
+            .iter()
+            .filter(|d| fuzzy_matches(line, &describe(d)))
+            .collect();
+        match matches.as_slice() {
+            [device] => return Ok(device.path.clone()),
+            [] => eprintln!("No device matches '{line}'"),
+            _ => devices = matches,
+        }
     }
+}
 
-    let report = match filter_id {
-        Some(id) => reports
-            .iter()
-            .find(|r| u8::from(r.report_id().unwrap()) == *id)
-            .context("Unable to find report {id}")?,
-        None => reports.first().unwrap(),
-    };
+/// Selects the Feature Report to write to
+#[derive(Args, Debug)]
+struct ReportSelection {
+    /// Specifies the Report ID
+    ///
+    /// If the device has more than one Feature Report, this option
+    /// (or --collection or --first) is required.
+    ///
+    /// Defaults to the device's report_id in the configuration file, if any.
+    #[arg(long)]
+    report_id: Option<u8>,
 
-    // ioctl uses 0 for Report ID None
-    let rid = report.report_id().map_or(0, u8::from);
+    /// Select the report by the usage of its Application Collection,
+    /// e.g. "Digitizers / Device Configuration" or "Device Configuration".
+    ///
+    /// Unlike the Report ID, the collection usage usually remains the same
+    /// across firmware revisions. The usage may contain wildcards, e.g.
+    /// "*Configuration".
+    #[arg(long)]
+    collection: Option<String>,
 
-    // Our report's length only includes the report ID if there is one but the ioctl
-    // always needs the first byte to be the report ID.
-    //
-    // The return value is properly sized, the report ID is not returned.
-    let report_size = report.size_in_bytes();
-    let fetch_size = match report.report_id() {
-        Some(_) => report_size,
-        None => report_size + 1,
-    };
-    let mut device = hidraw::Device::open(path)?;
-    let r = unsafe { device.get_feature_report_with_size::<[u8; 20]>(rid, fetch_size) }?;
+    /// Use the first matching Feature Report if there is more than one
+    #[arg(long)]
+    first: bool,
+}
 
-    // prepend the report ID again if need be
-    let mut values: FeatureReport = [0; 1024];
-    let rid_off = match report.report_id() {
-        Some(_) => 0,
-        None => {
-            values[0] = rid;
-            1
-        }
-    };
-    for (i, v) in r[0..report_size].iter().enumerate() {
-        values[i + rid_off] = *v;
+impl ReportSelection {
+    fn matches<R: Report>(&self, report: &R) -> bool {
+        self.report_id
+            .is_none_or(|id| report.report_id().map(u8::from) == Some(id))
+            && self
+                .collection
+                .as_ref()
+                .is_none_or(|name| report_in_collection(report, name))
     }
 
-    for (i, val) in bytes.iter().enumerate() {
-        let idx = offset + rid_off + i;
-        if val != "xx" {
-            values[idx] = u8::from_str_radix(val, 16)?;
-        } else {
-            values[idx] = r[i];
+    /// Returns the one report matching this selection
+    fn select<'a, R: Report>(&self, reports: &'a [R]) -> Result<&'a R> {
+        let candidates: Vec<&R> = reports.iter().filter(|r| self.matches(*r)).collect();
+        match candidates.as_slice() {
+            [] => match (&self.report_id, &self.collection) {
+                (Some(id), None) => bail!("Unable to find report {id}"),
+                (None, Some(name)) => bail!("Unable to find a report in collection '{name}'"),
+                (Some(id), Some(name)) => {
+                    bail!("Unable to find report {id} in collection '{name}'")
+                }
+                (None, None) => bail!("This device does not have any Feature Reports"),
+            },
+            [report] => Ok(report),
+            [report, ..] if self.first => Ok(report),
+            _ => bail!(
+                "Multiple reports match, use --report-id and/or --collection with one of {} or --first",
+                candidates
+                    .iter()
+                    .map(|r| {
+                        let id = r.report_id().map_or(0, u8::from);
+                        match report_collection(*r) {
+                            Some(collection) => format!("{id} ({collection})"),
+                            None => id.to_string(),
+                        }
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
         }
     }
+}
 
-    unsafe { device.send_feature_report_with_size::<FeatureReport>(&values, fetch_size) }?;
+/// A Report ID or an inclusive range of Report IDs, e.g. "4" or "4-9"
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ReportIdRange {
+    first: u8,
+    last: u8,
+}
 
-    Ok(())
+impl ReportIdRange {
+    fn contains(&self, report_id: u8) -> bool {
+        (self.first..=self.last).contains(&report_id)
+    }
 }
 
-fn hid_feature() -> Result<()> {
-    let cli = Cli::parse();
+impl std::str::FromStr for ReportIdRange {
+    type Err = anyhow::Error;
 
-    // Bit lame but easier to just set the env for owo_colors to figure out the rest
-    unsafe {
-        match cli.color {
-            ColorChoice::Never => std::env::set_var("NO_COLOR", "1"),
-            ColorChoice::Auto => {}
-            ColorChoice::Always => std::env::set_var("FORCE_COLOR", "1"),
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<u8>()
+                .with_context(|| format!("Invalid Report ID '{v}'"))
+        };
+        let (first, last) = match s.split_once('-') {
+            Some((first, last)) => (parse(first)?, parse(last)?),
+            None => (parse(s)?, parse(s)?),
+        };
+        if first > last {
+            bail!("Invalid Report ID range '{s}'");
         }
+        Ok(ReportIdRange { first, last })
     }
+}
 
-    match cli.command {
-        Commands::ListDevices {} => list_devices(),
-        Commands::List { report_id, path } => list(&path, &report_id),
-        Commands::Set {
-            report_id,
-            bytes,
-            path,
-            offset,
-        } => set(&path, &report_id, &bytes, offset),
+/// Selects reports by their Report ID
+#[derive(Args, Debug)]
+struct ReportFilter {
+    /// Only list the given Report ID or range of Report IDs, e.g. "4" or
+    /// "4-9". May be given multiple times. Use 0 for reports without a
+    /// Report ID.
+    #[arg(long = "report-id", value_delimiter = ',')]
+    include: Vec<ReportIdRange>,
+
+    /// Do not list the given Report ID or range of Report IDs. May be given
+    /// multiple times.
+    #[arg(long = "exclude-report-id", value_delimiter = ',')]
+    exclude: Vec<ReportIdRange>,
+}
+
+impl ReportFilter {
+    /// Returns true if the report with this Report ID (None for reports
+    /// without ID) is included by the filter
+    fn matches(&self, report_id: Option<u8>) -> bool {
+        let id = report_id.unwrap_or(0);
+        (self.include.is_empty() || self.include.iter().any(|r| r.contains(id)))
+            && !self.exclude.iter().any(|r| r.contains(id))
     }
 }
 
-fn main() -> ExitCode {
-    let rc = hid_feature();
-    match rc {
-        Ok(_) => ExitCode::SUCCESS,
-        Err(e) => {
-            eprintln!("Error: {e:#}");
-            ExitCode::FAILURE
-        }
+/// How `list` prints the fields
+#[derive(Args, Debug)]
+struct ListOutput {
+    /// The columns to print, in the given order
+    ///
+    /// By default, all columns except "collection" are printed. The
+    /// "collection" column is included if the device uses the same
+    /// Report ID in multiple collections.
+    #[arg(long, value_delimiter = ',')]
+    columns: Option<Vec<Column>>,
+
+    /// Print each field according to the given template instead of a table.
+    ///
+    /// The template may contain the placeholders {report_id}, {collection},
+    /// {usage_name}, {bits}, {bit_start}, {bit_end}, {byte_offset},
+    /// {bit_offset}, {logical_minimum}, {logical_maximum}, {count}, {value}
+    /// and {bytes}. Use {{ and }} for literal braces.
+    ///
+    /// For example:
+    ///    hid-feature list --template '{report_id}:{usage_name}={value}'
+    #[arg(long, conflicts_with = "columns")]
+    template: Option<String>,
+
+    /// The output format.
+    ///
+    /// With "env", each field is printed as a HID_<USAGE>=<value> line
+    /// suitable for `eval` in a shell script, e.g.
+    ///    eval "$(hid-feature list --format env /dev/hidraw0)"
+    ///
+    /// With "json", the reports and their fields are printed as a single
+    /// JSON object. Values are always decimal numbers.
+    #[arg(long, value_enum, default_value_t = ListFormat::Table, conflicts_with = "template")]
+    format: ListFormat,
+
+    /// Print each field in a stable, versioned format for scripts that is
+    /// not affected by --radix, --bit-order or --columns, see the README.
+    /// The only version is "v1".
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "v1", conflicts_with_all = ["template", "format", "print0"])]
+    porcelain: Option<PorcelainVersion>,
+
+    /// The radix of the field values
+    #[arg(long, value_enum, default_value_t = Radix::Dec)]
+    radix: Radix,
+
+    /// Print one line per report with its size and number of fields
+    /// instead of the fields. The device is not accessed.
+    #[arg(long, conflicts_with_all = ["columns", "template", "count_only"])]
+    reports_only: bool,
+
+    /// Only print the number of fields. The device is not accessed.
+    #[arg(long, conflicts_with_all = ["columns", "template"])]
+    count_only: bool,
+
+    /// The bit numbering used for the bit ranges
+    #[arg(long, value_enum, default_value_t = BitOrder::Lsb0)]
+    bit_order: BitOrder,
+
+    /// Terminate each field with a NUL character instead of a newline
+    /// and omit the table header, for use with e.g. `xargs -0`.
+    #[arg(short = '0', long)]
+    print0: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// List available hidraw devices
+    ///
+    /// By default, devices are sorted by their hidraw node number. Devices
+    /// that compare equal with the selected --sort order are sorted by
+    /// their hidraw node number.
+    ListDevices {
+        /// The order to sort the devices by
+        #[arg(long, value_enum, default_value_t = DeviceSort::Node)]
+        sort: DeviceSort,
+
+        /// Also print each device's physical location and unique identifier
+        #[arg(short, long)]
+        verbose: bool,
+
+        /// Terminate each device with a NUL character instead of a newline
+        /// and omit the header, for use with e.g. `xargs -0`.
+        #[arg(short = '0', long)]
+        print0: bool,
+
+        /// Only list devices whose name matches this case-insensitive
+        /// pattern, e.g. "*Logitech*". '*' matches any number of characters,
+        /// '?' exactly one.
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Group the hidraw nodes by physical device (e.g. the interfaces of
+        /// a USB device) and print each node's top-level Application
+        /// Collections, see 'list --collection'.
+        #[arg(short, long)]
+        group: bool,
+
+        /// The output format. "json" prints an array of objects with the
+        /// node, name, bus, vendor and product ID, phys, uniq and port of
+        /// each device.
+        #[arg(long, value_enum, default_value_t = DeviceListFormat::Table, conflicts_with_all = ["group", "print0"])]
+        format: DeviceListFormat,
+
+        /// Print each device in a stable, versioned format for scripts,
+        /// see the README. The only version is "v1".
+        #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "v1", conflicts_with_all = ["group", "print0", "format", "verbose"])]
+        porcelain: Option<PorcelainVersion>,
+    },
+    /// List available Feature Reports on a device.
+    ///
+    /// The output lists the Report ID (see --report-id), the Feature's usage,
+    /// the number of bits and their position in the report as well as the logical
+    /// value range and the Report Count for the respective field.
+    ///
+    /// A Report ID of "-" indicates the report has no ID.
+    ///
+    /// Each slot of an Array field is listed as a row of its own with its
+    /// own bit range, the usage shown is the one currently selected by the
+    /// slot's value.
+    ///
+    /// If the device can be opened, the current values for each feature report
+    /// are fetched from the device and printed, together with the full byte(s) at
+    /// the field's position.
+    ///
+    /// The byte value can be used with the 'set' command provided by this tool.
+    List {
+        /// The type of the reports to list. The current values of Input
+        /// and Output Reports are fetched with the HIDIOCGINPUT and
+        /// HIDIOCGOUTPUT ioctls which not all devices support.
+        #[arg(long, default_value_t = ReportType::Feature)]
+        report_type: ReportType,
+
+        #[command(flatten)]
+        filter: ReportFilter,
+
+        /// Only list reports in the Application Collection with this usage,
+        /// see 'set --collection'
+        #[arg(long)]
+        collection: Option<String>,
+
+        /// Only list fields with this usage, e.g. "Battery Strength" or,
+        /// with wildcards, "Battery*"
+        #[arg(long)]
+        usage: Option<String>,
+
+        #[command(flatten)]
+        output: ListOutput,
+
+        /// List the reports of this report descriptor instead of a
+        /// device's, e.g. from a bug report. The file contains the binary
+        /// descriptor, its bytes in hex ("05 01 09 02 ...") or an
+        /// "R: <size> <bytes>" line as written by 'record' or
+        /// hid-recorder. Use "-" for stdin. No device is accessed, so
+        /// there are no values.
+        #[arg(long, conflicts_with_all = ["path", "port", "identity", "name"])]
+        rdesc_file: Option<PathBuf>,
+
+        /// Only list the layout from the report descriptor, without
+        /// opening the device to fetch the current values, e.g. for a
+        /// device that cannot be opened or a slow Bluetooth device.
+        /// Without sysfs, the device is still opened to read the report
+        /// descriptor.
+        ///
+        /// Defaults to the device's "values" in the configuration file.
+        #[arg(long, overrides_with = "values")]
+        no_values: bool,
+
+        /// Fetch the current values, overriding --no-values or the
+        /// configuration file
+        #[arg(long, overrides_with = "no_values")]
+        values: bool,
+
+        #[command(flatten)]
+        device: DeviceArgs,
+    },
+
+    /// Print the fields of a Feature Report from bytes captured elsewhere.
+    ///
+    /// The bytes, e.g. from a logic analyzer or hid-recorder, are
+    /// interpreted against the device's report descriptor and printed like
+    /// the device's current values in 'list', e.g.:
+    ///    $ hid-feature decode --report-id=4 /dev/hidraw3 ab 12 cd 34
+    ///
+    /// The bytes may or may not start with the Report ID. The device is
+    /// not accessed except for its report descriptor.
+    Decode {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        /// The Report ID of the bytes, omit for a device without Report IDs
+        #[arg(long)]
+        report_id: Option<u8>,
+
+        /// Only print fields with this usage, see 'list --usage'
+        #[arg(long)]
+        usage: Option<String>,
+
+        #[command(flatten)]
+        output: ListOutput,
+
+        /// Use this report descriptor instead of a device's, see
+        /// 'list --rdesc-file'
+        #[arg(long, conflicts_with_all = ["port", "identity", "name"])]
+        rdesc_file: Option<PathBuf>,
+
+        /// The bytes of the report in hexadecimal, e.g. "ab 12 cd 34".
+        ///
+        /// Like for 'set', a byte may also be given with a 0x, 0b, 0o or d:
+        /// prefix, e.g. "0x4a", "0b01001010", "0o112" or "d:74", and a
+        /// value may be repeated with "*count", e.g. "00*8".
+        bytes: Vec<String>,
+    },
+
+    Set {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        #[command(flatten)]
+        report: ReportSelection,
+
+        /// The type of the report, see 'list --report-type'. The current
+        /// Output or Input Report is read with HIDIOCGOUTPUT or HIDIOCGINPUT
+        /// and written back with HIDIOCSOUTPUT or HIDIOCSINPUT.
+        #[arg(long, default_value_t = ReportType::Feature, conflicts_with = "usage")]
+        report_type: ReportType,
+
+        /// Sets the offset (in bytes) for the byte argument.
+        ///
+        /// Defaults to the device's offset in the configuration file or 0.
+        #[arg(long)]
+        offset: Option<usize>,
+
+        /// How long to wait for the device to return if it disconnects
+        /// before the report is written, e.g. "5s".
+        ///
+        /// If the device disconnects (e.g. a Bluetooth disconnect or a USB
+        /// reset) after the report was read, it is reopened, the report
+        /// is read again and the write is retried once.
+        #[arg(long, value_parser = parse_duration, default_value = "0s")]
+        retry_wait: Duration,
+
+        /// Sets the active usages of an Array field, e.g.
+        /// --select "Num Lock,Caps Lock".
+        ///
+        /// The usages are looked up in the Array fields of the selected
+        /// report and the array slots are filled with their index, all
+        /// remaining slots are cleared. This is applied after any bytes.
+        #[arg(long, value_delimiter = ',')]
+        select: Vec<String>,
+
+        /// Sets a field by usage name to a value or an expression, e.g.
+        /// --field "DPI=cur*2".
+        ///
+        /// 'cur' is the field's current logical value as read from the
+        /// device. The result must be within the field's logical range.
+        /// This is applied after any bytes and --select.
+        ///
+        /// An expression consists of decimal, hexadecimal (0x) and binary
+        /// (0b) numbers, 'cur', parentheses, the binary operators
+        /// "| ^ & << >> + - * / %" (from lowest to highest precedence) and
+        /// the unary operators "-" and "~". The arithmetic is on 64-bit
+        /// signed integers, an overflow or a division by zero is an error.
+        #[arg(long)]
+        field: Vec<String>,
+
+        /// Sets a byte to a value or an expression, e.g. --byte "3=cur|0x04".
+        ///
+        /// The byte index counts like --offset, 'cur' is the byte's current
+        /// value. This is applied after any bytes and --select.
+        #[arg(long)]
+        byte: Vec<String>,
+
+        /// Sets a 16-bit value starting at a byte, e.g. --u16 "2=0x1f40".
+        ///
+        /// Like --byte, the index counts like --offset and the value may be
+        /// an expression with 'cur'. The bytes are in the --byte-order.
+        #[arg(long)]
+        u16: Vec<String>,
+
+        /// Sets a 32-bit value starting at a byte, see --u16
+        #[arg(long)]
+        u32: Vec<String>,
+
+        /// The byte order of --u16 and --u32 values
+        #[arg(long, value_enum, default_value_t = ByteOrder::Little)]
+        byte_order: ByteOrder,
+
+        /// Write only the bits starting at this bit instead of bytes, e.g.
+        /// --bit-offset=18 --bit-length=1 1.
+        ///
+        /// The bits count like the bit ranges printed by 'list', the only
+        /// positional value is the value of the bits. Like --byte, it is
+        /// decimal unless prefixed and may be an expression with 'cur'.
+        /// All other bits keep their current value.
+        #[arg(long, conflicts_with_all = ["offset", "radix"])]
+        bit_offset: Option<usize>,
+
+        /// The number of bits written at the --bit-offset
+        #[arg(long, requires = "bit_offset", default_value_t = 1,
+              value_parser = clap::value_parser!(u32).range(1..=32))]
+        bit_length: u32,
+
+        /// Sets the field with this usage to the --value, e.g.
+        /// --usage "Surface Switch" --value 0.
+        ///
+        /// This is the same as --field "Surface Switch=0" but the report is
+        /// the one of the field, see 'set-field'.
+        #[arg(long, requires = "value", conflicts_with_all = ["bytes", "select", "field", "byte", "u16", "u32", "offset", "bit_offset", "from_file"])]
+        usage: Option<String>,
+
+        /// The value or expression for the --usage, see --field
+        #[arg(long, requires = "usage")]
+        value: Option<String>,
+
+        /// Write the --usage on every device that has a Feature field with
+        /// this usage, e.g. for all touchpads of a lab. A summary is printed
+        /// at the end.
+        #[arg(long, requires = "usage", conflicts_with_all = ["path", "port", "identity", "name"])]
+        all_devices: bool,
+
+        /// With --all-devices, write up to N devices at the same time
+        #[arg(long, value_name = "N", default_value_t = 1, requires = "all_devices",
+              value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: u32,
+
+        /// Write the report even if it is write-protected in the
+        /// configuration file
+        #[arg(long)]
+        force: bool,
+
+        /// Read the report first and skip the write if it already has the
+        /// requested values, e.g. for devices that reset or wear their
+        /// flash on every write
+        #[arg(long)]
+        only_changed: bool,
+
+        /// Read and merge the report as usual but only print the bytes
+        /// that would be written, with the changed bytes highlighted,
+        /// instead of writing them. The bytes include the Report ID or,
+        /// for a report without one, the 0 that takes its place.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Read the report back after the write and print the bytes that
+        /// did not stick, e.g. because the device clamped or ignored a
+        /// value. This fails if the report read back differs from the
+        /// report written.
+        #[arg(long, conflicts_with = "dry_run")]
+        verify: bool,
+
+        /// The set of bytes to set for this report.
+        ///
+        /// Values may be literal 'xx' or a 1-byte value, by default
+        /// hexadecimal without a prefix (e.g. "0a"). Any 'xx' is ignored
+        /// all other values overwrite the fetched value
+        /// from the report.
+        ///
+        /// For example:
+        ///    hid-feature set xx xx 4a xx 6c
+        /// set the third and fifth byte only. The same behaviour
+        /// be achieved with an offset:
+        ///    hid-feature set --offset=2 4a xx 6c
+        ///
+        /// A 16-bit or 32-bit value may be given as 4 or 8 hex digits with
+        /// a byte order prefix, "le:" for little-endian and "be:" for
+        /// big-endian. For example, "le:1f40" is the same as "40 1f".
+        ///
+        /// Any value may be repeated with "*count", e.g. "00*60" for 60
+        /// zero bytes or "xx*8" to skip 8 bytes.
+        ///
+        /// A "value/mask" value only changes the bits set in the mask and
+        /// keeps the others, e.g. "80/c0" sets the top two bits to 10.
+        ///
+        /// A value with a 0x, 0b or 0o prefix is hexadecimal, binary or
+        /// octal, one with a d: prefix is decimal, e.g. "0x4a",
+        /// "0b01001010", "0o112" and "d:74" are the same byte. See --radix for values without a
+        /// prefix.
+        ///
+        /// The list starts at the byte given by --offset. Like for
+        /// --from-file, the offset counts the Report ID byte of a report
+        /// that has one, so without an offset the first value is at the
+        /// Report ID's position, typically 'xx'. Use --report-id to select
+        /// the report.
+        bytes: Vec<String>,
+
+        /// The radix of byte values without a prefix, e.g. --radix dec to
+        /// copy decimal values from a datasheet
+        #[arg(long, value_enum, default_value_t = Radix::Hex)]
+        radix: Radix,
+
+        /// Take the bytes from this binary file instead of the byte list,
+        /// e.g. a report saved with 'get --save'. Use "-" for stdin.
+        ///
+        /// Like the byte list, the bytes start at the --offset and include
+        /// the Report ID if the report has one.
+        #[arg(long, conflicts_with_all = ["bytes", "radix", "bit_offset"])]
+        from_file: Option<PathBuf>,
+    },
+
+    /// Send an Output Report, e.g. to devices configured via Output
+    /// Reports rather than Feature Reports.
+    ///
+    /// The bytes, offset and report selection work as in 'set' except that
+    /// Output Reports usually cannot be read back: the report starts out
+    /// as zeroes and an 'xx' leaves the byte at zero. The report is
+    /// written to the hidraw node directly, not via the helper.
+    SendOutput {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        #[command(flatten)]
+        report: ReportSelection,
+
+        /// Sets the offset (in bytes) for the byte argument, see 'set'
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// The bytes in hexadecimal or 'xx', see 'set'
+        bytes: Vec<String>,
+    },
+
+    /// Fetch a Feature Report and print its raw bytes.
+    ///
+    /// The bytes are printed in hexadecimal and include the Report ID if
+    /// the report has one, e.g.:
+    ///    $ hid-feature get --report-id=23 /dev/hidraw2
+    ///    17 01 00
+    Get {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        #[command(flatten)]
+        report: ReportSelection,
+
+        /// The type of the report, see 'list --report-type'
+        #[arg(long, default_value_t = ReportType::Feature)]
+        report_type: ReportType,
+
+        /// Do not print the Report ID
+        #[arg(long)]
+        no_report_id: bool,
+
+        /// Only print the bytes covering this field, preceded by the
+        /// --report-id and --offset to use with 'set', e.g.
+        /// "--report-id=23 --offset=1 01".
+        ///
+        /// The name is matched as in 'edit --field'.
+        #[arg(long, conflicts_with = "no_report_id")]
+        field: Option<String>,
+
+        /// Print the Report ID and bytes in a stable, versioned format for
+        /// scripts, see the README. The only version is "v1".
+        #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "v1", conflicts_with = "no_report_id")]
+        porcelain: Option<PorcelainVersion>,
+
+        /// Read the --field this many times and print the mean, minimum,
+        /// maximum and standard deviation of its logical value instead of
+        /// the bytes, e.g. to characterize a noisy sensor value
+        #[arg(long, requires = "field", value_parser = clap::value_parser!(u32).range(1..))]
+        samples: Option<u32>,
+
+        /// The time between two --samples, e.g. "100ms"
+        #[arg(long, requires = "samples", value_parser = parse_duration, default_value = "0s")]
+        interval: Duration,
+
+        /// Print nothing and exit with status 0 if the --field is nonzero
+        /// and 1 otherwise, e.g. for switch fields in shell conditionals:
+        ///    if hid-feature get --field "Fn Lock" --exit-status /dev/hidraw3; then ...
+        #[arg(long, requires = "field", conflicts_with_all = ["porcelain", "samples"])]
+        exit_status: bool,
+
+        /// Write the raw bytes of the report to this binary file instead
+        /// of printing them, e.g. for 'set --from-file'. The bytes include
+        /// the Report ID if the report has one.
+        ///
+        /// The file is only replaced once the report was read, like the
+        /// global --output this cannot be used with --sandbox.
+        #[arg(long, conflicts_with_all = ["field", "porcelain", "no_report_id"])]
+        save: Option<PathBuf>,
+
+        /// Print each Variable field of the report (or only the --field)
+        /// according to the given template instead of the bytes, see
+        /// 'list --template'. For example:
+        ///    hid-feature get --template '{usage_name}={value}' --report-id=4 /dev/hidraw3
+        #[arg(long, conflicts_with_all = ["porcelain", "no_report_id", "samples", "exit_status", "save"])]
+        template: Option<String>,
+
+        /// The output format.
+        ///
+        /// With "env", each Variable field of the report (or only the
+        /// --field) is printed as a HID_<USAGE>=<value> line suitable for
+        /// `eval` in a shell script, e.g.
+        ///    eval "$(hid-feature get --format=env --report-id=4 /dev/hidraw3)"
+        #[arg(long, value_enum, default_value_t = GetFormat::Bytes, conflicts_with_all = ["porcelain", "no_report_id", "samples", "exit_status", "save", "template"])]
+        format: GetFormat,
+
+        /// The radix of the field values with --template or --format=env
+        #[arg(long, value_enum, default_value_t = Radix::Dec)]
+        radix: Radix,
+    },
+
+    /// Write Feature fields by their usage name.
+    ///
+    /// Each value is validated against the field's logical range and
+    /// packed into the field's bits, all other bits of the report are left
+    /// unchanged. All fields must be in the same report. For example:
+    ///    hid-feature set-field /dev/hidraw3 "Surface Switch=1" "Button Switch=0"
+    ///
+    /// The names are matched as in 'edit --field'.
+    SetField {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        #[command(flatten)]
+        report: ReportSelection,
+
+        /// See 'set --retry-wait'
+        #[arg(long, value_parser = parse_duration, default_value = "0s")]
+        retry_wait: Duration,
+
+        /// See 'set --force'
+        #[arg(long)]
+        force: bool,
+
+        /// See 'set --only-changed'
+        #[arg(long)]
+        only_changed: bool,
+
+        /// See 'set --dry-run'
+        #[arg(long)]
+        dry_run: bool,
+
+        /// See 'set --verify'
+        #[arg(long, conflicts_with = "dry_run")]
+        verify: bool,
+
+        /// The fields and their logical values as NAME=VALUE. The value
+        /// may be an expression with the current value 'cur', e.g.
+        /// "Resolution Multiplier=cur+1", see 'set --field'
+        assignments: Vec<String>,
+    },
+
+    /// Print the bytes of a Feature Report with the given field values.
+    ///
+    /// This is the inverse of 'decode': the fields are packed into a
+    /// report of zeroes and the bytes are printed like 'get' prints them,
+    /// e.g. for firmware test scripts:
+    ///    $ hid-feature encode /dev/hidraw3 "Report Rate=500" "LED Mode=2"
+    ///    05 f4 01 02
+    ///
+    /// The device is not accessed except for its report descriptor. The
+    /// names are matched as in 'edit --field', all fields must be in the
+    /// same report.
+    Encode {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        #[command(flatten)]
+        report: ReportSelection,
+
+        /// Do not print the Report ID
+        #[arg(long)]
+        no_report_id: bool,
+
+        /// Print the Report ID and bytes like 'get --porcelain'
+        #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "v1", conflicts_with = "no_report_id")]
+        porcelain: Option<PorcelainVersion>,
+
+        /// Use this report descriptor instead of a device's, see
+        /// 'list --rdesc-file'
+        #[arg(long, conflicts_with_all = ["port", "identity", "name"])]
+        rdesc_file: Option<PathBuf>,
+
+        /// The fields and their logical values as NAME=VALUE. The value
+        /// may be an expression, see 'set --field', but 'cur' is always 0.
+        assignments: Vec<String>,
+    },
+
+    /// Print the /dev/hidraw node(s) of a device given its stable identity.
+    ///
+    /// Unlike the hidraw node number, the identity of a device does not
+    /// change across reboots or when devices are plugged in in a different
+    /// order. The identity is one of:
+    ///
+    ///   - the vendor and product ID, e.g. "046d:c52b"
+    ///
+    ///   - the vendor and product ID and the device's serial number,
+    ///     e.g. "046d:c52b:ABCD1234"
+    ///
+    ///   - the USB port path as shown by 'list-devices', e.g. "1-3.2" or "1-3.2:1.0"
+    ///
+    ///   - the report descriptor fingerprint as shown by 'stats' or
+    ///     'list-devices --verbose', e.g. "rdesc:8c1f0e6a7b3d2f41". This
+    ///     identifies a firmware's report layout rather than a device.
+    ///
+    /// Where a device has multiple hidraw nodes, all of them are printed.
+    Resolve {
+        /// The device identity
+        identity: DeviceIdentity,
+    },
+
+    /// Check whether a device can be used with this tool.
+    ///
+    /// This checks the permissions on the device node, the availability
+    /// of the report descriptor and the required ioctls and whether the
+    /// device responds to a GetFeature request. Where a check fails, a hint
+    /// is printed on how to resolve the issue.
+    ///
+    /// If no device is given, all hidraw devices are checked. If some
+    /// but not all devices fail the checks, the exit code is 2.
+    Doctor {
+        #[command(flatten)]
+        device: DeviceArgs,
+    },
+
+    /// Check that a Feature Report reads back the same after writing it.
+    ///
+    /// This reads the report, writes the identical bytes back, reads the
+    /// report again and prints any difference. A report that changes when
+    /// written back has side effects or asymmetric reads and writes, and
+    /// 'set' (which writes back the bytes it does not change) should not
+    /// be trusted with it.
+    Selftest {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        #[command(flatten)]
+        report: ReportSelection,
+
+        /// Write the report even if it is write-protected in the
+        /// configuration file
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Check a report descriptor for spec violations and suspicious patterns.
+    ///
+    /// The path may be a /dev/hidraw node or a file containing the binary
+    /// report descriptor, e.g. a copy of the report_descriptor file in sysfs.
+    ///
+    /// This checks for overlapping fields, a mix of reports with and without
+    /// Report IDs, logical ranges that do not fit into the field size and
+    /// reports that exceed the maximum packet size of low and full speed USB
+    /// devices.
+    Lint {
+        #[command(flatten)]
+        device: DeviceArgs,
+    },
+
+    /// Print which fields occupy each byte of the Feature Reports.
+    ///
+    /// For each byte of a report this prints the fields (and which of
+    /// their bits) stored in that byte. Bytes shared by more than one
+    /// field are marked as shared, writing such a byte with 'set' changes
+    /// all of those fields.
+    ///
+    /// The path may be a /dev/hidraw node or a file containing the binary
+    /// report descriptor.
+    Map {
+        /// Only print the report with the given Report ID
+        #[arg(long)]
+        report_id: Option<u8>,
+
+        #[command(flatten)]
+        device: DeviceArgs,
+    },
+
+    /// Interactively change the values of Feature fields.
+    ///
+    /// Without --field this opens a full-screen editor listing every
+    /// Feature field with its current value and logical range. Use the
+    /// arrow keys to select a field, Enter or a digit to type a new value
+    /// and Enter to confirm it. New values are validated against the
+    /// logical range and only written to the device with w (or Ctrl-S), q
+    /// quits. Values are fetched again once they are older than 5 seconds,
+    /// r fetches them immediately. Like 'set', the editor refuses to write
+    /// protected reports and fields and runs the write hooks.
+    ///
+    /// With --field this prints the field's current value and logical
+    /// range and prompts for the new value. An empty value leaves the
+    /// field unchanged.
+    Edit {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        /// The field's usage name, e.g. "Report Rate" or "Digitizers / Report Rate".
+        ///
+        /// The name is case-insensitive and may be a unique part of the
+        /// name, e.g. "report rate" or "rate".
+        #[arg(long)]
+        field: Option<String>,
+
+        /// The Report ID of the field, required if the usage exists in
+        /// multiple reports
+        #[arg(long, requires = "field")]
+        report_id: Option<u8>,
+
+        /// See 'set --force'
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Get or set the common digitizer settings of a touchscreen or
+    /// touchpad.
+    ///
+    /// This looks up the Digitizers page Feature usages Report Rate,
+    /// Latency Mode, Surface Switch and Button Switch. Without any options
+    /// the current values are printed, otherwise the given settings are
+    /// written and all other fields are left unchanged.
+    ///
+    /// For example:
+    ///    hid-feature digitizer --report-rate 120 --latency-mode normal /dev/hidraw3
+    Digitizer {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        /// The report rate in Hz
+        #[arg(long)]
+        report_rate: Option<u32>,
+
+        #[arg(long)]
+        latency_mode: Option<LatencyMode>,
+
+        /// Whether contacts are reported
+        #[arg(long)]
+        surface_switch: Option<Switch>,
+
+        /// Whether buttons are reported
+        #[arg(long)]
+        button_switch: Option<Switch>,
+
+        /// See 'set --force'
+        #[arg(long)]
+        force: bool,
+
+        /// See 'set --only-changed'
+        #[arg(long)]
+        only_changed: bool,
+
+        /// See 'set --dry-run'
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Print aggregate counts across all hidraw devices.
+    ///
+    /// This prints the number of devices, how many of them have Feature
+    /// Reports, the total number of Feature Reports and fields, the largest
+    /// Feature Report and the number of devices whose Feature fields are
+    /// all on vendor-defined usage pages. See 'stats' for a per-device
+    /// summary.
+    Stat,
+
+    /// Print a summary of a device's reports and fields.
+    ///
+    /// This prints the number of reports and their total size for each
+    /// report type and the number of fields per usage page.
+    ///
+    /// If no device is given, all hidraw devices are summarized.
+    Stats {
+        #[command(flatten)]
+        device: DeviceArgs,
+    },
+
+    /// Poll Feature Reports and print their changes.
+    ///
+    /// The reports are fetched every interval and printed with a timestamp
+    /// whenever their bytes change, with the changed bytes highlighted and
+    /// the old and new value of each changed Variable field, until the
+    /// duration has passed or the command is interrupted. By default all
+    /// Feature Reports are watched.
+    ///
+    /// In a terminal, r fetches the reports immediately, s prints each
+    /// report with how long ago it was fetched, h prints the values kept
+    /// with --show-last and q quits.
+    Watch {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        #[command(flatten)]
+        filter: ReportFilter,
+
+        /// How often to fetch the reports, e.g. "500ms"
+        #[arg(long, value_parser = parse_duration, default_value = "1s")]
+        interval: Duration,
+
+        /// Stop after this duration, e.g. "30s"
+        #[arg(long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+
+        /// Show a desktop notification when this field changes, e.g.
+        /// "Battery Strength", or when its value crosses a threshold, e.g.
+        /// "Battery Strength<20". May be given multiple times. Requires
+        /// notify-send.
+        #[arg(long)]
+        notify: Vec<NotifyCondition>,
+
+        /// Keep the last N values of each Variable field, i.e. its first
+        /// value and its changes, and print them when h is pressed and when
+        /// watch exits
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        show_last: Option<u32>,
+
+        /// Publish each changed Variable field to an MQTT broker, e.g.
+        /// "mqtt://broker.local/home/hid". The URL is
+        /// mqtt://[user[:password]@]host[:port]/topic, the message is a JSON
+        /// object with the device, Report ID, field, old and new value.
+        #[arg(long, value_name = "URL")]
+        mqtt: Option<mqtt::MqttUrl>,
+
+        /// Print the Variable fields according to the given template
+        /// instead of the bytes and changed fields: all fields when the
+        /// reports are first fetched, then each field whose value changed.
+        /// See 'list --template'.
+        #[arg(long)]
+        template: Option<String>,
+
+        /// The radix of the field values
+        #[arg(long, value_enum, default_value_t = Radix::Dec)]
+        radix: Radix,
+    },
+
+    /// Read Input Reports from the device and print their fields.
+    ///
+    /// Each Input Report is matched against the report descriptor and
+    /// printed with a timestamp, its bytes and the values of its fields,
+    /// until the count is reached or the command is interrupted. Array
+    /// fields are printed as the usages they currently select.
+    Read {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        #[command(flatten)]
+        filter: ReportFilter,
+
+        /// Only print fields with this usage, e.g. "X" or, with wildcards,
+        /// "Button*"
+        #[arg(long)]
+        usage: Option<String>,
+
+        /// Stop after this many (printed) reports
+        #[arg(long)]
+        count: Option<usize>,
+    },
+
+    /// Record a session of Feature and Input Reports.
+    ///
+    /// This fetches all Feature Reports, then logs every Input Report and
+    /// any change to a Feature Report with a timestamp until the duration
+    /// has passed or the command is interrupted.
+    ///
+    /// The recording is line-based text, empty lines and lines starting
+    /// with '#' are comments. Bytes are space-separated hex values and
+    /// timestamps are seconds since the start of the recording. The first
+    /// record is the report descriptor as "R: <size> <bytes>". Feature
+    /// Reports are recorded as "F: <timestamp> <report id> <size> <bytes>",
+    /// Input Reports as "I: <timestamp> <size> <bytes>". The bytes include
+    /// the Report ID if the report has one, the report id of a Feature
+    /// Report without a Report ID is 0.
+    Record {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        /// Write the recording to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+
+        /// Stop recording after this duration, e.g. "30s"
+        #[arg(long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+
+        /// How often to fetch the Feature Reports to detect changes
+        #[arg(long, value_parser = parse_duration, default_value = "1s")]
+        interval: Duration,
+    },
+
+    /// Save all Feature Reports of a device to a file.
+    ///
+    /// The snapshot contains the bytes of every Feature Report and the
+    /// fingerprint of the report descriptor, see 'restore'.
+    ///
+    /// The snapshot is a JSON object with the format "version" (1), the
+    /// "device" it was taken from, the report descriptor "rdesc"
+    /// fingerprint, the report "descriptor" and the "reports". Each report
+    /// has a "report_id" (null for a report without one) and its "bytes"
+    /// including the Report ID, all bytes are space-separated hex values.
+    Backup {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        /// Write the snapshot to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Write the Feature Reports of a snapshot back to the device.
+    ///
+    /// The snapshot must have been taken with 'backup' from a device with
+    /// the same report descriptor. Each report is written as with 'set',
+    /// e.g. a write-protected report is an error unless --force is given.
+    Restore {
+        #[command(flatten)]
+        device: DeviceArgs,
+
+        /// The snapshot as written by 'backup'
+        file: Option<PathBuf>,
+
+        #[command(flatten)]
+        filter: ReportFilter,
+
+        /// See 'set --force'
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Show the Feature Report values that differ between two snapshots
+    /// or devices.
+    ///
+    /// Each side is a snapshot written by 'backup' or a /dev/hidraw node
+    /// whose reports are fetched, e.g. to find the bit a vendor tool
+    /// flips:
+    ///    hid-feature diff before.json /dev/hidraw3
+    ///
+    /// The fields that differ are printed by usage name if both sides have
+    /// the same report descriptor, otherwise only the bytes are compared.
+    Diff {
+        /// The first snapshot or device
+        first: PathBuf,
+
+        /// The second snapshot or device
+        second: PathBuf,
+    },
+
+    /// Replay a recording onto a virtual uhid device.
+    ///
+    /// This creates a device with the recorded report descriptor and sends
+    /// the recorded Input Reports with their original timing. Feature
+    /// Report requests are answered with the recorded values. The device
+    /// is removed when the replay ends. Requires access to /dev/uhid.
+    Replay {
+        /// The recording as written by 'record'
+        file: PathBuf,
+
+        /// The name of the virtual device
+        #[arg(long, default_value = "hid-feature replay")]
+        name: String,
+
+        /// Edit the report descriptor as described in this JSON file,
+        /// e.g. to add a field or change a logical range
+        #[arg(long, value_name = "FILE")]
+        edit: Option<PathBuf>,
+
+        /// Keep the device after the last event until interrupted
+        #[arg(long)]
+        keep: bool,
+    },
+
+    /// Convert a Wireshark capture of a USB HID device into a recording.
+    ///
+    /// The capture must be exported as JSON with
+    /// 'tshark -r capture.pcapng -T json -x'. Report descriptor, Feature
+    /// Reports and Input Reports are extracted into the format written by
+    /// 'record', which is printed to stdout.
+    Import {
+        /// The capture as exported by tshark
+        file: PathBuf,
+
+        /// The USB device address, required if the capture contains more
+        /// than one device
+        #[arg(long)]
+        address: Option<u8>,
+
+        /// A file with the binary report descriptor, for captures that
+        /// do not contain the device's enumeration
+        #[arg(long)]
+        rdesc: Option<PathBuf>,
+    },
+
+    /// Unbind or rebind the kernel driver of a HID device.
+    ///
+    /// Some devices only accept feature requests while the kernel driver
+    /// is detached. Note that unbinding the driver also removes the
+    /// device's hidraw node, the device must be rebound by its HID device
+    /// name (e.g. "0003:046D:C52B.000F") as printed by 'driver unbind'.
+    Driver {
+        #[command(subcommand)]
+        action: DriverAction,
+    },
+
+    /// Apply a scene from the configuration file.
+    ///
+    /// A scene is a named list of writes to one or more devices, each
+    /// equivalent to a 'set' invocation. Devices are resolved when the
+    /// scene is applied and the remaining writes continue if one fails.
+    Scene {
+        /// The name of the scene
+        name: String,
+
+        /// Skip the writes to reports that already have the scene's
+        /// values, see 'set --only-changed'
+        #[arg(long)]
+        only_changed: bool,
+
+        /// Write up to N of the scene's entries at the same time
+        #[arg(long, value_name = "N", default_value_t = 1,
+              value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: u32,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DriverAction {
+    /// Unbind the device from its kernel driver
+    Unbind {
+        /// Unbind the driver even if the device is a keyboard or mouse
+        #[arg(long)]
+        force: bool,
+
+        #[command(flatten)]
+        device: DeviceArgs,
+    },
+
+    /// Bind the device to a kernel driver again
+    Rebind {
+        /// Bind to this driver instead of the best matching driver
+        #[arg(long)]
+        driver: Option<String>,
+
+        /// The HID device name (e.g. "0003:046D:C52B.000F") or the
+        /// /dev/hidraw node. A device that is still bound is unbound
+        /// first.
+        device: String,
+    },
+}
+
+fn resolve(identity: &DeviceIdentity) -> Result<()> {
+    for device in identity.resolve()? {
+        println!("{}", device.path.display());
+    }
+    Ok(())
+}
+
+/// Prints the line terminated by a newline or, if `print0` is true, by a NUL character
+fn print_record(line: &str, print0: bool) {
+    if print0 {
+        print!("{line}\0");
+    } else {
+        println!("{line}");
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum DeviceSort {
+    /// Sort by device name
+    Name,
+    /// Sort by hidraw node number
+    Node,
+    /// Sort by vendor ID, then product ID
+    VidPid,
+    /// Sort by bus type
+    Bus,
+}
+
+impl DeviceSort {
+    fn compare(&self, a: &HidrawDevice, b: &HidrawDevice) -> std::cmp::Ordering {
+        let by_node = || human_sort::compare(&a.path.to_string_lossy(), &b.path.to_string_lossy());
+        match self {
+            DeviceSort::Name => a.name.cmp(&b.name),
+            DeviceSort::Node => std::cmp::Ordering::Equal,
+            DeviceSort::VidPid => (a.vid, a.pid).cmp(&(b.vid, b.pid)),
+            DeviceSort::Bus => a.bus.cmp(&b.bus),
+        }
+        .then_with(by_node)
+    }
+}
+
+fn list_devices(
+    sort: DeviceSort,
+    verbose: bool,
+    print0: bool,
+    name: &Option<String>,
+    group: bool,
+) -> Result<()> {
+    if !print0 {
+        println!("Available HID devices:");
+    }
+
+    let mut devices = backend().devices()?;
+    if let Some(pattern) = name {
+        devices.retain(|d| glob_matches(pattern, &d.name));
+    }
+    devices.sort_by(|a, b| sort.compare(a, b));
+    if group {
+        return list_device_groups(devices, print0);
+    }
+    for device in devices {
+        let path = device.path.display().to_string();
+        let name = device.name;
+        let id = format!(
+            "{} {:04x}:{:04x}",
+            bus_name(device.bus),
+            device.vid,
+            device.pid
+        );
+        let mut line = match device.port {
+            Some(port) => format!("{path:13} - {id} - {name} (port {port})"),
+            None => format!("{path:13} - {id} - {name}"),
+        };
+        if verbose {
+            let unknown = String::from("<unknown>");
+            let phys = device.phys.as_ref().unwrap_or(&unknown);
+            let uniq = device.serial.as_ref().unwrap_or(&unknown);
+            line.push_str(&format!("\n{:13}   phys: {phys}", ""));
+            line.push_str(&format!("\n{:13}   uniq: {uniq}", ""));
+            let fingerprint = backend()
+                .report_descriptor(&device.path)
+                .map(|rdesc| format!("rdesc:{}", rdesc_fingerprint(&rdesc)))
+                .unwrap_or(unknown);
+            line.push_str(&format!("\n{:13}   fingerprint: {fingerprint}", ""));
+        }
+        print_record(&line, print0);
+    }
+    Ok(())
+}
+
+/// Prints one --porcelain=v1 line per device: node, bus type, vendor ID,
+/// product ID, port, phys, uniq and name
+fn list_devices_porcelain(sort: DeviceSort, name: &Option<String>) -> Result<()> {
+    let mut devices = backend().devices()?;
+    if let Some(pattern) = name {
+        devices.retain(|d| glob_matches(pattern, &d.name));
+    }
+    devices.sort_by(|a, b| sort.compare(a, b));
+    for device in devices {
+        println!(
+            "{}",
+            porcelain_line(&[
+                device.path.display().to_string(),
+                format!("{:04x}", device.bus),
+                format!("{:04x}", device.vid),
+                format!("{:04x}", device.pid),
+                device.port.unwrap_or_default(),
+                device.phys.unwrap_or_default(),
+                device.serial.unwrap_or_default(),
+                device.name,
+            ])
+        );
+    }
+    Ok(())
+}
+
+fn list_devices_json(sort: DeviceSort, name: &Option<String>) -> Result<()> {
+    let mut devices = backend().devices()?;
+    if let Some(pattern) = name {
+        devices.retain(|d| glob_matches(pattern, &d.name));
+    }
+    devices.sort_by(|a, b| sort.compare(a, b));
+    let devices: Vec<_> = devices
+        .iter()
+        .map(|device| {
+            serde_json::json!({
+                "path": device.path,
+                "name": device.name,
+                "bus": bus_name(device.bus),
+                "bus_type": device.bus,
+                "vendor_id": format!("{:04x}", device.vid),
+                "product_id": format!("{:04x}", device.pid),
+                "phys": device.phys,
+                "uniq": device.serial,
+                "port": device.port,
+                "fingerprint": backend().report_descriptor(&device.path)
+                    .ok()
+                    .map(|rdesc| format!("rdesc:{}", rdesc_fingerprint(&rdesc))),
+            })
+        })
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&devices)?);
+    Ok(())
+}
+
+/// Returns the usages of the device's top-level Application Collections
+fn application_collections(path: &Path) -> Vec<String> {
+    let Ok(rdesc) = report_descriptor(path) else {
+        return Vec::new();
+    };
+    let mut collections: Vec<String> = Vec::new();
+    let fields = rdesc
+        .input_reports()
+        .iter()
+        .flat_map(|r| r.fields())
+        .chain(rdesc.output_reports().iter().flat_map(|r| r.fields()))
+        .chain(rdesc.feature_reports().iter().flat_map(|r| r.fields()));
+    for collection in fields.filter_map(application_collection) {
+        if !collections.contains(&collection) {
+            collections.push(collection);
+        }
+    }
+    collections
+}
+
+/// Returns the sysfs path of the physical device, see
+/// [hidraw_physical_device]
+#[cfg(target_os = "linux")]
+fn physical_device(path: &Path) -> Option<PathBuf> {
+    let file = path.file_name()?.to_string_lossy().to_string();
+    hidraw_physical_device(&file)
+}
+
+/// Off Linux, every device is a group of its own
+#[cfg(not(target_os = "linux"))]
+fn physical_device(_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+fn list_device_groups(devices: Vec<HidrawDevice>, print0: bool) -> Result<()> {
+    let mut groups: Vec<(Option<PathBuf>, Vec<HidrawDevice>)> = Vec::new();
+    for device in devices {
+        let physical = physical_device(&device.path);
+        match groups
+            .iter_mut()
+            .find(|(p, _)| p.is_some() && *p == physical)
+        {
+            Some((_, members)) => members.push(device),
+            None => groups.push((physical, vec![device])),
+        }
+    }
+
+    for (physical, members) in groups {
+        let first = &members[0];
+        let name = physical
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_else(|| "<unknown>".into());
+        let mut record = format!(
+            "{name} - {} {:04x}:{:04x} - {}",
+            bus_name(first.bus),
+            first.vid,
+            first.pid,
+            first.name
+        );
+        for device in &members {
+            record.push_str(&format!("\n  {}", device.path.display()));
+            if let Some(port) = &device.port {
+                record.push_str(&format!(" (port {port})"));
+            }
+            for collection in application_collections(&device.path) {
+                record.push_str(&format!("\n      {collection}"));
+            }
+        }
+        print_record(&record, print0);
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Column {
+    Report,
+    Collection,
+    Usage,
+    Bits,
+    BitRange,
+    /// The byte offset and the bit within that byte, e.g. "2.3"
+    Offset,
+    ValueRange,
+    Count,
+    Value,
+    Bytes,
+}
+
+impl std::fmt::Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let value = self.to_possible_value().unwrap();
+        write!(f, "{}", value.get_name())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum LatencyMode {
+    /// Full report rate (0)
+    Normal,
+    /// Reduced report rate to save power (1)
+    High,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Switch {
+    Off,
+    On,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+enum Radix {
+    /// Hexadecimal, e.g. 0x1f
+    Hex,
+    /// Decimal, e.g. 31
+    #[default]
+    Dec,
+    /// Binary with one digit per bit of the field, e.g. 0b00011111
+    Bin,
+}
+
+impl Radix {
+    /// Formats the value of a field with the given number of bits. Negative
+    /// values are printed in two's complement in hex and binary.
+    fn format(&self, value: i32, bits: usize) -> String {
+        let unsigned = match bits {
+            0..32 => value as u32 & ((1 << bits) - 1),
+            _ => value as u32,
+        };
+        match self {
+            Radix::Hex => format!("{unsigned:#x}"),
+            Radix::Dec => value.to_string(),
+            Radix::Bin => format!("{unsigned:#0width$b}", width = bits.min(32) + 2),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum BitOrder {
+    /// Bit 0 is the least significant bit of the first byte, as in the HID
+    /// specification
+    Lsb0,
+    /// Bit 0 is the most significant bit of the first byte, as in many
+    /// hardware datasheets
+    Msb0,
+}
+
+impl BitOrder {
+    /// Converts a bit position in the report from lsb0 to this bit order
+    fn position(&self, bit: usize) -> usize {
+        match self {
+            BitOrder::Lsb0 => bit,
+            BitOrder::Msb0 => (bit / 8) * 8 + 7 - bit % 8,
+        }
+    }
+
+    /// Returns the first and last bit of the range in this bit order
+    fn range(&self, bits: &std::ops::Range<usize>) -> (usize, usize) {
+        let (first, last) = (self.position(bits.start), self.position(bits.end - 1));
+        (first.min(last), first.max(last))
+    }
+}
+
+/// The byte order of a multi-byte value
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+enum ByteOrder {
+    /// Least significant byte first, as in the HID specification
+    #[default]
+    Little,
+    /// Most significant byte first
+    Big,
+}
+
+/// The version of the --porcelain output, see [porcelain_line]
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum PorcelainVersion {
+    V1,
+}
+
+/// Joins the values into a line of --porcelain output: the values are
+/// separated by tabs, an empty value is printed as "-" and tabs or
+/// newlines within a value are replaced by spaces. Numbers are always
+/// decimal and IDs are lowercase hex, independent of any other option.
+fn porcelain_line(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|v| match v.as_str() {
+            "" => "-".into(),
+            v => v.replace(['\t', '\n', '\r'], " "),
+        })
+        .collect::<Vec<String>>()
+        .join("\t")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum DeviceListFormat {
+    /// One line per device
+    Table,
+    /// A JSON array of devices
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum ListFormat {
+    /// A human-readable table
+    Table,
+    /// HID_<USAGE>=<value> lines for use with `eval`
+    Env,
+    /// A JSON object with the reports and their fields. Errors are
+    /// printed as JSON object on stderr.
+    Json,
+    /// Set by --porcelain
+    #[value(skip)]
+    Porcelain,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+enum GetFormat {
+    /// The bytes of the report
+    #[default]
+    Bytes,
+    /// HID_<USAGE>=<value> lines for use with `eval`, see 'list --format'
+    Env,
+}
+
+/// Assigns the shell variable names of --format env, a usage that occurs
+/// more than once gets a _2, _3, ... suffix
+#[derive(Default)]
+struct EnvNames(HashMap<String, usize>);
+
+impl EnvNames {
+    /// Returns the NAME=value line for the usage, see [env_name]
+    fn line(&mut self, usage: &Usage, value: &str) -> String {
+        let name = env_name(usage);
+        let n = self.0.entry(name.clone()).or_default();
+        *n += 1;
+        match *n {
+            1 => format!("{name}={value}"),
+            n => format!("{name}_{n}={value}"),
+        }
+    }
+}
+
+/// Returns the shell variable name for the usage, e.g. HID_REPORT_RATE.
+/// Unknown usages use their hex page and id, e.g. HID_FF00_0001.
+fn env_name(usage: &Usage) -> String {
+    let page = u16::from(usage.usage_page);
+    let id = u16::from(usage.usage_id);
+    let name = match hut::Usage::new_from_page_and_id(page, id) {
+        Ok(u) => u.name(),
+        Err(_) => format!("{page:04X} {id:04X}"),
+    };
+    let mut sanitized = String::from("HID");
+    for word in name.split(|c: char| !c.is_ascii_alphanumeric()) {
+        if !word.is_empty() {
+            sanitized.push('_');
+            sanitized.push_str(&word.to_ascii_uppercase());
+        }
+    }
+    sanitized
+}
+
+/// A single row in the `list` output
+struct FieldRow {
+    /// The Report ID, `None` for a report without one
+    report_id: Option<u8>,
+    collection: String,
+    usage: String,
+    /// The usage page and id, if the field has a usage
+    usage_code: Option<(u16, u16)>,
+    bits: std::ops::Range<usize>,
+    logical_minimum: i32,
+    logical_maximum: u32,
+    count: usize,
+    /// The current value, `None` without a device (see --rdesc-file)
+    value: Option<i32>,
+    radix: Radix,
+    bit_order: BitOrder,
+    bytes: Vec<u8>,
+}
+
+impl FieldRow {
+    /// Returns the row of a Variable field with its value in the bytes of
+    /// its report, `None` for other fields or if the bytes are too short
+    fn variable(
+        field: &Field,
+        report_id: Option<u8>,
+        bytes: &[u8],
+        radix: Radix,
+    ) -> Option<FieldRow> {
+        let Field::Variable(var) = field else {
+            return None;
+        };
+        let value = var.extract(bytes).ok()?;
+        let bytes = bytes.get(var.bits.start / 8..=(var.bits.end - 1) / 8)?;
+        Some(FieldRow {
+            report_id,
+            collection: application_collection(field).unwrap_or_default(),
+            usage: usage_name(&var.usage),
+            usage_code: Some((
+                u16::from(var.usage.usage_page),
+                u16::from(var.usage.usage_id),
+            )),
+            bits: var.bits.clone(),
+            logical_minimum: i32::from(var.logical_minimum),
+            logical_maximum: i32::from(var.logical_maximum) as u32,
+            count: 1,
+            value: Some(i32::from(value)),
+            radix,
+            bit_order: BitOrder::Lsb0,
+            bytes: bytes.to_vec(),
+        })
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let (bit_start, bit_end) = self.bit_order.range(&self.bits);
+        serde_json::json!({
+            "report_id": self.report_id,
+            "collection": self.collection,
+            "usage": self.usage,
+            "bits": self.bits.end - self.bits.start,
+            "bit_start": bit_start,
+            "bit_end": bit_end,
+            "byte_offset": self.offset().0,
+            "logical_minimum": self.logical_minimum,
+            "logical_maximum": self.logical_maximum,
+            "count": self.count,
+            "value": self.value,
+            "bytes": self.value.is_some().then_some(&self.bytes),
+        })
+    }
+
+    /// Returns the byte offset of the field's first bit (as used by
+    /// `set --offset`) and the position of that bit within the byte
+    fn offset(&self) -> (usize, usize) {
+        let start = self.bits.start;
+        (start / 8, self.bit_order.position(start) % 8)
+    }
+
+    /// The --porcelain=v1 line: report ID, collection, usage page, usage
+    /// id, bit start, bit end (exclusive), logical minimum, logical
+    /// maximum, count, value, bytes and usage name
+    fn to_porcelain(&self) -> String {
+        let (page, id) = match self.usage_code {
+            Some((page, id)) => (format!("{page:04x}"), format!("{id:04x}")),
+            None => Default::default(),
+        };
+        porcelain_line(&[
+            self.report_id.map(|id| id.to_string()).unwrap_or_default(),
+            self.collection.clone(),
+            page,
+            id,
+            self.bits.start.to_string(),
+            self.bits.end.to_string(),
+            self.logical_minimum.to_string(),
+            self.logical_maximum.to_string(),
+            self.count.to_string(),
+            self.value.map(|v| v.to_string()).unwrap_or_default(),
+            self.bytes.iter().map(|b| format!("{b:02x}")).collect(),
+            self.usage.clone(),
+        ])
+    }
+
+    fn formatted_value(&self) -> String {
+        match self.value {
+            Some(value) => self.radix.format(value, self.bits.end - self.bits.start),
+            None => "-".into(),
+        }
+    }
+
+    fn placeholder(&self, name: &str) -> Result<String> {
+        let bits = &self.bits;
+        Ok(match name {
+            "report_id" => self.report_id.map(|id| id.to_string()).unwrap_or_default(),
+            "collection" => self.collection.clone(),
+            "usage_name" => self.usage.clone(),
+            "bits" => (bits.end - bits.start).to_string(),
+            "bit_start" => self.bit_order.range(bits).0.to_string(),
+            "bit_end" => self.bit_order.range(bits).1.to_string(),
+            "byte_offset" => self.offset().0.to_string(),
+            "bit_offset" => self.offset().1.to_string(),
+            "logical_minimum" => self.logical_minimum.to_string(),
+            "logical_maximum" => self.logical_maximum.to_string(),
+            "count" => self.count.to_string(),
+            "value" => self.formatted_value(),
+            "bytes" => print_bytes(&self.bytes),
+            _ => bail!("Unknown template placeholder {{{name}}}"),
+        })
+    }
+
+    /// Formats this row according to a template string with `{placeholder}`
+    /// fields, see [FieldRow::placeholder] for the supported placeholders.
+    fn format_template(&self, template: &str) -> Result<String> {
+        let mut output = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    output.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    output.push('}');
+                }
+                '{' => {
+                    let (name, rest) = chars
+                        .as_str()
+                        .split_once('}')
+                        .context("Unterminated placeholder in template")?;
+                    output.push_str(&self.placeholder(name)?);
+                    chars = rest.chars();
+                }
+                _ => output.push(c),
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl Column {
+    /// Returns the default columns, see [Column::Collection] for `with_collection`
+    fn defaults(with_collection: bool) -> Vec<Column> {
+        Column::value_variants()
+            .iter()
+            .filter(|c| with_collection || **c != Column::Collection)
+            .copied()
+            .collect()
+    }
+
+    fn header(&self) -> String {
+        match self {
+            Column::Report => "Report".into(),
+            Column::Collection => format!("{:^40}", "Collection"),
+            Column::Usage => format!("{:^48}", "Usage"),
+            Column::Bits => "Bits".into(),
+            Column::BitRange => "Bit Range".into(),
+            Column::Offset => "Offset".into(),
+            Column::ValueRange => "Value Range".into(),
+            Column::Count => "Count".into(),
+            Column::Value => "Value".into(),
+            Column::Bytes => "Bytes".into(),
+        }
+    }
+
+    fn format(&self, row: &FieldRow) -> String {
+        let bits = &row.bits;
+        let (min, max) = (row.logical_minimum, row.logical_maximum);
+        match self {
+            Column::Report => match row.report_id {
+                Some(id) => format!("{id:^6}"),
+                None => format!("{:^6}", "-"),
+            },
+            Column::Collection => format!("{:40}", row.collection),
+            Column::Usage => format!("{:48}", row.usage),
+            Column::Bits => format!("{:^4}", bits.end - bits.start),
+            Column::BitRange => {
+                let (start, end) = row.bit_order.range(bits);
+                format!("{start:3}..={end:<3}")
+            }
+            Column::Offset => {
+                let (byte, bit) = row.offset();
+                format!("{:>6}", format!("{byte}.{bit}"))
+            }
+            Column::ValueRange => format!("{min:4}..={max:<4}"),
+            Column::Count => format!("{:^5}", row.count),
+            Column::Value => format!("{:>5}", row.formatted_value()),
+            Column::Bytes => print_bytes(&row.bytes),
+        }
+    }
+}
+
+/// Where `list` takes the report descriptor and the field values from
+enum ListSource<'a> {
+    /// The device's report descriptor and current values, fetched through
+    /// the backend
+    Device(&'a dyn HidBackend),
+    /// Only a report descriptor, e.g. from a file or with --no-values,
+    /// there are no values
+    Descriptor(Vec<u8>),
+    /// A report descriptor and the bytes of the selected report, see 'decode'
+    Bytes { rdesc: Vec<u8>, bytes: Vec<u8> },
+}
+
+/// Returns true if the report is selected by the `list` filters
+fn list_report_selected<R: Report>(
+    report: &R,
+    filter: &ReportFilter,
+    collection: &Option<String>,
+) -> bool {
+    filter.matches(report.report_id().map(u8::from))
+        && collection
+            .as_ref()
+            .is_none_or(|c| report_in_collection(report, c))
+}
+
+fn list(
+    path: &Path,
+    report_type: ReportType,
+    filter: &ReportFilter,
+    collection: &Option<String>,
+    usage: &Option<String>,
+    output: &ListOutput,
+    source: ListSource,
+) -> Result<()> {
+    let template = &output.template;
+    let (format, print0) = (output.format, output.print0);
+    // Without a device (--rdesc-file or --no-values), only the layout is listed
+    let offline = matches!(source, ListSource::Descriptor(_));
+    if offline && format == ListFormat::Env {
+        bail!("--format env requires the device's current values, it cannot be used with --rdesc-file or --no-values");
+    }
+    let (rdesc_bytes, decoded, backend) = match source {
+        ListSource::Device(backend) => (
+            backend.report_descriptor(path).device_context(path, None)?,
+            None,
+            Some(backend),
+        ),
+        ListSource::Descriptor(rdesc) => (rdesc, None, None),
+        ListSource::Bytes { rdesc, bytes } => (rdesc, Some(bytes), None),
+    };
+    let rdesc = ReportDescriptor::try_from(&rdesc_bytes)?;
+
+    // With --debug, the table shows the descriptor items of each field
+    let show_items = debug() && template.is_none() && format == ListFormat::Table && !print0;
+    let feature_items = if show_items && report_type == ReportType::Feature {
+        items::feature_items(&rdesc_bytes)?
+    } else {
+        Vec::new()
+    };
+
+    let context = ListContext {
+        path,
+        report_type,
+        filter,
+        collection,
+        usage,
+        output,
+        offline,
+        backend,
+        decoded,
+        feature_items,
+    };
+    match report_type {
+        ReportType::Feature => list_reports(rdesc.feature_reports(), context),
+        ReportType::Input => list_reports(rdesc.input_reports(), context),
+        ReportType::Output => list_reports(rdesc.output_reports(), context),
+    }
+}
+
+/// What [list_reports] prints and where the values come from
+struct ListContext<'a> {
+    path: &'a Path,
+    report_type: ReportType,
+    filter: &'a ReportFilter,
+    collection: &'a Option<String>,
+    usage: &'a Option<String>,
+    output: &'a ListOutput,
+    /// There are no values, only the layout is listed
+    offline: bool,
+    /// The backend to fetch the values from the device, if any
+    backend: Option<&'a dyn HidBackend>,
+    /// The bytes of the selected report, see 'decode'
+    decoded: Option<Vec<u8>>,
+    /// The descriptor items of each field, see --debug
+    feature_items: Vec<items::FeatureItem>,
+}
+
+fn list_reports<R: Report>(reports: &[R], context: ListContext) -> Result<()> {
+    let ListContext {
+        path,
+        report_type,
+        filter,
+        collection,
+        usage,
+        output,
+        offline,
+        backend,
+        decoded,
+        mut feature_items,
+    } = context;
+    let template = &output.template;
+    let (format, radix, print0) = (output.format, output.radix, output.print0);
+
+    if reports.is_empty() {
+        if format == ListFormat::Json {
+            println!("{}", serde_json::json!({ "device": path, "reports": [] }));
+        } else if format != ListFormat::Porcelain {
+            println!(
+                "This device does not have any {} Reports",
+                report_type.name()
+            );
+        }
+        return Ok(());
+    }
+
+    let report_selected = |report| list_report_selected(report, filter, collection);
+    let field_selected = |field: &Field| match (field, usage) {
+        (Field::Constant(_), _) => false,
+        (_, None) => true,
+        (Field::Variable(var), Some(usage)) => usage_matches(&var.usage, usage),
+        (Field::Array(arr), Some(usage)) => arr.usages().iter().any(|u| usage_matches(u, usage)),
+    };
+
+    if output.count_only {
+        let count: usize = reports
+            .iter()
+            .filter(|r| report_selected(*r))
+            .map(|r| r.fields().iter().filter(|f| field_selected(f)).count())
+            .sum();
+        println!("{count}");
+        return Ok(());
+    }
+
+    if output.reports_only && format == ListFormat::Json {
+        let reports: Vec<_> = reports
+            .iter()
+            .filter(|r| report_selected(*r))
+            .map(|report| {
+                serde_json::json!({
+                    "report_id": report.report_id().map(u8::from),
+                    "size": report.size_in_bytes(),
+                    "collection": report_collection(report),
+                    "fields": report.fields().iter().filter(|f| field_selected(f)).count(),
+                })
+            })
+            .collect();
+        let json = serde_json::json!({ "device": path, "reports": reports });
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+
+    if output.reports_only && format == ListFormat::Porcelain {
+        // Report ID, size, number of fields and collection
+        for report in reports.iter().filter(|r| report_selected(*r)) {
+            let fields = report.fields().iter().filter(|f| field_selected(f)).count();
+            println!(
+                "{}",
+                porcelain_line(&[
+                    report
+                        .report_id()
+                        .map(|id| u8::from(id).to_string())
+                        .unwrap_or_default(),
+                    report.size_in_bytes().to_string(),
+                    fields.to_string(),
+                    report_collection(report).unwrap_or_default(),
+                ])
+            );
+        }
+        return Ok(());
+    }
+
+    if output.reports_only {
+        for report in reports.iter().filter(|r| report_selected(*r)) {
+            let id = report
+                .report_id()
+                .map_or("-".into(), |id| u8::from(id).to_string());
+            let fields = report.fields().iter().filter(|f| field_selected(f)).count();
+            let mut line = format!(
+                "Report {id:>3} │ {:>4} bytes │ {fields:>3} field(s)",
+                report.size_in_bytes()
+            );
+            if let Some(collection) = report_collection(report) {
+                line.push_str(&format!(" │ {collection}"));
+            }
+            print_record(&line, print0);
+        }
+        return Ok(());
+    }
+
+    let columns = output.columns.clone().unwrap_or_else(|| {
+        let mut columns = Column::defaults(has_duplicate_report_ids(reports));
+        if offline {
+            columns.retain(|c| ![Column::Value, Column::Bytes].contains(c));
+        }
+        columns
+    });
+
+    // Fetch all reports with a single open device before printing anything,
+    // opening the device is slow for some (e.g. Bluetooth) devices
+    let selected: Vec<_> = reports.iter().filter(|r| report_selected(*r)).collect();
+    let device = backend.map(|b| b.open_device(path)).transpose()?;
+    let fetched = selected
+        .iter()
+        .map(|report| {
+            let Some(device) = &device else {
+                return Ok(decoded.clone());
+            };
+            let report_id = report.report_id().map(u8::from);
+            let r = device
+                .get_report(path, report_type, report_id, report.size_in_bytes())
+                .device_context(path, report_id)?;
+            Ok(Some(r))
+        })
+        .collect::<Result<Vec<Option<Vec<u8>>>>>()?;
+
+    if template.is_none() && format == ListFormat::Table && !print0 {
+        let headers: Vec<String> = columns.iter().map(Column::header).collect();
+
+        cprintln!(Styles::Header, "{}", headers.join(" ┃ "));
+        cprintln!(
+            Styles::Header,
+            "{}",
+            headers
+                .iter()
+                .map(|h| str::repeat("━", h.chars().count()))
+                .collect::<Vec<String>>()
+                .join("━╇━")
+        );
+    }
+
+    let mut env_names = EnvNames::default();
+    let mut json_reports = Vec::new();
+    let mut failures = 0;
+    for (report, values) in selected.into_iter().zip(fetched) {
+        let report_id = report.report_id().map(u8::from);
+        let report_size = report.size_in_bytes();
+        // Offline, the fields are extracted from zeroes (after the Report
+        // ID) but not printed
+        let values = values.unwrap_or_else(|| {
+            let mut zeroes = vec![0; report_size];
+            if let Some(id) = report_id {
+                zeroes[0] = id;
+            }
+            zeroes
+        });
+        let mut json_fields = Vec::new();
+        for field in report.fields() {
+            if !field_selected(field) {
+                continue;
+            }
+
+            // Each slot of an Array field is a row of its own, with the
+            // usage selected by the slot's current value
+            let (min, max, slots) = match field {
+                Field::Variable(var) => (
+                    i32::from(var.logical_minimum),
+                    i32::from(var.logical_maximum) as u32,
+                    vec![(
+                        usage_name(&var.usage),
+                        Some(&var.usage),
+                        var.bits.clone(),
+                        var.extract(&values).map(i32::from),
+                    )],
+                ),
+                Field::Array(arr) => {
+                    let min = i32::from(arr.logical_minimum);
+                    let count = usize::from(arr.report_count).max(1);
+                    let size = arr.bits.len() / count;
+                    let slots = (0..count)
+                        .map(|idx| {
+                            let start = arr.bits.start + idx * size;
+                            let value = arr.extract_one(&values, idx).map(i32::from);
+                            let usage = value
+                                .as_ref()
+                                .ok()
+                                .and_then(|v| usize::try_from(v - min).ok())
+                                .and_then(|i| arr.usages().get(i));
+                            let name = match usage {
+                                _ if offline => format!("<one of {} usages>", arr.usages().len()),
+                                Some(usage) => usage_name(usage),
+                                None => "<none>".into(),
+                            };
+                            let usage = usage.filter(|_| !offline);
+                            (
+                                format!("{name} (array slot {idx})"),
+                                usage,
+                                start..start + size,
+                                value,
+                            )
+                        })
+                        .collect();
+                    (min, i32::from(arr.logical_maximum) as u32, slots)
+                }
+                _ => continue,
+            };
+
+            for (hutstr, usage, bits, value) in slots {
+                let offset = bits.start / 8;
+                let end = (bits.end - 1) / 8;
+                let value = value.map_err(anyhow::Error::from).and_then(|v| {
+                    if end < values.len() {
+                        Ok(v)
+                    } else {
+                        Err(anyhow!("field exceeds the report"))
+                    }
+                });
+                let value = match value {
+                    Ok(value) => value,
+                    Err(error) => {
+                        eprintln!(
+                            "Warning: Unable to extract {hutstr} at bits {}..={} of report {}: {error} (declared size {report_size} bytes, fetched {} bytes)",
+                            bits.start,
+                            bits.end - 1,
+                            report_id.unwrap_or(0),
+                            values.len(),
+                        );
+                        failures += 1;
+                        continue;
+                    }
+                };
+
+                if let Some(feature_item) = feature_items.iter_mut().find(|i| {
+                    i.report_id == report.report_id().map(u8::from) && i.bits.contains(&bits.start)
+                }) {
+                    for item in feature_item.items.drain(..) {
+                        cprintln!(Styles::Header, "  # {item}");
+                    }
+                }
+
+                let row = FieldRow {
+                    report_id,
+                    collection: application_collection(field).unwrap_or_default(),
+                    usage: hutstr,
+                    usage_code: usage.map(|u| (u16::from(u.usage_page), u16::from(u.usage_id))),
+                    bits,
+                    logical_minimum: min,
+                    logical_maximum: max,
+                    count: 1,
+                    value: (!offline).then_some(value),
+                    radix,
+                    bit_order: output.bit_order,
+                    bytes: match offline {
+                        true => Vec::new(),
+                        false => values[offset..=end].to_vec(),
+                    },
+                };
+                let line = match template {
+                    Some(template) => row.format_template(template)?,
+                    None if format == ListFormat::Json => {
+                        json_fields.push(row.to_json());
+                        continue;
+                    }
+                    None if format == ListFormat::Porcelain => row.to_porcelain(),
+                    None if format == ListFormat::Env => {
+                        let Field::Variable(var) = field else {
+                            continue;
+                        };
+                        env_names.line(&var.usage, &row.formatted_value())
+                    }
+                    None => columns
+                        .iter()
+                        .map(|c| c.format(&row))
+                        .collect::<Vec<String>>()
+                        .join(" │ "),
+                };
+                print_record(&line, print0);
+            }
+        }
+        if format == ListFormat::Json {
+            json_reports.push(serde_json::json!({
+                "report_id": report.report_id().map(u8::from),
+                "size": report_size,
+                "collection": report_collection(report),
+                "bytes": (!offline).then_some(values),
+                "fields": json_fields,
+            }));
+        }
+    }
+
+    if format == ListFormat::Json {
+        let json = serde_json::json!({ "device": path, "reports": json_reports });
+        println!("{}", serde_json::to_string_pretty(&json)?);
+    }
+
+    if failures > 0 {
+        bail!("Unable to extract {failures} field(s)");
+    }
+
+    Ok(())
+}
+
+/// Reads a whole file, "-" is stdin
+fn read_file_or_stdin(file: &Path) -> Result<Vec<u8>> {
+    if file.as_os_str() == "-" {
+        let mut contents = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut contents)?;
+        Ok(contents)
+    } else {
+        std::fs::read(file).with_context(|| format!("Unable to read {}", file.display()))
+    }
+}
+
+/// Reads a report descriptor file as accepted by --rdesc-file, "-" is stdin
+fn read_rdesc_file(file: &Path) -> Result<Vec<u8>> {
+    parse_report_descriptor(&read_file_or_stdin(file)?)
+}
+
+/// Prints the fields of a Feature Report from the given bytes instead of
+/// the device's current values, e.g. bytes captured with a logic analyzer.
+/// The bytes may or may not start with the Report ID.
+fn decode(
+    path: &Path,
+    rdesc: Vec<u8>,
+    report_id: Option<u8>,
+    usage: &Option<String>,
+    bytes: &[String],
+    output: &ListOutput,
+) -> Result<()> {
+    let mut bytes = parse_byte_list(bytes, Radix::Hex)?
+        .into_iter()
+        .map(|b| match b {
+            Some(MaskedByte { value, mask: 0xff }) => Ok(value),
+            _ => bail!("Decoding needs the value of every byte, 'xx' and masks are not supported"),
+        })
+        .collect::<Result<Vec<u8>>>()?;
+    // Report ID 0 is reserved, like for --report-id in 'list' it stands
+    // for the report without a Report ID
+    let report_id = report_id.filter(|id| *id != 0);
+    let descriptor = ReportDescriptor::try_from(&rdesc)?;
+    let reports = descriptor.feature_reports();
+    let Some(report) = reports
+        .iter()
+        .find(|r| r.report_id().map(u8::from) == report_id)
+    else {
+        let ids = reports
+            .iter()
+            .map(|r| r.report_id().map_or(0, u8::from).to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        match report_id {
+            _ if reports.is_empty() => bail!("This device does not have any Feature Reports"),
+            Some(id) => bail!("Unable to find report {id}, use --report-id with one of {ids}"),
+            None => bail!("This device uses Report IDs, use --report-id with one of {ids}"),
+        }
+    };
+    let size = report.size_in_bytes();
+    if let Some(id) = report_id {
+        if bytes.len() < size || bytes.first() != Some(&id) {
+            bytes.insert(0, id);
+        }
+    }
+    if bytes.len() != size {
+        eprintln!(
+            "Warning: Report {} has {size} bytes but {} bytes were given",
+            report_id.unwrap_or(0),
+            bytes.len()
+        );
+    }
+
+    let id = report_id.unwrap_or(0);
+    let filter = ReportFilter {
+        include: vec![ReportIdRange {
+            first: id,
+            last: id,
+        }],
+        exclude: Vec::new(),
+    };
+    list(
+        path,
+        ReportType::Feature,
+        &filter,
+        &None,
+        usage,
+        output,
+        ListSource::Bytes { rdesc, bytes },
+    )
+}
+
+/// Sends an Output Report with the bytes patched into a report of zeroes
+/// like 'set' patches them into the current Feature Report
+fn send_output(
+    path: &Path,
+    selection: &ReportSelection,
+    bytes: &[String],
+    offset: usize,
+) -> Result<()> {
+    let rdesc = report_descriptor(path)?;
+    let reports = rdesc.output_reports();
+    if reports.is_empty() {
+        bail!("This device does not have any Output Reports");
+    }
+    let report = selection.select(reports)?;
+    let bytes = parse_byte_list(bytes, Radix::Hex)?;
+
+    // Like the Feature Report ioctls, a write always starts with the
+    // Report ID, 0 for a report without one
+    let rid = report.report_id().map_or(0, u8::from);
+    let (size, rid_off) = match report.report_id() {
+        Some(_) => (report.size_in_bytes(), 0),
+        None => (report.size_in_bytes() + 1, 1),
+    };
+    let mut values = vec![0u8; size];
+    values[0] = rid;
+    for (i, val) in bytes.iter().enumerate() {
+        let idx = offset + rid_off + i;
+        if idx >= size {
+            bail!("Byte {} is outside of the {size}-byte report", offset + i);
+        }
+        if let Some(val) = val {
+            values[idx] = val.apply(values[idx]);
+        }
+    }
+    send_output_report(path, &values)
+}
+
+/// Kernel drivers that write Feature Reports themselves, e.g. when the
+/// device is probed or resumed, and the usages they manage
+#[cfg(target_os = "linux")]
+const DRIVER_USAGES: &[(&str, &[&str])] = &[
+    (
+        "hid-multitouch",
+        &[
+            "Digitizers / Device Mode",
+            "Digitizers / Surface Switch",
+            "Digitizers / Button Switch",
+            "Digitizers / Latency Mode",
+        ],
+    ),
+    ("wacom", &["Digitizers / Device Mode"]),
+    ("hid-sensor-hub", &["Sensors / *"]),
+];
+
+/// Prints a warning if the driver bound to the device is known to manage
+/// any of the report's fields, a value written by us may then be
+/// overwritten by the driver at any time.
+#[cfg(target_os = "linux")]
+fn warn_driver_conflicts<R: Report>(path: &Path, report: &R) {
+    let Some(file) = path.file_name().map(|f| f.to_string_lossy().to_string()) else {
+        return;
+    };
+    let Some(driver) = hidraw_uevent(&file)
+        .ok()
+        .and_then(|uevent| uevent.get("DRIVER").cloned())
+    else {
+        return;
+    };
+    let Some((_, managed)) = DRIVER_USAGES.iter().find(|(name, _)| *name == driver) else {
+        return;
+    };
+    let usages: Vec<String> = report
+        .fields()
+        .iter()
+        .filter_map(|f| match f {
+            Field::Variable(var) => Some(&var.usage),
+            _ => None,
+        })
+        .filter(|usage| managed.iter().any(|name| usage_matches(usage, name)))
+        .map(usage_name)
+        .collect();
+    if !usages.is_empty() {
+        eprintln!(
+            "Warning: the {driver} kernel driver manages {} and may overwrite the new value",
+            usages.join(", ")
+        );
+    }
+}
+
+/// How often and how fast `get --samples` reads a field
+struct Sampling {
+    samples: u32,
+    interval: Duration,
+}
+
+/// Reads the field repeatedly and prints statistics of its logical value
+fn print_field_stats(
+    handle: &FeatureReportHandle,
+    var: &VariableField,
+    sampling: &Sampling,
+    porcelain: bool,
+) -> Result<()> {
+    let mut values = Vec::new();
+    for n in 0..sampling.samples {
+        if n > 0 {
+            std::thread::sleep(sampling.interval);
+        }
+        let bytes = handle.read()?;
+        values.push(i32::from(var.extract(&bytes)?) as f64);
+    }
+
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    // The sample standard deviation, 0 for a single sample
+    let stddev = match values.len() {
+        1 => 0.0,
+        _ => (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (count - 1.0)).sqrt(),
+    };
+    if porcelain {
+        println!(
+            "{}",
+            porcelain_line(&[
+                values.len().to_string(),
+                format!("{mean:.3}"),
+                min.to_string(),
+                max.to_string(),
+                format!("{stddev:.3}"),
+            ])
+        );
+    } else {
+        println!("Samples: {}", values.len());
+        println!("Mean:    {mean:.3}");
+        println!("Min:     {min}");
+        println!("Max:     {max}");
+        println!("Stddev:  {stddev:.3}");
+    }
+    Ok(())
+}
+
+/// How `get` prints the report
+#[derive(Default)]
+struct GetOutput {
+    /// Do not print the Report ID
+    no_report_id: bool,
+    porcelain: bool,
+    /// Print statistics of the field instead, see [print_field_stats]
+    sampling: Option<Sampling>,
+    /// Print nothing, return [FieldIsZero] if the field is zero
+    exit_status: bool,
+    /// Write the bytes to this file instead, see [write_atomically]
+    save: Option<PathBuf>,
+    /// Print the fields with this template instead, see [template_lines]
+    template: Option<String>,
+    format: GetFormat,
+    /// The radix of the values with a template or [GetFormat::Env]
+    radix: Radix,
+}
+
+/// Returned by 'get --exit-status' if the field is zero, the process
+/// exits with status 1 without printing an error
+#[derive(Debug)]
+struct FieldIsZero;
+
+impl std::fmt::Display for FieldIsZero {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "The field is zero")
+    }
+}
+
+impl std::error::Error for FieldIsZero {}
+
+fn get(
+    backend: &dyn HidBackend,
+    path: &Path,
+    report_type: ReportType,
+    selection: &ReportSelection,
+    field: &Option<String>,
+    output: &GetOutput,
+) -> Result<()> {
+    let rdesc = backend.report_descriptor(path).device_context(path, None)?;
+    let rdesc = ReportDescriptor::try_from(&rdesc)?;
+    match report_type {
+        ReportType::Feature => get_report(
+            backend,
+            path,
+            report_type,
+            rdesc.feature_reports(),
+            selection,
+            field,
+            output,
+        ),
+        ReportType::Input => get_report(
+            backend,
+            path,
+            report_type,
+            rdesc.input_reports(),
+            selection,
+            field,
+            output,
+        ),
+        ReportType::Output => get_report(
+            backend,
+            path,
+            report_type,
+            rdesc.output_reports(),
+            selection,
+            field,
+            output,
+        ),
+    }
+}
+
+fn get_report<R: Report>(
+    backend: &dyn HidBackend,
+    path: &Path,
+    report_type: ReportType,
+    reports: &[R],
+    selection: &ReportSelection,
+    field: &Option<String>,
+    output: &GetOutput,
+) -> Result<()> {
+    if reports.is_empty() {
+        bail!(
+            "This device does not have any {} Reports",
+            report_type.name()
+        );
+    }
+    let (no_report_id, porcelain) = (output.no_report_id, output.porcelain);
+
+    let (report, var) = match field {
+        Some(name) => {
+            let (report, var) = find_variable_field(reports, name, |r| selection.matches(r))?;
+            (report, Some(var))
+        }
+        None => (selection.select(reports)?, None),
+    };
+    let handle = FeatureReportHandle::new(backend, path, report).with_report_type(report_type);
+    if let (Some(var), Some(sampling)) = (var, &output.sampling) {
+        return print_field_stats(&handle, var, sampling, porcelain);
+    }
+    let bits = var.map(|var| var.bits.clone());
+
+    let rid = report.report_id().map_or(0, u8::from);
+    let bytes = &handle
+        .read()
+        .device_context(path, report.report_id().map(u8::from))?;
+
+    if let (Some(var), true) = (var, output.exit_status) {
+        return match i32::from(var.extract(bytes)?) {
+            0 => Err(FieldIsZero.into()),
+            _ => Ok(()),
+        };
+    }
+
+    let selected = |f: &Field| var.is_none_or(|var| f.bits() == &var.bits);
+    if let Some(template) = &output.template {
+        for line in template_lines(report, None, bytes, selected, template, output.radix)? {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+    if output.format == GetFormat::Env {
+        for line in env_lines(report, bytes, selected, output.radix) {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+
+    if let Some(file) = &output.save {
+        write_atomically(file, bytes)?;
+        eprintln!(
+            "Saved {} byte(s) of report {rid} to {}",
+            bytes.len(),
+            file.display()
+        );
+        return Ok(());
+    }
+
+    let report_id = report
+        .report_id()
+        .map(|id| u8::from(id).to_string())
+        .unwrap_or_default();
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    match bits {
+        // Report ID, offset and the bytes covering the field
+        Some(bits) if porcelain => {
+            let first = bits.start / 8;
+            let last = (bits.end - 1) / 8;
+            println!(
+                "{}",
+                porcelain_line(&[report_id, first.to_string(), hex(&bytes[first..=last])])
+            );
+        }
+        // Report ID and the bytes, including the Report ID if any
+        None if porcelain => println!("{}", porcelain_line(&[report_id, hex(bytes)])),
+        // The field's bits include the Report ID, so the first byte
+        // is also the offset for set
+        Some(bits) => {
+            let first = bits.start / 8;
+            let last = (bits.end - 1) / 8;
+            let mut line = String::new();
+            if report.report_id().is_some() {
+                line.push_str(&format!("--report-id={rid} "));
+            }
+            line.push_str(&format!(
+                "--offset={first} {}",
+                print_bytes(&bytes[first..=last])
+            ));
+            println!("{line}");
+        }
+        None if no_report_id && report.report_id().is_some() => {
+            println!("{}", print_bytes(&bytes[1..]))
+        }
+        None => println!("{}", print_bytes(bytes)),
+    }
+
+    Ok(())
+}
+
+/// Prints the report's bytes with the bytes that differ from the previous
+/// bytes highlighted, followed by the Variable fields that changed
+fn print_report_change<R: Report>(
+    report: &R,
+    previous: Option<&[u8]>,
+    bytes: &[u8],
+    time: Duration,
+    radix: Radix,
+) {
+    let id = report.report_id().map_or(0, u8::from);
+    println!(
+        "{:>4}.{:03} report {id}: {}",
+        time.as_secs(),
+        time.subsec_millis(),
+        highlight_changes(bytes, previous)
+    );
+
+    if let Some(previous) = previous {
+        print_changed_fields(report, previous, bytes, "         ", radix);
+    }
+}
+
+/// Formats the bytes as space-separated hex values with the bytes that
+/// differ from the previous bytes highlighted
+fn highlight_changes(bytes: &[u8], previous: Option<&[u8]>) -> String {
+    bytes
+        .iter()
+        .enumerate()
+        .map(|(idx, b)| {
+            let text = format!("{b:02x}");
+            if previous.is_some_and(|p| p.get(idx) != Some(b)) {
+                text.if_supports_color(Stdout, |t| t.style(Styles::Changed.style()))
+                    .to_string()
+            } else {
+                text
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Prints the Variable fields whose values differ between the old and new
+/// bytes of the report as "name: old → new"
+fn print_changed_fields<R: Report>(report: &R, old: &[u8], new: &[u8], indent: &str, radix: Radix) {
+    for field in report.fields() {
+        let Field::Variable(var) = field else {
+            continue;
+        };
+        let (Ok(old), Ok(new)) = (var.extract(old), var.extract(new)) else {
+            continue;
+        };
+        let (old, new) = (i32::from(old), i32::from(new));
+        if old != new {
+            let bits = var.bits.len();
+            println!(
+                "{indent}{}: {} → {}",
+                usage_name(&var.usage),
+                radix.format(old, bits),
+                radix.format(new, bits)
+            );
+        }
+    }
+}
+
+/// Returns the Variable fields of the report formatted with the template,
+/// see `list --template`. `selected` picks the fields and, with the
+/// previous bytes, only the fields whose value changed are included.
+fn template_lines<R: Report>(
+    report: &R,
+    previous: Option<&[u8]>,
+    bytes: &[u8],
+    selected: impl Fn(&Field) -> bool,
+    template: &str,
+    radix: Radix,
+) -> Result<Vec<String>> {
+    let report_id = report.report_id().map(u8::from);
+    let value = |field, bytes| FieldRow::variable(field, report_id, bytes, radix);
+    let mut lines = Vec::new();
+    for field in report.fields().iter().filter(|f| selected(f)) {
+        let Some(row) = value(field, bytes) else {
+            continue;
+        };
+        if previous.is_some_and(|p| value(field, p).is_some_and(|old| old.value == row.value)) {
+            continue;
+        }
+        lines.push(row.format_template(template)?);
+    }
+    Ok(lines)
+}
+
+/// Returns the selected Variable fields of the report as HID_<USAGE>=<value>
+/// lines, see [EnvNames]
+fn env_lines<R: Report>(
+    report: &R,
+    bytes: &[u8],
+    selected: impl Fn(&Field) -> bool,
+    radix: Radix,
+) -> Vec<String> {
+    let report_id = report.report_id().map(u8::from);
+    let mut names = EnvNames::default();
+    report
+        .fields()
+        .iter()
+        .filter(|f| selected(f))
+        .filter_map(|field| match field {
+            Field::Variable(var) => {
+                let row = FieldRow::variable(field, report_id, bytes, radix)?;
+                Some(names.line(&var.usage, &row.formatted_value()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// A field to notify about in 'watch': any change of its value or, with
+/// a threshold, the value dropping below or rising above the threshold
+#[derive(Clone, Debug)]
+struct NotifyCondition {
+    name: String,
+    threshold: Option<(char, i64)>,
+}
+
+impl std::str::FromStr for NotifyCondition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, threshold) = match s.find(['<', '>']) {
+            Some(idx) => {
+                let (name, threshold) = s.split_at(idx);
+                let value = threshold[1..]
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid threshold in '{s}'"))?;
+                (name, Some((threshold.as_bytes()[0] as char, value)))
+            }
+            None => (s, None),
+        };
+        if name.trim().is_empty() {
+            bail!("Missing field name in '{s}'");
+        }
+        Ok(NotifyCondition {
+            name: name.trim().to_string(),
+            threshold,
+        })
+    }
+}
+
+impl NotifyCondition {
+    /// Returns true if the change from `old` to `new` should be notified
+    fn triggers(&self, old: i64, new: i64) -> bool {
+        let matches = |v: i64| match self.threshold {
+            Some(('<', threshold)) => v < threshold,
+            Some((_, threshold)) => v > threshold,
+            None => true,
+        };
+        old != new && matches(new) && (self.threshold.is_none() || !matches(old))
+    }
+}
+
+/// Shows a desktop notification for each changed field that one of the
+/// conditions selects
+fn notify_changes<R: Report>(
+    device: &str,
+    report: &R,
+    previous: &[u8],
+    bytes: &[u8],
+    conditions: &[NotifyCondition],
+) {
+    for field in report.fields() {
+        let Field::Variable(var) = field else {
+            continue;
+        };
+        let (Ok(old), Ok(new)) = (var.extract(previous), var.extract(bytes)) else {
+            continue;
+        };
+        let (old, new) = (i32::from(old) as i64, i32::from(new) as i64);
+        if !conditions
+            .iter()
+            .any(|c| usage_matches(&var.usage, &c.name) && c.triggers(old, new))
+        {
+            continue;
+        }
+        let body = format!("{}: {old} → {new}", usage_name(&var.usage));
+        let status = std::process::Command::new("notify-send")
+            .args(["--app-name=hid-feature", device, &body])
+            .status();
+        if !status.is_ok_and(|s| s.success()) {
+            eprintln!("Warning: Unable to show the notification with notify-send");
+        }
+    }
+}
+
+/// The last values of the Variable fields of the watched reports, see
+/// `watch --show-last`
+struct WatchHistory {
+    /// The number of values kept per field
+    size: usize,
+    fields: Vec<FieldHistory>,
+}
+
+struct FieldHistory {
+    /// The index of the field's report among the watched reports
+    report: usize,
+    report_id: u8,
+    var: VariableField,
+    /// The values, oldest first, with the time they were first fetched
+    values: std::collections::VecDeque<(Duration, i32)>,
+}
+
+impl WatchHistory {
+    fn new<R: Report>(reports: &[&R], size: usize) -> WatchHistory {
+        let fields = reports
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, report)| {
+                report.fields().iter().filter_map(move |f| match f {
+                    Field::Variable(var) => Some(FieldHistory {
+                        report: idx,
+                        report_id: report.report_id().map_or(0, u8::from),
+                        var: var.clone(),
+                        values: Default::default(),
+                    }),
+                    _ => None,
+                })
+            })
+            .collect();
+        WatchHistory { size, fields }
+    }
+
+    /// Adds the values of the report's fields that differ from their last
+    /// value, dropping the oldest values beyond the size
+    fn record(&mut self, report: usize, bytes: &[u8], time: Duration) {
+        for field in self.fields.iter_mut().filter(|f| f.report == report) {
+            let Ok(value) = field.var.extract(bytes).map(i32::from) else {
+                continue;
+            };
+            if field.values.back().is_some_and(|(_, v)| *v == value) {
+                continue;
+            }
+            if field.values.len() == self.size {
+                field.values.pop_front();
+            }
+            field.values.push_back((time, value));
+        }
+    }
+
+    /// Returns one line per field with its values
+    fn lines(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .map(|field| {
+                let values: Vec<String> = field
+                    .values
+                    .iter()
+                    .map(|(time, value)| {
+                        format!("{value} ({}.{:03})", time.as_secs(), time.subsec_millis())
+                    })
+                    .collect();
+                format!(
+                    "report {} {}: {}",
+                    field.report_id,
+                    usage_name(&field.var.usage),
+                    values.join(", ")
+                )
+            })
+            .collect()
+    }
+
+    fn print(&self) {
+        println!("Last {} value(s) of each field:", self.size);
+        for line in self.lines() {
+            println!("  {line}");
+        }
+    }
+}
+
+/// Publishes the Variable fields whose values differ between the previous
+/// and the new bytes of the report, see `watch --mqtt`
+fn publish_changes<R: Report>(
+    publisher: &mut mqtt::MqttPublisher,
+    device: &serde_json::Value,
+    report: &R,
+    previous: &[u8],
+    bytes: &[u8],
+) {
+    for field in report.fields() {
+        let Field::Variable(var) = field else {
+            continue;
+        };
+        let (Ok(old), Ok(new)) = (var.extract(previous), var.extract(bytes)) else {
+            continue;
+        };
+        let (old, new) = (i32::from(old), i32::from(new));
+        if old == new {
+            continue;
+        }
+        let message = serde_json::json!({
+            "device": device,
+            "report_id": report.report_id().map(u8::from),
+            "field": usage_name(&var.usage),
+            "previous": old,
+            "value": new,
+        });
+        if let Err(e) = publisher.publish(message.to_string().as_bytes()) {
+            eprintln!("Warning: Unable to publish to MQTT: {e:#}");
+        }
+    }
+}
+
+/// How [watch] fetches the reports and what it does with their changes
+struct WatchOptions {
+    /// How often to fetch the reports
+    interval: Duration,
+    /// Stop after this duration, None to watch until interrupted
+    duration: Option<Duration>,
+    notify: Vec<NotifyCondition>,
+    /// The number of values to keep per field, see [WatchHistory]
+    show_last: Option<usize>,
+    /// Where to publish the changed fields, see [publish_changes]
+    mqtt: Option<mqtt::MqttPublisher>,
+    /// Print the fields with this template, see [template_lines]
+    template: Option<String>,
+    /// The radix of the field values
+    radix: Radix,
+}
+
+/// Fetches the Feature Reports every interval and prints those that
+/// changed, see [print_report_change]. On a terminal, r fetches the reports
+/// immediately, s prints when each report was last fetched, h prints the
+/// [WatchHistory] and q quits.
+fn watch(
+    backend: &dyn HidBackend,
+    path: &Path,
+    filter: &ReportFilter,
+    options: WatchOptions,
+) -> Result<()> {
+    let WatchOptions {
+        interval,
+        duration,
+        ref notify,
+        show_last,
+        mut mqtt,
+        ref template,
+        radix,
+    } = options;
+    let rdesc = backend.report_descriptor(path).device_context(path, None)?;
+    let rdesc = ReportDescriptor::try_from(&rdesc)?;
+    let reports: Vec<_> = rdesc
+        .feature_reports()
+        .iter()
+        .filter(|r| filter.matches(r.report_id().map(u8::from)))
+        .collect();
+    if reports.is_empty() {
+        bail!("No Feature Reports to watch");
+    }
+    // The reports are only fetched every interval or on request, the
+    // cache keeps them with the time they were fetched
+    let cache = CachingBackend::new(backend, interval);
+
+    let info = path_device(path);
+    let device = info
+        .as_ref()
+        .map_or_else(|| path.display().to_string(), |d| d.name.clone());
+    // The device identity in the MQTT messages
+    let identity = serde_json::json!({
+        "path": path,
+        "name": info.as_ref().map(|d| &d.name),
+        "id": info.as_ref().map(|d| format!("{:04x}:{:04x}", d.vid, d.pid)),
+        "serial": info.as_ref().and_then(|d| d.serial.as_ref()),
+    });
+    let start = std::time::Instant::now();
+    let mut previous: Vec<Option<Vec<u8>>> = vec![None; reports.len()];
+    let mut history = show_last.map(|size| WatchHistory::new(&reports, size));
+    let fetch = |previous: &mut [Option<Vec<u8>>],
+                 history: &mut Option<WatchHistory>,
+                 mqtt: &mut Option<mqtt::MqttPublisher>|
+     -> Result<()> {
+        let time = start.elapsed();
+        for (idx, (report, previous)) in reports.iter().zip(previous).enumerate() {
+            let report_id = report.report_id().map(u8::from);
+            let bytes = cache
+                .refresh(path, ReportType::Feature, report_id, report.size_in_bytes())
+                .device_context(path, report_id)?;
+            if previous.as_ref() != Some(&bytes) {
+                match template {
+                    Some(template) => {
+                        let lines = template_lines(
+                            *report,
+                            previous.as_deref(),
+                            &bytes,
+                            |_| true,
+                            template,
+                            radix,
+                        )?;
+                        for line in lines {
+                            println!("{line}");
+                        }
+                    }
+                    None => print_report_change(*report, previous.as_deref(), &bytes, time, radix),
+                }
+                if let Some(previous) = previous.as_deref() {
+                    notify_changes(&device, *report, previous, &bytes, notify);
+                    if let Some(mqtt) = mqtt.as_mut() {
+                        publish_changes(mqtt, &identity, *report, previous, &bytes);
+                    }
+                }
+                if let Some(history) = history.as_mut() {
+                    history.record(idx, &bytes, time);
+                }
+                *previous = Some(bytes);
+            }
+        }
+        Ok(())
+    };
+
+    let keys = tui::KeyInput::enable()?;
+    if keys.is_some() {
+        match show_last {
+            Some(_) => eprintln!(
+                "Press r to fetch the reports now, s for their age, h for the last values, q to quit"
+            ),
+            None => eprintln!("Press r to fetch the reports now, s for their age, q to quit"),
+        }
+    }
+    // The values kept with --show-last are printed even if fetching fails,
+    // e.g. because the device was unplugged
+    let result = (|| -> Result<()> {
+        let mut next_fetch = start;
+        'watch: loop {
+            fetch(&mut previous, &mut history, &mut mqtt)?;
+            next_fetch += interval;
+            if duration.is_some_and(|d| next_fetch > start + d) {
+                break;
+            }
+            let Some(keys) = &keys else {
+                std::thread::sleep(next_fetch.saturating_duration_since(std::time::Instant::now()));
+                continue;
+            };
+            while let Some(key) =
+                keys.wait(next_fetch.saturating_duration_since(std::time::Instant::now()))?
+            {
+                match key {
+                    tui::Key::Char('r') => fetch(&mut previous, &mut history, &mut mqtt)?,
+                    tui::Key::Char('s') => {
+                        for report in &reports {
+                            let report_id = report.report_id().map(u8::from);
+                            if let Some(cached) = cache.cached(path, ReportType::Feature, report_id)
+                            {
+                                println!(
+                                    "Report {}: {} (fetched {:.1}s ago)",
+                                    report_id.unwrap_or(0),
+                                    print_bytes(&cached.bytes),
+                                    cached.age().as_secs_f64()
+                                );
+                            }
+                        }
+                    }
+                    tui::Key::Char('h') => {
+                        if let Some(history) = &history {
+                            history.print();
+                        }
+                    }
+                    tui::Key::Char('q') | tui::Key::Quit => break 'watch,
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    })();
+    if let Some(history) = &history {
+        history.print();
+    }
+    result
+}
+
+/// Prints an Input Report's bytes and the values of its fields, Array
+/// fields as the usages they select
+fn print_input_report<R: Report>(report: &R, bytes: &[u8], usage: &Option<String>, time: Duration) {
+    let id = report.report_id().map_or(0, u8::from);
+    println!(
+        "{:>4}.{:03} report {id}: {}",
+        time.as_secs(),
+        time.subsec_millis(),
+        print_bytes(bytes)
+    );
+    let usage_selected = |u: &Usage| usage.as_ref().is_none_or(|name| usage_matches(u, name));
+    for field in report.fields() {
+        match field {
+            Field::Variable(var) if usage_selected(&var.usage) => {
+                if let Ok(value) = var.extract(bytes) {
+                    println!("         {}: {}", usage_name(&var.usage), i32::from(value));
+                }
+            }
+            Field::Array(arr) => {
+                let min = i32::from(arr.logical_minimum);
+                let Ok(values) = arr.extract(bytes) else {
+                    continue;
+                };
+                let selected: Vec<String> = values
+                    .into_iter()
+                    .filter_map(|v| usize::try_from(i32::from(v) - min).ok())
+                    .filter_map(|idx| arr.usages().get(idx))
+                    .filter(|u| usage_selected(u))
+                    .map(usage_name)
+                    .collect();
+                if !selected.is_empty() {
+                    println!("         {}", selected.join(", "));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reads Input Reports from the device and prints them, see
+/// [print_input_report]
+fn read_input(
+    path: &Path,
+    filter: &ReportFilter,
+    usage: &Option<String>,
+    count: Option<usize>,
+) -> Result<()> {
+    let rdesc = report_descriptor(path)?;
+    if rdesc.input_reports().is_empty() {
+        bail!("This device does not have any Input Reports");
+    }
+    let mut device = std::fs::File::open(path)
+        .with_context(|| format!("Unable to open {} for reading", path.display()))?;
+
+    let start = std::time::Instant::now();
+    let mut buf = vec![0u8; 16384];
+    let mut printed = 0;
+    while count.is_none_or(|count| printed < count) {
+        let size = std::io::Read::read(&mut device, &mut buf)?;
+        if size == 0 {
+            break;
+        }
+        let bytes = &buf[..size];
+        let Some(report) = rdesc.find_input_report(bytes) else {
+            eprintln!("Warning: Unknown Input Report: {}", print_bytes(bytes));
+            continue;
+        };
+        if !filter.matches(report.report_id().map(u8::from)) {
+            continue;
+        }
+        print_input_report(report, bytes, usage, start.elapsed());
+        printed += 1;
+    }
+
+    Ok(())
+}
+
+/// A value to write into some bits of a report, computed by an expression
+/// from the bits' current value, see [expr::Expr]
+struct Assignment {
+    /// The field or byte being assigned, for error messages
+    target: String,
+    bits: std::ops::Range<usize>,
+    expr: expr::Expr,
+    /// The values the result must be within
+    range: std::ops::RangeInclusive<i64>,
+    /// True if the current value is sign-extended
+    signed: bool,
+    /// The byte order of a multi-byte value, the bits of a big-endian
+    /// value must be whole bytes
+    byte_order: ByteOrder,
+}
+
+impl Assignment {
+    /// Evaluates the expression with the current value in the bytes and
+    /// writes the result into the bytes
+    fn apply(&self, bytes: &mut [u8]) -> Result<()> {
+        // A big-endian value is swapped to little-endian and back
+        let swap = |bytes: &mut [u8]| {
+            if self.byte_order == ByteOrder::Big {
+                bytes[self.bits.start / 8..self.bits.end / 8].reverse();
+            }
+        };
+        swap(bytes);
+        let result = self.apply_le(bytes);
+        swap(bytes);
+        result
+    }
+
+    fn apply_le(&self, bytes: &mut [u8]) -> Result<()> {
+        let current = unpack_field(bytes, &self.bits, self.signed);
+        let value = self
+            .expr
+            .eval(current)
+            .with_context(|| format!("Unable to compute the value for {}", self.target))?;
+        if !self.range.contains(&value) {
+            bail!(
+                "Value {value} for {} is outside the range {}..={}",
+                self.target,
+                self.range.start(),
+                self.range.end()
+            );
+        }
+        pack_field(bytes, &self.bits, value as u32);
+        Ok(())
+    }
+}
+
+/// Returns the assignments for "name=expression" assignments to the
+/// report's Variable fields. The result must be within the field's logical
+/// range, `cur` in the expression is the field's current logical value.
+fn field_assignments<R: Report>(report: &R, assignments: &[String]) -> Result<Vec<Assignment>> {
+    assignments
+        .iter()
+        .map(|assignment| {
+            let Some((name, value)) = assignment.split_once('=') else {
+                bail!("Invalid field assignment '{assignment}', expected NAME=VALUE");
+            };
+            let (_, var) =
+                find_variable_field(std::slice::from_ref(report), name.trim(), |_| true)?;
+            let target = usage_name(&var.usage);
+            let expr = value
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid value for {target}"))?;
+            let min = i32::from(var.logical_minimum) as i64;
+            let max = i32::from(var.logical_maximum) as i64;
+            Ok(Assignment {
+                target,
+                bits: var.bits.clone(),
+                expr,
+                range: min..=max,
+                signed: min < 0,
+                byte_order: ByteOrder::Little,
+            })
+        })
+        .collect()
+}
+
+/// Returns the assignments for "index=expression" assignments to bytes,
+/// `cur` in the expression is the byte's current value. The index counts
+/// like --offset.
+fn byte_assignments(assignments: &[String]) -> Result<Vec<Assignment>> {
+    int_assignments(assignments, 1, ByteOrder::Little)
+}
+
+/// Returns the assignments for "index=expression" assignments to unsigned
+/// integers of `size` bytes starting at the byte index, e.g. for --u16
+fn int_assignments(
+    assignments: &[String],
+    size: usize,
+    byte_order: ByteOrder,
+) -> Result<Vec<Assignment>> {
+    let (kind, max) = match size {
+        1 => ("byte".to_string(), 255),
+        _ => (format!("u{}", size * 8), (1i64 << (size * 8)) - 1),
+    };
+    assignments
+        .iter()
+        .map(|assignment| {
+            let Some((index, value)) = assignment.split_once('=') else {
+                bail!("Invalid {kind} assignment '{assignment}', expected INDEX=VALUE");
+            };
+            let index: usize = index
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid byte index '{index}'"))?;
+            let target = match size {
+                1 => format!("byte {index}"),
+                _ => format!("{kind} at byte {index}"),
+            };
+            Ok(Assignment {
+                target,
+                bits: index * 8..(index + size) * 8,
+                expr: value.trim().parse()?,
+                range: 0..=max,
+                signed: false,
+                byte_order,
+            })
+        })
+        .collect()
+}
+
+/// The changes to a report besides its bytes, see [set]
+#[derive(Default)]
+struct ReportEdits<'a> {
+    /// The usages to select in an Array field, see [array_selection]
+    select: &'a [String],
+    /// "name=expression" field assignments, see [field_assignments]
+    fields: &'a [String],
+    /// "index=expression" byte assignments, see [byte_assignments]
+    bytes: &'a [String],
+    /// "index=expression" assignments to 16-bit and 32-bit values, see
+    /// [int_assignments]
+    u16: &'a [String],
+    u32: &'a [String],
+    /// The byte order of the u16 and u32 values
+    byte_order: ByteOrder,
+    /// The bits and the value or expression of a --bit-offset write
+    bits: Option<(std::ops::Range<usize>, &'a str)>,
+}
+
+/// Writes fields by usage name, see [field_assignments]. Without a
+/// --report-id or --collection, the report is the one of the first field.
+fn set_field(
+    path: &Path,
+    selection: &ReportSelection,
+    assignments: &[String],
+    options: &WriteOptions,
+) -> Result<()> {
+    let Some((name, _)) = assignments.first().and_then(|a| a.split_once('=')) else {
+        bail!("At least one NAME=VALUE assignment is required");
+    };
+    let selection = if selection.report_id.is_none() && selection.collection.is_none() {
+        let rdesc = report_descriptor(path)?;
+        let (report, _) = find_variable_field(rdesc.feature_reports(), name.trim(), |_| true)?;
+        ReportSelection {
+            report_id: report.report_id().map(u8::from),
+            collection: report_collection(report),
+            first: false,
+        }
+    } else {
+        ReportSelection {
+            report_id: selection.report_id,
+            collection: selection.collection.clone(),
+            first: selection.first,
+        }
+    };
+    let edits = ReportEdits {
+        fields: assignments,
+        ..Default::default()
+    };
+    set(
+        path,
+        ReportType::Feature,
+        &selection,
+        &[],
+        &edits,
+        0,
+        options,
+    )
+}
+
+/// Packs "name=expression" field assignments into the bytes of a report
+/// and prints them like 'get'. The report starts out as zeroes, so `cur`
+/// is 0. Without a --report-id or --collection, the report is the one of
+/// the first field.
+fn encode(
+    rdesc: &[u8],
+    selection: &ReportSelection,
+    assignments: &[String],
+    no_report_id: bool,
+    porcelain: bool,
+) -> Result<()> {
+    let Some((name, _)) = assignments.first().and_then(|a| a.split_once('=')) else {
+        bail!("At least one NAME=VALUE assignment is required");
+    };
+    let rdesc = ReportDescriptor::try_from(rdesc)?;
+    let reports = rdesc.feature_reports();
+    let report = if selection.report_id.is_none() && selection.collection.is_none() {
+        find_variable_field(reports, name.trim(), |_| true)?.0
+    } else {
+        selection.select(reports)?
+    };
+
+    let mut bytes = vec![0; report.size_in_bytes()];
+    if let Some(id) = report.report_id() {
+        bytes[0] = u8::from(id);
+    }
+    for assignment in field_assignments(report, assignments)? {
+        assignment.apply(&mut bytes)?;
+    }
+
+    let report_id = report.report_id().map(|id| u8::from(id).to_string());
+    if porcelain {
+        let hex = bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+        println!("{}", porcelain_line(&[report_id.unwrap_or_default(), hex]));
+    } else if no_report_id && report_id.is_some() {
+        println!("{}", print_bytes(&bytes[1..]));
+    } else {
+        println!("{}", print_bytes(&bytes));
+    }
+    Ok(())
+}
+
+/// Returns the hidraw devices with a Variable Feature field of this usage,
+/// see [usage_matches]. Devices whose report descriptor cannot be read are
+/// skipped.
+fn devices_with_usage(usage: &str) -> Result<Vec<PathBuf>> {
+    let paths: Vec<PathBuf> =
+        backend()
+            .devices()?
+            .into_iter()
+            .map(|d| d.path)
+            .filter(|path| {
+                report_descriptor(path).is_ok_and(|rdesc| {
+                    rdesc.feature_reports().iter().flat_map(|r| r.fields()).any(
+                        |f| matches!(f, Field::Variable(var) if usage_matches(&var.usage, usage)),
+                    )
+                })
+            })
+            .collect();
+    if paths.is_empty() {
+        bail!("No device has a Feature field '{usage}'");
+    }
+    Ok(paths)
+}
+
+fn selftest(
+    backend: &dyn HidBackend,
+    path: &Path,
+    selection: &ReportSelection,
+    protected: &[&config::Protected],
+) -> Result<()> {
+    let rdesc = ReportDescriptor::try_from(&backend.report_descriptor(path)?)?;
+    let report = selection.select(rdesc.feature_reports())?;
+    let rid = report.report_id().map_or(0, u8::from);
+    let all_bytes: Vec<usize> = (0..report.size_in_bytes()).collect();
+    check_protected(report, protected, &all_bytes)?;
+
+    let device = backend.open_device(path)?;
+    let handle = FeatureReportHandle::new(&*device, path, report);
+    let before = handle.read()?;
+    println!("Read:    {}", print_bytes(&before));
+    handle.write(&before)?;
+    let after = handle.read()?;
+    println!("Re-read: {}", print_bytes(&after));
+
+    let mut differences = 0;
+    for (idx, (b, a)) in before.iter().zip(&after).enumerate() {
+        if b != a {
+            differences += 1;
+            let fields: Vec<String> = report
+                .fields()
+                .iter()
+                .filter(|f| (f.bits().start / 8..=(f.bits().end - 1) / 8).contains(&idx))
+                .map(field_name)
+                .collect();
+            println!("  byte {idx}: {b:02x} → {a:02x} ({})", fields.join(", "));
+        }
+    }
+    if differences > 0 {
+        bail!("Report {rid} changed in {differences} byte(s) after writing back its own bytes");
+    }
+    cprintln!(Styles::Success, "Report {rid} reads back unchanged");
+    Ok(())
+}
+
+/// How [set] writes a report
+struct WriteOptions<'a> {
+    /// The backend the report is read and written through
+    backend: &'a dyn HidBackend,
+    /// How long to wait for a disconnected device, see `set --retry-wait`
+    retry_wait: Duration,
+    hooks: &'a config::Hooks,
+    /// The device's write-protected reports and fields, see [device_protected]
+    protected: Vec<&'a config::Protected>,
+    /// Write protected reports and fields anyway
+    force: bool,
+    /// Skip the write if the report already has the requested values
+    only_changed: bool,
+    /// Print the bytes instead of writing them, see `set --dry-run`
+    dry_run: bool,
+    /// Read the report back after the write, see `set --verify`
+    verify: bool,
+}
+
+/// Fails if the write changes any of the bytes in the report that are
+/// write-protected. `touched` are the indices of the bytes changed by
+/// the write, the bytes include the Report ID if any.
+fn check_protected<R: Report>(
+    report: &R,
+    protected: &[&config::Protected],
+    touched: &[usize],
+) -> Result<()> {
+    let rid = report.report_id().map_or(0, u8::from);
+    for entry in protected {
+        if entry.report_id.is_some_and(|id| id != rid) {
+            continue;
+        }
+        let what = match &entry.field {
+            Some(name) => {
+                let fields: Vec<String> = report
+                    .fields()
+                    .iter()
+                    .filter_map(|f| match f {
+                        Field::Variable(var) if usage_matches(&var.usage, name) => Some(var),
+                        _ => None,
+                    })
+                    .filter(|var| {
+                        let bytes = var.bits.start / 8..=(var.bits.end - 1) / 8;
+                        touched.iter().any(|idx| bytes.contains(idx))
+                    })
+                    .map(|var| usage_name(&var.usage))
+                    .collect();
+                if fields.is_empty() {
+                    continue;
+                }
+                format!("{} in report {rid}", fields.join(", "))
+            }
+            None if touched.is_empty() => continue,
+            None => format!("Report {rid}"),
+        };
+        match &entry.reason {
+            Some(reason) => {
+                bail!("{what} is write-protected ({reason}), use --force to write it anyway")
+            }
+            None => bail!("{what} is write-protected, use --force to write it anyway"),
+        }
+    }
+    Ok(())
+}
+
+/// Selects a report of the given type, see [ReportSelection::select]
+fn select_report<'a, R: Report>(
+    reports: &'a [R],
+    report_type: ReportType,
+    selection: &ReportSelection,
+) -> Result<&'a R> {
+    if reports.is_empty() {
+        bail!(
+            "This device does not have any {} Reports",
+            report_type.name()
+        );
+    }
+    selection.select(reports)
+}
+
+/// Parses a 1-byte value of a byte list. "0x4a", "0b01001010", "0o112"
+/// and "d:74" are hexadecimal, binary, octal and decimal, a value without
+/// a prefix is in the radix.
+fn parse_byte(v: &str, radix: Radix) -> Result<u8> {
+    let (digits, radix) = if let Some(hex) = v.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(bin) = v.strip_prefix("0b") {
+        (bin, 2)
+    } else if let Some(oct) = v.strip_prefix("0o") {
+        (oct, 8)
+    } else if let Some(dec) = v.strip_prefix("d:") {
+        (dec, 10)
+    } else {
+        let radix = match radix {
+            Radix::Hex => 16,
+            Radix::Dec => 10,
+            Radix::Bin => 2,
+        };
+        (v, radix)
+    };
+    u8::from_str_radix(digits, radix)
+        .with_context(|| format!("Invalid value '{v}', must be 'xx' or a 1-byte value"))
+}
+
+/// A byte of a byte list as given to 'set', only the bits in the mask are
+/// written
+#[derive(Clone, Copy, Debug)]
+struct MaskedByte {
+    value: u8,
+    mask: u8,
+}
+
+impl MaskedByte {
+    fn new(value: u8) -> MaskedByte {
+        MaskedByte { value, mask: 0xff }
+    }
+
+    /// Returns the current byte with the bits in the mask replaced
+    fn apply(&self, current: u8) -> u8 {
+        current & !self.mask | self.value
+    }
+}
+
+/// Returns the byte list for [set] that writes each value into the bits of
+/// its field and leaves the other bits of the `size`-byte report alone
+fn field_bytes<'a>(
+    size: usize,
+    fields: impl IntoIterator<Item = (&'a std::ops::Range<usize>, u32)>,
+) -> Vec<Option<MaskedByte>> {
+    let mut values = vec![0; size];
+    let mut masks = vec![0; size];
+    for (bits, value) in fields {
+        pack_field(&mut values, bits, value);
+        pack_field(&mut masks, bits, u32::MAX);
+    }
+    values
+        .iter()
+        .zip(&masks)
+        .map(|(value, mask)| {
+            (*mask != 0).then_some(MaskedByte {
+                value: value & mask,
+                mask: *mask,
+            })
+        })
+        .collect()
+}
+
+/// Parses the values of a byte list as given to 'set', `None` for an
+/// 'xx'. See [parse_byte] for the 1-byte values. A "value/mask" value,
+/// e.g. "80/c0", only changes the bits in the mask. An "le:" or "be:" value
+/// is expanded into single bytes, e.g. "le:1f40" into 0x40 and 0x1f. The
+/// number of hex digits is the size of the value, 4 for 16 bits and 8 for
+/// 32 bits. Any value may be repeated with a decimal count, e.g. "00*60"
+/// or "xx*8".
+fn parse_byte_list(bytes: &[String], radix: Radix) -> Result<Vec<Option<MaskedByte>>> {
+    let mut parsed = Vec::new();
+    for v in bytes {
+        let (value, count) = match v.rsplit_once('*') {
+            Some((value, count)) => {
+                let count: usize = count
+                    .parse()
+                    .with_context(|| format!("Invalid repeat count in '{v}'"))?;
+                (value, count)
+            }
+            None => (v.as_str(), 1),
+        };
+        let value = parse_byte_list_value(value, radix)?;
+        if value
+            .len()
+            .saturating_mul(count)
+            .saturating_add(parsed.len())
+            > MAX_REPORT_SIZE
+        {
+            bail!("The byte list exceeds the maximum report size of {MAX_REPORT_SIZE} bytes");
+        }
+        for _ in 0..count {
+            parsed.extend_from_slice(&value);
+        }
+    }
+    Ok(parsed)
+}
+
+/// Parses a single value of a byte list, see [parse_byte_list]
+fn parse_byte_list_value(v: &str, radix: Radix) -> Result<Vec<Option<MaskedByte>>> {
+    let (digits, big_endian) = match (v.strip_prefix("le:"), v.strip_prefix("be:")) {
+        (Some(digits), _) => (digits, false),
+        (_, Some(digits)) => (digits, true),
+        _ if v == "xx" => return Ok(vec![None]),
+        _ => {
+            let byte = match v.split_once('/') {
+                Some((value, mask)) => {
+                    let (value, mask) = (parse_byte(value, radix)?, parse_byte(mask, radix)?);
+                    if value & !mask != 0 {
+                        bail!("Invalid value '{v}', the value has bits outside of the mask");
+                    }
+                    MaskedByte { value, mask }
+                }
+                None => MaskedByte::new(parse_byte(v, radix)?),
+            };
+            return Ok(vec![Some(byte)]);
+        }
+    };
+    if !matches!(digits.len(), 4 | 8) {
+        bail!("Invalid value '{v}', expected 4 or 8 hex digits after the prefix");
+    }
+    let value = u32::from_str_radix(digits, 16)
+        .with_context(|| format!("Invalid value '{v}', expected hex digits"))?;
+    let mut le = value.to_le_bytes()[..digits.len() / 2].to_vec();
+    if big_endian {
+        le.reverse();
+    }
+    Ok(le.into_iter().map(|b| Some(MaskedByte::new(b))).collect())
+}
+
+fn set(
+    path: &Path,
+    report_type: ReportType,
+    selection: &ReportSelection,
+    bytes: &[Option<MaskedByte>],
+    edits: &ReportEdits,
+    offset: usize,
+    options: &WriteOptions,
+) -> Result<()> {
+    let rdesc = options
+        .backend
+        .report_descriptor(path)
+        .device_context(path, None)?;
+    let rdesc = ReportDescriptor::try_from(&rdesc)?;
+    let report_id = |report: &dyn Report| report.report_id().map(u8::from);
+
+    match report_type {
+        ReportType::Feature => {
+            let report = select_report(rdesc.feature_reports(), report_type, selection)?;
+            set_report(path, report_type, report, bytes, edits, offset, options)
+                .device_context(path, report_id(report))
+        }
+        ReportType::Input => {
+            let report = select_report(rdesc.input_reports(), report_type, selection)?;
+            set_report(path, report_type, report, bytes, edits, offset, options)
+                .device_context(path, report_id(report))
+        }
+        ReportType::Output => {
+            let report = select_report(rdesc.output_reports(), report_type, selection)?;
+            set_report(path, report_type, report, bytes, edits, offset, options)
+                .device_context(path, report_id(report))
+        }
+    }
+}
+
+fn set_report<R: Report>(
+    path: &Path,
+    report_type: ReportType,
+    report: &R,
+    bytes: &[Option<MaskedByte>],
+    edits: &ReportEdits,
+    offset: usize,
+    options: &WriteOptions,
+) -> Result<()> {
+    warn_driver_conflicts(path, report);
+    let slots = if edits.select.is_empty() {
+        Vec::new()
+    } else {
+        array_selection(report, edits.select)?
+    };
+    let mut assignments = field_assignments(report, edits.fields)?;
+    assignments.extend(byte_assignments(edits.bytes)?);
+    assignments.extend(int_assignments(edits.u16, 2, edits.byte_order)?);
+    assignments.extend(int_assignments(edits.u32, 4, edits.byte_order)?);
+    if let Some((bits, value)) = &edits.bits {
+        assignments.push(Assignment {
+            target: format!("bits {}..={}", bits.start, bits.end - 1),
+            bits: bits.clone(),
+            expr: value.parse()?,
+            range: 0..=(1i64 << bits.len()) - 1,
+            signed: false,
+            byte_order: ByteOrder::Little,
+        });
+    }
+    if let Some(a) = assignments
+        .iter()
+        .find(|a| a.bits.end > report.size_in_bytes() * 8)
+    {
+        bail!(
+            "The {} is outside of the {}-byte report",
+            a.target,
+            report.size_in_bytes()
+        );
+    }
+    if !options.force {
+        let touched: Vec<usize> = bytes
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_some())
+            .map(|(i, _)| offset + i)
+            .chain(
+                slots
+                    .iter()
+                    .map(|(bits, _)| bits)
+                    .chain(assignments.iter().map(|a| &a.bits))
+                    .flat_map(|bits| bits.start / 8..=(bits.end - 1) / 8),
+            )
+            .collect();
+        check_protected(report, &options.protected, &touched)?;
+    }
+
+    let report_id = report.report_id().map(u8::from);
+    // Printed as 0 for Report ID None
+    let rid = report_id.unwrap_or(0);
+
+    // Our report's length only includes the report ID if there is one, the
+    // merged bytes always start with the report ID or 0 like the ioctl's.
+    let report_size = report.size_in_bytes();
+    let fetch_size = match report.report_id() {
+        Some(_) => report_size,
+        None => report_size + 1,
+    };
+    if !bytes.is_empty() && offset + bytes.len() > report_size {
+        bail!(
+            "{} byte(s) at offset {offset} exceed the {report_size}-byte report {rid}",
+            bytes.len()
+        );
+    }
+    let rid_off = fetch_size - report_size;
+    // Returns the merged report and the current report
+    let read_merged = |device: &dyn HidBackend| -> Result<(Vec<u8>, Vec<u8>)> {
+        let r = device.get_report(path, report_type, report_id, report_size)?;
+        // prepend the report ID again if need be
+        let mut values = vec![rid; fetch_size];
+        values[rid_off..].copy_from_slice(&r[..report_size]);
+        let current = values.clone();
+
+        for (i, val) in bytes.iter().enumerate() {
+            if let Some(val) = val {
+                let idx = offset + rid_off + i;
+                values[idx] = val.apply(values[idx]);
+            }
+        }
+
+        for (bits, value) in &slots {
+            let bits = bits.start + rid_off * 8..bits.end + rid_off * 8;
+            pack_field(&mut values, &bits, *value);
+        }
+        // Expressions see the values after the bytes and slots were applied
+        for assignment in &assignments {
+            assignment.apply(&mut values[rid_off..])?;
+        }
+        Ok((values, current))
+    };
+
+    let mut device = options.backend.open_device(path)?;
+    let (mut values, current) = read_merged(&*device)?;
+    if options.only_changed && values == current {
+        println!("Report {rid} already has the requested values, skipping the write");
+        return Ok(());
+    }
+    if options.dry_run {
+        println!(
+            "Would write report {rid}: {}",
+            highlight_changes(&values, Some(&current))
+        );
+        return Ok(());
+    }
+
+    let hook_env = [
+        ("HID_FEATURE_DEVICE", path.display().to_string()),
+        ("HID_FEATURE_REPORT_ID", rid.to_string()),
+        ("HID_FEATURE_BYTES", print_bytes(&values)),
+    ];
+    config::run_hooks(&options.hooks.pre_write, &hook_env)?;
+
+    if let Err(e) = device.set_report(path, report_type, report_id, &values[rid_off..]) {
+        if !is_disconnect(&e) {
+            return Err(e);
+        }
+        // The device was reset or reconnected since we read the report,
+        // re-read the report so we don't write back stale values
+        eprintln!("Warning: {e}, reopening {}", path.display());
+        device = reopen(options.backend, path, options.retry_wait)?;
+        (values, _) = read_merged(&*device)?;
+        device.set_report(path, report_type, report_id, &values[rid_off..])?;
+    }
+
+    config::run_hooks(&options.hooks.post_write, &hook_env)?;
+
+    if options.verify {
+        let r = device.get_report(path, report_type, report_id, report_size)?;
+        // Without a Report ID, the report read back does not start with the 0
+        let written = &values[rid_off..];
+        let read = &r[..report_size];
+        let mismatches: Vec<String> = (0..report_size)
+            .filter(|&idx| written[idx] != read[idx])
+            .map(|idx| idx.to_string())
+            .collect();
+        if mismatches.is_empty() {
+            println!("Verified report {rid}");
+        } else {
+            println!("Written:   {}", print_bytes(written));
+            println!("Read back: {}", highlight_changes(read, Some(written)));
+            print_changed_fields(report, written, read, "  ", Radix::Dec);
+            bail!(
+                "Byte(s) {} of report {rid} did not stick",
+                mismatches.join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the snapshot of a 'diff' side: the snapshot file or, for
+/// anything but a file, the device's current reports
+fn diff_snapshot(backend: &dyn HidBackend, path: &Path) -> Result<snapshot::Snapshot> {
+    if !std::fs::metadata(path).is_ok_and(|m| m.is_file()) {
+        return snapshot::Snapshot::take(backend, path)
+            .with_context(|| format!("Unable to read {}", path.display()));
+    }
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read {}", path.display()))?;
+    snapshot::Snapshot::parse(&json).with_context(|| format!("Invalid snapshot {}", path.display()))
+}
+
+fn diff(backend: &dyn HidBackend, first: &Path, second: &Path) -> Result<()> {
+    let (old, new) = (
+        diff_snapshot(backend, first)?,
+        diff_snapshot(backend, second)?,
+    );
+    // The fields can only be decoded if both sides have the same layout
+    let rdesc = if old.rdesc != new.rdesc {
+        eprintln!("Warning: the report descriptors differ, only the bytes are compared");
+        None
+    } else {
+        [&old.descriptor, &new.descriptor]
+            .into_iter()
+            .find(|d| !d.is_empty())
+            .map(ReportDescriptor::try_from)
+            .transpose()?
+    };
+
+    let mut ids: Vec<Option<u8>> = old
+        .reports
+        .iter()
+        .chain(&new.reports)
+        .map(|r| r.report_id)
+        .collect();
+    ids.sort();
+    ids.dedup();
+
+    let width = first.as_os_str().len().max(second.as_os_str().len()) + 1;
+    let mut differences = 0;
+    for id in ids {
+        let saved = |snapshot: &snapshot::Snapshot| {
+            snapshot
+                .reports
+                .iter()
+                .find(|r| r.report_id == id)
+                .map(|r| r.bytes.clone())
+        };
+        let rid = id.unwrap_or(0);
+        match (saved(&old), saved(&new)) {
+            (Some(old), Some(new)) if old == new => continue,
+            (Some(old), Some(new)) => {
+                cprintln!(Styles::Header, "Report {rid}:");
+                let label = |path: &Path| format!("{}:", path.display());
+                println!("  {:<width$} {}", label(first), print_bytes(&old));
+                println!(
+                    "  {:<width$} {}",
+                    label(second),
+                    highlight_changes(&new, Some(&old))
+                );
+                let report = rdesc.as_ref().and_then(|rdesc| {
+                    rdesc
+                        .feature_reports()
+                        .iter()
+                        .find(|r| r.report_id().map(u8::from) == id)
+                });
+                if let Some(report) = report {
+                    print_changed_fields(report, &old, &new, "  ", Radix::Dec);
+                }
+            }
+            (Some(_), None) => println!("Report {rid}: only in {}", first.display()),
+            (None, Some(_)) => println!("Report {rid}: only in {}", second.display()),
+            (None, None) => continue,
+        }
+        differences += 1;
+    }
+    if differences == 0 {
+        println!("No differences");
+    }
+    Ok(())
+}
+
+/// Writes the reports of the snapshot back to the device, see 'restore'
+fn restore(
+    path: &Path,
+    snapshot: &snapshot::Snapshot,
+    filter: &ReportFilter,
+    options: &WriteOptions,
+) -> Result<()> {
+    let fingerprint = rdesc_fingerprint(&options.backend.report_descriptor(path)?);
+    if fingerprint != snapshot.rdesc {
+        bail!(
+            "The report descriptor of {} (rdesc:{fingerprint}) does not match the snapshot's (rdesc:{}), the reports may have a different layout",
+            path.display(),
+            snapshot.rdesc
+        );
+    }
+    let reports: Vec<_> = snapshot
+        .reports
+        .iter()
+        .filter(|r| filter.matches(r.report_id))
+        .collect();
+    if reports.is_empty() {
+        bail!("The snapshot does not contain any of the selected reports");
+    }
+    for saved in reports {
+        let rid = saved.report_id.unwrap_or(0);
+        // A device uses Report IDs for all or none of its reports, so the
+        // Report ID alone identifies the report
+        let selection = ReportSelection {
+            report_id: saved.report_id,
+            collection: None,
+            first: true,
+        };
+        let bytes: Vec<Option<MaskedByte>> = saved
+            .bytes
+            .iter()
+            .map(|b| Some(MaskedByte::new(*b)))
+            .collect();
+        let edits = ReportEdits::default();
+        set(
+            path,
+            ReportType::Feature,
+            &selection,
+            &bytes,
+            &edits,
+            0,
+            options,
+        )
+        .with_context(|| format!("Unable to restore report {rid}"))?;
+        println!("Restored report {rid}");
+    }
+    Ok(())
+}
+
+/// Off Linux, the driver bound to the device is not known
+#[cfg(not(target_os = "linux"))]
+fn warn_driver_conflicts<R: Report>(_path: &Path, _report: &R) {}
+
+/// Returns the running kernel's (major, minor, patch) version
+#[cfg(target_os = "linux")]
+fn kernel_version() -> Option<(u32, u32, u32)> {
+    let release = std::fs::read_to_string("/proc/sys/kernel/osrelease").ok()?;
+    let mut parts = release.trim().split(['.', '-']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+#[cfg(target_os = "linux")]
+struct Doctor {
+    failures: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl Doctor {
+    fn pass(&self, msg: &str) {
+        cprintln!(Styles::Success, "  ✓ {msg}");
+    }
+
+    fn fail(&mut self, msg: &str, hint: &str) {
+        self.failures += 1;
+        cprintln!(Styles::Failure, "  ✗ {msg}");
+        println!("      hint: {hint}");
+    }
+
+    /// Checks the device, returning an error if any check failed
+    fn check_device(&mut self, path: &Path) -> Result<()> {
+        let failures = self.failures;
+        self.run_device_checks(path);
+        if self.failures > failures {
+            bail!("{} check(s) failed", self.failures - failures);
+        }
+        Ok(())
+    }
+
+    fn run_device_checks(&mut self, path: &Path) {
+        cprintln!(Styles::Header, "{}:", path.display());
+
+        if !path.exists() {
+            self.fail(
+                "Device node does not exist",
+                "check the path or use 'list-devices' to find the device",
+            );
+            return;
+        }
+        self.pass("Device node exists");
+
+        let mut can_open = true;
+        for (mode, write) in [("read", false), ("write", true)] {
+            match std::fs::File::options()
+                .read(!write)
+                .write(write)
+                .open(path)
+            {
+                Ok(_) => self.pass(&format!("Device node is accessible for {mode}")),
+                Err(e) => {
+                    can_open = false;
+                    self.fail(
+                        &format!("Device node is not accessible for {mode}: {e}"),
+                        "run as root or add a udev rule granting access to the device",
+                    );
+                }
+            }
+        }
+
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        let rdesc_path = format!("/sys/class/hidraw/{filename}/device/report_descriptor");
+        let sysfs_result = if use_sysfs() {
+            std::fs::read(&rdesc_path)
+        } else {
+            Err(std::io::Error::other("sysfs access is disabled"))
+        };
+        let bytes = match sysfs_result {
+            Ok(bytes) => {
+                self.pass("Report descriptor is available in sysfs");
+                Some(bytes)
+            }
+            Err(e) => {
+                let result = hidraw::Device::open(path).and_then(|mut d| d.get_report_descriptor());
+                match result {
+                    Ok(bytes) => {
+                        self.pass(&format!(
+                            "Report descriptor is not available in sysfs ({e}) but via HIDIOCGRDESC"
+                        ));
+                        Some(bytes)
+                    }
+                    Err(ioctl_error) => {
+                        self.fail(
+                            &format!("Report descriptor is not available at {rdesc_path} ({e}) or via HIDIOCGRDESC ({ioctl_error})"),
+                            "ensure /sys is mounted and accessible or the device node can be opened",
+                        );
+                        None
+                    }
+                }
+            }
+        };
+        let rdesc = bytes.and_then(|bytes| match ReportDescriptor::try_from(&bytes) {
+            Ok(rdesc) => {
+                self.pass("Report descriptor can be parsed");
+                Some(rdesc)
+            }
+            Err(e) => {
+                self.fail(
+                    &format!("Report descriptor cannot be parsed: {e}"),
+                    "please file a bug with the output of 'hid-decode' for this device",
+                );
+                None
+            }
+        });
+
+        if !can_open {
+            return;
+        }
+
+        let Some(rdesc) = rdesc else {
+            return;
+        };
+        let Some(report) = rdesc.feature_reports().first() else {
+            self.pass("Device has no Feature Reports, skipping GetFeature check");
+            return;
+        };
+        let rid = report.report_id().map_or(0, u8::from);
+        let fetch_size = match report.report_id() {
+            Some(_) => report.size_in_bytes(),
+            None => report.size_in_bytes() + 1,
+        };
+        let result =
+            FeatureDevice::open(path).and_then(|device| device.get_feature_report(rid, fetch_size));
+        match result {
+            Ok(_) => self.pass(&format!("GetFeature works for report ID {rid}")),
+            Err(e) => self.fail(
+                &format!("GetFeature failed for report ID {rid}: {e}"),
+                "the device or its driver may not support GetFeature",
+            ),
+        }
+    }
+
+    fn check_kernel(&mut self) {
+        cprintln!(Styles::Header, "Kernel:");
+        let Some(version) = kernel_version() else {
+            self.fail(
+                "Unable to determine the kernel version",
+                "ensure /proc is mounted and accessible",
+            );
+            return;
+        };
+
+        let (major, minor, patch) = version;
+        for (ioctls, required) in [
+            ("HIDIOCGFEATURE/HIDIOCSFEATURE", (2, 6, 39)),
+            ("HIDIOCGRAWUNIQ", (5, 6, 0)),
+            ("HIDIOCGINPUT/HIDIOCSOUTPUT", (5, 11, 0)),
+        ] {
+            if version >= required {
+                self.pass(&format!(
+                    "{ioctls} supported by kernel {major}.{minor}.{patch}"
+                ));
+            } else {
+                let (major, minor, patch) = required;
+                self.fail(
+                    &format!("{ioctls} not supported by this kernel"),
+                    &format!("upgrade to kernel {major}.{minor}.{patch} or later"),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn doctor(device: &DeviceArgs) -> Result<()> {
+    let paths = device.resolve_or_all()?;
+
+    let mut doctor = Doctor { failures: 0 };
+    doctor.check_kernel();
+    // The devices are checked even if the kernel is too old, the failed
+    // kernel checks are part of the result
+    let kernel_failures = doctor.failures;
+    let result = for_each_device(&paths, |path| doctor.check_device(path));
+    match (kernel_failures, result) {
+        (0, result) => result,
+        (n, Ok(())) => bail!("{n} kernel check(s) failed"),
+        (n, Err(e)) => bail!("{n} kernel check(s) failed, {e:#}"),
+    }
+}
+
+enum Severity {
+    Error,
+    Warning,
+}
+
+struct Lint {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Lint {
+    fn report(&mut self, severity: Severity, msg: &str) {
+        match severity {
+            Severity::Error => {
+                self.errors += 1;
+                cprintln!(Styles::Failure, "error: {msg}");
+            }
+            Severity::Warning => {
+                self.warnings += 1;
+                cprintln!(Styles::Header, "warning: {msg}");
+            }
+        }
+    }
+
+    /// Checks the fields of each report
+    fn check_reports<R: Report>(&mut self, kind: &str, reports: &[R], max_size: Option<usize>) {
+        for report in reports {
+            let name = match report.report_id() {
+                Some(id) => format!("{kind} Report {}", u8::from(id)),
+                None => format!("{kind} Report (no ID)"),
+            };
+
+            if let Some(max_size) = max_size {
+                if report.size_in_bytes() > max_size {
+                    self.report(
+                        Severity::Warning,
+                        &format!(
+                            "{name} is {} bytes and exceeds the maximum packet size of {max_size} bytes for this USB device",
+                            report.size_in_bytes()
+                        ),
+                    );
+                }
+            }
+
+            let fields = report.fields();
+            for (idx, field) in fields.iter().enumerate() {
+                let bits = field.bits();
+                for other in &fields[idx + 1..] {
+                    let other_bits = other.bits();
+                    if bits.start < other_bits.end && other_bits.start < bits.end {
+                        self.report(
+                            Severity::Error,
+                            &format!(
+                                "{name}: fields at bits {}..={} and {}..={} overlap",
+                                bits.start,
+                                bits.end - 1,
+                                other_bits.start,
+                                other_bits.end - 1
+                            ),
+                        );
+                    }
+                }
+
+                let (min, max, size) = match field {
+                    Field::Variable(var) => (
+                        i32::from(var.logical_minimum) as i64,
+                        i32::from(var.logical_maximum) as i64,
+                        bits.len(),
+                    ),
+                    Field::Array(arr) => (
+                        i32::from(arr.logical_minimum) as i64,
+                        i32::from(arr.logical_maximum) as i64,
+                        bits.len() / usize::from(arr.report_count).max(1),
+                    ),
+                    _ => continue,
+                };
+                // The HID spec allows a logical maximum of e.g. 255 for an 8-bit
+                // field with a logical minimum of 0, i.e. the field is unsigned
+                // if the minimum is not negative.
+                if size == 0 {
+                    continue;
+                }
+                let size = size.min(32) as u32;
+                let (lower, upper) = if min < 0 {
+                    (-(1i64 << (size - 1)), (1i64 << (size - 1)) - 1)
+                } else {
+                    (0, (1i64 << size) - 1)
+                };
+                if min < lower || max > upper || min > max {
+                    self.report(
+                        Severity::Error,
+                        &format!(
+                            "{name}: logical range {min}..={max} of the field at bits {}..={} does not fit into {size} bits",
+                            bits.start,
+                            bits.end - 1
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check(&mut self, rdesc: &ReportDescriptor, max_size: Option<usize>) {
+        let ids: Vec<bool> = rdesc
+            .input_reports()
+            .iter()
+            .map(|r| r.report_id().is_some())
+            .chain(
+                rdesc
+                    .output_reports()
+                    .iter()
+                    .map(|r| r.report_id().is_some()),
+            )
+            .chain(
+                rdesc
+                    .feature_reports()
+                    .iter()
+                    .map(|r| r.report_id().is_some()),
+            )
+            .collect();
+        if ids.contains(&true) && ids.contains(&false) {
+            self.report(
+                Severity::Error,
+                "Some reports have a Report ID and others do not",
+            );
+        }
+
+        self.check_reports("Input", rdesc.input_reports(), max_size);
+        self.check_reports("Output", rdesc.output_reports(), max_size);
+        self.check_reports("Feature", rdesc.feature_reports(), max_size);
+    }
+}
+
+fn map(path: &Path, filter_id: &Option<u8>) -> Result<()> {
+    let rdesc = report_descriptor_from(path)?;
+
+    let reports = rdesc.feature_reports();
+    if reports.is_empty() {
+        println!("This device does not have any Feature Reports");
+        return Ok(());
+    }
+
+    let mut found = false;
+    for report in reports {
+        let report_id = report.report_id().map(u8::from);
+        if filter_id.is_some() && report_id != *filter_id {
+            continue;
+        }
+        found = true;
+
+        match report_id {
+            Some(id) => cprintln!(Styles::Header, "Report {id}:"),
+            None => cprintln!(Styles::Header, "Report (no Report ID):"),
+        }
+        for byte in 0..report.size_in_bytes() {
+            let byte_bits = byte * 8..byte * 8 + 8;
+            if byte == 0 && report_id.is_some() {
+                println!("  byte {byte:3}: Report ID");
+                continue;
+            }
+
+            let mut pieces = Vec::new();
+            let mut used = 0u8;
+            let mut shared = 0;
+            for field in report.fields() {
+                let bits = field.bits();
+                let start = bits.start.max(byte_bits.start);
+                let end = bits.end.min(byte_bits.end);
+                if start >= end {
+                    continue;
+                }
+                for bit in start..end {
+                    used |= 1 << (bit % 8);
+                }
+                if !matches!(field, Field::Constant(_)) {
+                    shared += 1;
+                }
+                let (first, last) = (start - byte_bits.start, end - 1 - byte_bits.start);
+                let piece = if bits.end - bits.start <= 8 && *bits == (start..end) {
+                    format!("{} [bits {first}..={last}]", field_name(field))
+                } else {
+                    format!(
+                        "{} [bits {first}..={last} = field bits {}..={}]",
+                        field_name(field),
+                        start - bits.start,
+                        end - 1 - bits.start
+                    )
+                };
+                pieces.push(piece);
+            }
+            if used != 0xff {
+                let unused: Vec<String> = (0..8)
+                    .filter(|bit| used & (1 << bit) == 0)
+                    .map(|bit| bit.to_string())
+                    .collect();
+                pieces.push(format!("<unused> [bits {}]", unused.join(",")));
+            }
+
+            let label = if shared > 1 {
+                format!("byte {byte:3} (shared)")
+            } else {
+                format!("byte {byte:3}")
+            };
+            for (idx, piece) in pieces.iter().enumerate() {
+                if idx == 0 {
+                    println!("  {label:17}: {piece}");
+                } else {
+                    println!("  {:17}  {piece}", "");
+                }
+            }
+        }
+    }
+
+    if !found {
+        bail!("Unable to find report {}", filter_id.unwrap_or_default());
+    }
+
+    Ok(())
+}
+
+/// Returns the maximum interrupt packet size of a low or full speed USB
+/// device
+#[cfg(target_os = "linux")]
+fn max_packet_size(path: &Path) -> Option<usize> {
+    let filename = path.file_name()?.to_string_lossy().to_string();
+    // The maximum interrupt packet size is 8 bytes for low speed
+    // (1.5 Mbit/s) and 64 bytes for full speed (12 Mbit/s) USB devices
+    hidraw_usb_speed(&filename).and_then(|speed| match speed {
+        s if s <= 1.5 => Some(8),
+        s if s <= 12.0 => Some(64),
+        _ => None,
+    })
+}
+
+/// Off Linux, the USB speed is not known
+#[cfg(not(target_os = "linux"))]
+fn max_packet_size(_path: &Path) -> Option<usize> {
+    None
+}
+
+fn lint(path: &Path) -> Result<()> {
+    let (bytes, max_size) = if is_device_path(path)? {
+        (backend().report_descriptor(path)?, max_packet_size(path))
+    } else {
+        (std::fs::read(path)?, None)
+    };
+    let rdesc = ReportDescriptor::try_from(&bytes)?;
+
+    let mut lint = Lint {
+        errors: 0,
+        warnings: 0,
+    };
+    lint.check(&rdesc, max_size);
+
+    if lint.errors > 0 {
+        bail!(
+            "{} error(s) and {} warning(s) found",
+            lint.errors,
+            lint.warnings
+        );
+    }
+    if lint.warnings == 0 {
+        println!("No problems found");
+    }
+    Ok(())
+}
+
+/// Returns the usage page of the given field or `None` for constant fields
+fn field_usage_page(field: &Field) -> Option<u16> {
+    match field {
+        Field::Variable(var) => Some(u16::from(var.usage.usage_page)),
+        Field::Array(arr) => arr.usages().first().map(|u| u16::from(u.usage_page)),
+        _ => None,
+    }
+}
+
+/// Returns the number of reports and their total size in bytes, and adds
+/// each field to the per-usage-page count
+fn report_stats<R: Report>(reports: &[R], pages: &mut BTreeMap<u16, usize>) -> (usize, usize) {
+    for page in reports
+        .iter()
+        .flat_map(|r| r.fields())
+        .filter_map(field_usage_page)
+    {
+        *pages.entry(page).or_default() += 1;
+    }
+    let bytes = reports.iter().map(|r| r.size_in_bytes()).sum();
+    (reports.len(), bytes)
+}
+
+fn stats(path: &Path) -> Result<()> {
+    let rdesc_bytes = backend().report_descriptor(path)?;
+    let rdesc = ReportDescriptor::try_from(&rdesc_bytes)?;
+
+    let mut pages = BTreeMap::new();
+    let types = [
+        ("Input", report_stats(rdesc.input_reports(), &mut pages)),
+        ("Output", report_stats(rdesc.output_reports(), &mut pages)),
+        ("Feature", report_stats(rdesc.feature_reports(), &mut pages)),
+    ];
+
+    cprintln!(Styles::Header, "{}:", path.display());
+    println!("  Fingerprint: rdesc:{}", rdesc_fingerprint(&rdesc_bytes));
+    for (kind, (count, bytes)) in types {
+        println!("  {kind:7} reports: {count:3} ({bytes} bytes)");
+    }
+
+    let total: usize = pages.values().sum();
+    let vendor: usize = pages
+        .iter()
+        .filter(|(page, _)| **page >= 0xff00)
+        .map(|(_, count)| count)
+        .sum();
+    println!("  Fields by usage page:");
+    for (page, count) in pages.iter() {
+        let name = match hut::UsagePage::try_from(*page) {
+            Ok(up) => up.to_string(),
+            Err(_) => format!("Unknown Usage Page {page:04X}"),
+        };
+        println!("    {count:5}  {name}");
+    }
+    println!("  Vendor-defined fields: {vendor} of {total}");
+
+    Ok(())
+}
+
+fn stat() -> Result<()> {
+    let devices = backend().devices()?;
+
+    let mut with_features = 0;
+    let mut reports = 0;
+    let mut fields = 0;
+    let mut vendor_only = 0;
+    let mut unreadable = 0;
+    let mut largest: Option<(usize, PathBuf, Option<u8>)> = None;
+    for device in devices.iter() {
+        let rdesc = match report_descriptor(&device.path) {
+            Ok(rdesc) => rdesc,
+            Err(e) => {
+                eprintln!("Warning: skipping {}: {e:#}", device.path.display());
+                unreadable += 1;
+                continue;
+            }
+        };
+        let feature_reports = rdesc.feature_reports();
+        if feature_reports.is_empty() {
+            continue;
+        }
+        with_features += 1;
+        reports += feature_reports.len();
+
+        let pages: Vec<u16> = feature_reports
+            .iter()
+            .flat_map(|r| r.fields())
+            .filter_map(field_usage_page)
+            .collect();
+        fields += pages.len();
+        if !pages.is_empty() && pages.iter().all(|page| *page >= 0xff00) {
+            vendor_only += 1;
+        }
+
+        for report in feature_reports {
+            let size = report.size_in_bytes();
+            if largest
+                .as_ref()
+                .is_none_or(|(largest, _, _)| size > *largest)
+            {
+                let id = report.report_id().map(u8::from);
+                largest = Some((size, device.path.clone(), id));
+            }
+        }
+    }
+
+    println!("Devices:                      {:5}", devices.len());
+    println!("Devices with Feature Reports: {with_features:5}");
+    println!("Feature Reports:              {reports:5}");
+    println!("Feature fields:               {fields:5}");
+    match largest {
+        Some((size, path, Some(id))) => println!(
+            "Largest Feature Report:       {size:5} bytes ({}, report {id})",
+            path.display()
+        ),
+        Some((size, path, None)) => println!(
+            "Largest Feature Report:       {size:5} bytes ({})",
+            path.display()
+        ),
+        None => println!("Largest Feature Report:           -"),
+    }
+    println!("Vendor-page-only devices:     {vendor_only:5}");
+    println!("Unreadable devices:           {unreadable:5}");
+
+    Ok(())
+}
+
+fn edit(path: &Path, name: &str, filter_id: &Option<u8>, options: &WriteOptions) -> Result<()> {
+    let backend = options.backend;
+    let rdesc = ReportDescriptor::try_from(&backend.report_descriptor(path)?)?;
+
+    let (report, var) = find_variable_field(rdesc.feature_reports(), name, |r| {
+        filter_id.is_none() || r.report_id().map(u8::from) == *filter_id
+    })?;
+
+    let rid = report.report_id().map_or(0, u8::from);
+    let r = FeatureReportHandle::new(backend, path, report).read()?;
+    let current: i32 = var.extract(&r)?.into();
+
+    let min = i32::from(var.logical_minimum);
+    let max = i32::from(var.logical_maximum);
+    let bits = &var.bits;
+    println!(
+        "{} (report {}, bits {}..={})",
+        usage_name(&var.usage),
+        rid,
+        bits.start,
+        bits.end - 1
+    );
+    println!("  Current value: {current}");
+    println!("  Logical range: {min}..={max}");
+    if let Some(unit) = var.unit.as_ref().and_then(unit_name) {
+        println!("  Unit: {unit}");
+    }
+
+    let value = loop {
+        print!("New value (empty to keep {current}): ");
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        let value = match line.strip_prefix("0x") {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => line.parse::<i64>(),
+        };
+        match value {
+            Ok(v) if (min as i64..=max as i64).contains(&v) => break v as i32,
+            Ok(v) => eprintln!("Value {v} is outside the logical range {min}..={max}"),
+            Err(e) => eprintln!("Invalid value '{line}': {e}"),
+        }
+    };
+    if value == current {
+        println!("The value is unchanged, skipping the write");
+        return Ok(());
+    }
+
+    let selection = ReportSelection {
+        report_id: report.report_id().map(u8::from),
+        collection: None,
+        first: false,
+    };
+    set(
+        path,
+        ReportType::Feature,
+        &selection,
+        &field_bytes(report.size_in_bytes(), [(bits, value as u32)]),
+        &ReportEdits::default(),
+        0,
+        options,
+    )
+}
+
+/// The usages handled by the `digitizer` command, in the order of its options
+const DIGITIZER_USAGES: [&str; 4] = [
+    "Digitizers / Report Rate",
+    "Digitizers / Latency Mode",
+    "Digitizers / Surface Switch",
+    "Digitizers / Button Switch",
+];
+
+/// Returns the value of a digitizer setting as printed by the `digitizer` command
+fn digitizer_value(usage: &str, value: i32) -> String {
+    match (usage, value) {
+        ("Digitizers / Report Rate", v) => format!("{v} Hz"),
+        ("Digitizers / Latency Mode", 0) => "normal".into(),
+        ("Digitizers / Latency Mode", 1) => "high".into(),
+        (_, 0) => "off".into(),
+        (_, 1) => "on".into(),
+        (_, v) => v.to_string(),
+    }
+}
+
+/// Prints the digitizer settings or, for each value that is `Some`, writes
+/// it to the field with the corresponding [DIGITIZER_USAGES] usage.
+fn digitizer(path: &Path, values: &[Option<u32>; 4], options: &WriteOptions) -> Result<()> {
+    let rdesc = ReportDescriptor::try_from(&options.backend.report_descriptor(path)?)?;
+    let find = |name: &str| {
+        rdesc.feature_reports().iter().find_map(|r| {
+            r.fields().iter().find_map(|f| match f {
+                Field::Variable(var) if usage_matches(&var.usage, name) => Some((r, var)),
+                _ => None,
+            })
+        })
+    };
+
+    let write = values.iter().any(Option::is_some);
+    // Writes go through [set] which opens the device itself
+    let device = match write {
+        true => None,
+        false => Some(options.backend.open_device(path)?),
+    };
+    for (name, value) in DIGITIZER_USAGES.iter().zip(values) {
+        let label = name.split_once(" / ").map_or(*name, |(_, usage)| usage);
+        let Some((report, var)) = find(name) else {
+            if value.is_some() {
+                bail!("This device does not have a {label} Feature field");
+            }
+            if !write {
+                println!("{label:15} not supported");
+            }
+            continue;
+        };
+
+        let Some(value) = value else {
+            if let Some(device) = &device {
+                let r = FeatureReportHandle::new(&**device, path, report).read()?;
+                let current: i32 = var.extract(&r)?.into();
+                println!("{label:15} {}", digitizer_value(name, current));
+            }
+            continue;
+        };
+        let min = i32::from(var.logical_minimum) as i64;
+        let max = i32::from(var.logical_maximum) as i64;
+        if !(min..=max).contains(&(*value as i64)) {
+            bail!("{label} value {value} is outside the logical range {min}..={max}");
+        }
+        let selection = ReportSelection {
+            report_id: report.report_id().map(u8::from),
+            collection: None,
+            first: false,
+        };
+        set(
+            path,
+            ReportType::Feature,
+            &selection,
+            &field_bytes(report.size_in_bytes(), [(&var.bits, *value)]),
+            &ReportEdits::default(),
+            0,
+            options,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns true if the device has a keyboard or mouse Application
+/// Collection, detaching its driver may leave the user without input
+#[cfg(target_os = "linux")]
+fn is_keyboard_or_mouse(path: &Path) -> Result<bool> {
+    let rdesc = report_descriptor(path)?;
+    Ok(rdesc.input_reports().iter().any(|r| {
+        r.fields()
+            .iter()
+            .filter_map(application_collection)
+            .any(|c| c == "Generic Desktop / Keyboard" || c == "Generic Desktop / Mouse")
+    }))
+}
+
+#[cfg(target_os = "linux")]
+fn driver_command(action: DriverAction) -> Result<()> {
+    match action {
+        DriverAction::Unbind { force, device } => {
+            let path = device.resolve()?;
+            if !force && is_keyboard_or_mouse(&path)? {
+                bail!(
+                    "{} is a keyboard or mouse and will stop working, use --force to unbind it anyway",
+                    path.display()
+                );
+            }
+            let name = driver::hid_device_name(&path)?;
+            let driver = driver::unbind(&name)?;
+            println!("Unbound {name} from {driver}");
+            println!("Rebind with: hid-feature driver rebind {name}");
+        }
+        DriverAction::Rebind { driver, device } => {
+            let name = if device.starts_with('/') {
+                let name = driver::hid_device_name(Path::new(&device))?;
+                let previous = driver::unbind(&name)?;
+                println!("Unbound {name} from {previous}");
+                name
+            } else {
+                device
+            };
+            driver::bind(&name, driver.as_deref())?;
+            match driver::bound_driver(&name)? {
+                Some(driver) => println!("Bound {name} to {driver}"),
+                None => bail!("No driver bound to {name}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if the device in a configuration entry, a hidraw node or
+/// a [DeviceIdentity], is the device at the given path
+fn config_device_matches(spec: &str, path: &Path, device: Option<&HidrawDevice>) -> bool {
+    if spec.starts_with('/') {
+        Path::new(spec) == path
+    } else {
+        match (spec.parse::<DeviceIdentity>(), device) {
+            (Ok(identity), Some(device)) => identity.matches(device),
+            _ => false,
+        }
+    }
+}
+
+/// Returns the hidraw device at the given path, if it can be identified
+#[cfg(target_os = "linux")]
+fn path_device(path: &Path) -> Option<HidrawDevice> {
+    let file = path.file_name()?.to_string_lossy().to_string();
+    hidraw_device(&file).ok()
+}
+
+/// Returns the backend's device at the given path, if any
+#[cfg(not(target_os = "linux"))]
+fn path_device(path: &Path) -> Option<HidrawDevice> {
+    backend()
+        .devices()
+        .ok()?
+        .into_iter()
+        .find(|d| d.path == path)
+}
+
+/// Returns the first entry in the configuration's defaults that matches
+/// the device at the given path
+fn device_defaults<'a>(
+    config: &'a config::Config,
+    path: &Path,
+) -> Option<&'a config::DeviceDefaults> {
+    let device = path_device(path);
+    config
+        .defaults
+        .iter()
+        .find(|d| config_device_matches(&d.device, path, device.as_ref()))
+}
+
+/// Returns the configuration's write-protected entries for the device at
+/// the given path
+fn device_protected<'a>(config: &'a config::Config, path: &Path) -> Vec<&'a config::Protected> {
+    if config.protected.is_empty() {
+        return Vec::new();
+    }
+    let device = path_device(path);
+    config
+        .protected
+        .iter()
+        .filter(|p| match &p.device {
+            Some(spec) => config_device_matches(spec, path, device.as_ref()),
+            None => true,
+        })
+        .collect()
+}
+
+fn scene(config: &config::Config, name: &str, only_changed: bool, jobs: usize) -> Result<()> {
+    let Some(entries) = config.scenes.get(name) else {
+        bail!("No scene named '{name}' in the configuration");
+    };
+    for_each_parallel(
+        entries,
+        jobs,
+        |e| e.device.clone(),
+        |entry| {
+            let path = if entry.device.starts_with('/') {
+                PathBuf::from(&entry.device)
+            } else {
+                entry.device.parse::<DeviceIdentity>()?.resolve_one()?
+            };
+            let selection = ReportSelection {
+                report_id: entry.report_id,
+                collection: entry.collection.clone(),
+                first: entry.first,
+            };
+            set(
+                &path,
+                ReportType::Feature,
+                &selection,
+                &parse_byte_list(&entry.bytes, Radix::Hex)?,
+                &ReportEdits::default(),
+                entry.offset,
+                &WriteOptions {
+                    backend: backend(),
+                    retry_wait: Duration::ZERO,
+                    hooks: &config.hooks,
+                    protected: device_protected(config, &path),
+                    force: false,
+                    only_changed,
+                    dry_run: false,
+                    verify: false,
+                },
+            )
+        },
+    )
+}
+
+/// Returned by [for_each_device] if some but not all devices failed
+#[derive(Debug)]
+struct PartialFailure {
+    failed: usize,
+    total: usize,
+}
+
+impl std::fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} of {} devices failed", self.failed, self.total)
+    }
+}
+
+impl std::error::Error for PartialFailure {}
+
+/// Runs `func` for each device, continuing with the remaining devices
+/// if one device fails.
+///
+/// Where there is more than one device, the progress is printed to stderr
+/// and a summary of the results is printed once all devices are done.
+/// Returns a [PartialFailure] error if some but not all devices failed.
+fn for_each_device(paths: &[PathBuf], mut func: impl FnMut(&Path) -> Result<()>) -> Result<()> {
+    for_each_labeled(paths, |p| p.display().to_string(), |p| func(p))
+}
+
+/// Like [for_each_device] but for arbitrary items, `label` returns the
+/// name of an item for the progress and summary.
+fn for_each_labeled<T>(
+    items: &[T],
+    label: impl Fn(&T) -> String,
+    mut func: impl FnMut(&T) -> Result<()>,
+) -> Result<()> {
+    match items {
+        [] => return Ok(()),
+        [item] => return func(item),
+        _ => {}
+    }
+
+    let total = items.len();
+    let mut results = Vec::new();
+    for (idx, item) in items.iter().enumerate() {
+        eprintln!("[{}/{total}] {}", idx + 1, label(item));
+        results.push((label(item), func(item)));
+    }
+    summarize(results)
+}
+
+/// Like [for_each_labeled] but runs `func` for up to `jobs` items at the
+/// same time, e.g. to write many Bluetooth devices without waiting for
+/// each one in turn.
+///
+/// The progress is printed as the items start, the summary is printed in
+/// the order of the items once all are done.
+fn for_each_parallel<T: Sync>(
+    items: &[T],
+    jobs: usize,
+    label: impl Fn(&T) -> String + Sync,
+    func: impl Fn(&T) -> Result<()> + Sync,
+) -> Result<()> {
+    if jobs <= 1 || items.len() <= 1 {
+        return for_each_labeled(items, label, func);
+    }
+
+    let total = items.len();
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results: std::sync::Mutex<Vec<Option<Result<()>>>> =
+        std::sync::Mutex::new(items.iter().map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(total) {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(idx) else {
+                    break;
+                };
+                eprintln!("[{}/{total}] {}", idx + 1, label(item));
+                let result = func(item);
+                results.lock().unwrap()[idx] = Some(result);
+            });
+        }
+    });
+    let results = results.into_inner().unwrap();
+    summarize(
+        items
+            .iter()
+            .map(&label)
+            .zip(results.into_iter().map(|r| r.expect("every item was run")))
+            .collect(),
+    )
+}
+
+/// Prints the summary of [for_each_labeled] and returns its result
+fn summarize(results: Vec<(String, Result<()>)>) -> Result<()> {
+    let total = results.len();
+    cprintln!(Styles::Header, "Summary:");
+    for (label, result) in results.iter() {
+        match result {
+            Ok(_) => cprintln!(Styles::Success, "  ✓ {label}"),
+            Err(e) => cprintln!(Styles::Failure, "  ✗ {label}: {e:#}"),
+        }
+    }
+
+    let failed = results.iter().filter(|(_, r)| r.is_err()).count();
+    match failed {
+        0 => Ok(()),
+        _ if failed == total => bail!("All {total} devices failed"),
+        _ => Err(PartialFailure { failed, total }.into()),
+    }
+}
+
+fn hid_feature() -> Result<()> {
+    let cli = Cli::parse();
+
+    set_use_sysfs(!cli.no_sysfs);
+    DEBUG.store(cli.debug, Ordering::Relaxed);
+    match cli.backend {
+        None => {}
+        #[cfg(target_os = "linux")]
+        Some(BackendKind::Hidraw) => {}
+        #[cfg(not(target_os = "linux"))]
+        Some(BackendKind::Hidraw) => bail!("The hidraw backend is only available on Linux"),
+        #[cfg(feature = "hidapi")]
+        Some(BackendKind::Hidapi) => set_backend(Box::new(HidapiBackend::new()?)),
+        #[cfg(not(feature = "hidapi"))]
+        Some(BackendKind::Hidapi) => bail!("hid-feature was built without the hidapi feature"),
+    }
+    let hidapi = cli.backend == Some(BackendKind::Hidapi) || !cfg!(target_os = "linux");
+    if hidapi && cli.helper.is_some() {
+        bail!("--helper requires the hidraw backend");
+    }
+    if hidapi && cli.sandbox {
+        bail!("--sandbox requires the hidraw backend");
+    }
+    if let Some(helper) = &cli.helper {
+        if cli.sandbox {
+            bail!("--sandbox cannot be used with --helper");
+        }
+        let command: Vec<String> = helper.split_whitespace().map(String::from).collect();
+        if command.is_empty() {
+            bail!("--helper requires a command");
+        }
+        set_helper(command);
+    }
+    if let Some(timeout) = cli.ioctl_timeout {
+        set_ioctl_timeout(timeout);
+    }
+
+    // Bit lame but easier to just set the env for owo_colors to figure out the rest
+    unsafe {
+        match cli.color {
+            ColorChoice::Never => std::env::set_var("NO_COLOR", "1"),
+            ColorChoice::Auto => {}
+            ColorChoice::Always => std::env::set_var("FORCE_COLOR", "1"),
+        }
+    }
+
+    if cli.output.is_some() {
+        if cli.sandbox {
+            bail!("--sandbox cannot be used with --output");
+        }
+        unsafe { std::env::set_var("NO_COLOR", "1") };
+    }
+    let output = cli
+        .output
+        .as_deref()
+        .map(OutputFile::redirect)
+        .transpose()?;
+    if let Some(timeout) = cli.timeout {
+        start_watchdog(timeout, output.as_ref().map(|o| o.tmp.clone()));
+    }
+    run_command(cli)?;
+    if let Some(output) = output {
+        output.commit()?;
+    }
+    Ok(())
+}
+
+/// The exit status once the --timeout expired
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Exits the process once the --timeout has expired. A hung ioctl or a
+/// blocking read cannot be cancelled, so the watchdog exits from its own
+/// thread instead of returning an error. `tmp` is the temporary --output
+/// file to remove.
+fn start_watchdog(timeout: Duration, tmp: Option<PathBuf>) {
+    std::thread::spawn(move || {
+        std::thread::sleep(timeout);
+        let error = anyhow::anyhow!("Timed out after {timeout:?}");
+        if JSON_ERRORS.load(Ordering::Relaxed) {
+            eprintln!("{}", json_error(TIMEOUT_EXIT_CODE as u8, &error));
+        } else {
+            eprintln!("Error: {error}");
+        }
+        if let Some(tmp) = tmp {
+            let _ = std::fs::remove_file(tmp);
+        }
+        std::process::exit(TIMEOUT_EXIT_CODE);
+    });
+}
+
+fn run_command(cli: Cli) -> Result<()> {
+    match cli.command {
+        Commands::ListDevices {
+            sort,
+            verbose,
+            print0,
+            name,
+            group,
+            format,
+            porcelain,
+        } => {
+            JSON_ERRORS.store(format == DeviceListFormat::Json, Ordering::Relaxed);
+            if porcelain.is_some() {
+                list_devices_porcelain(sort, &name)
+            } else if format == DeviceListFormat::Json {
+                list_devices_json(sort, &name)
+            } else {
+                list_devices(sort, verbose, print0, &name, group)
+            }
+        }
+        Commands::List {
+            report_type,
+            filter,
+            collection,
+            usage,
+            mut output,
+            rdesc_file,
+            no_values,
+            values,
+            mut device,
+        } => {
+            if output.porcelain.is_some() {
+                output.format = ListFormat::Porcelain;
+            }
+            JSON_ERRORS.store(output.format == ListFormat::Json, Ordering::Relaxed);
+            let collection = device.merge_collection(collection)?;
+            if let Some(file) = rdesc_file {
+                let rdesc = read_rdesc_file(&file)?;
+                let source = ListSource::Descriptor(rdesc);
+                return list(
+                    &file,
+                    report_type,
+                    &filter,
+                    &collection,
+                    &usage,
+                    &output,
+                    source,
+                );
+            }
+            let path = device.resolve_or_pick()?;
+            let values = match (values, no_values) {
+                (false, false) => {
+                    let config = config::Config::load(cli.config.as_deref())?;
+                    device_defaults(&config, &path).and_then(|d| d.values) != Some(false)
+                }
+                _ => values,
+            };
+            if cli.sandbox {
+                sandbox::restrict(&path)?;
+            }
+            let source = match values {
+                true => ListSource::Device(backend()),
+                false => ListSource::Descriptor(backend().report_descriptor(&path)?),
+            };
+            list(
+                &path,
+                report_type,
+                &filter,
+                &collection,
+                &usage,
+                &output,
+                source,
+            )
+        }
+        Commands::Decode {
+            mut device,
+            report_id,
+            usage,
+            mut output,
+            rdesc_file,
+            mut bytes,
+        } => {
+            if output.porcelain.is_some() {
+                output.format = ListFormat::Porcelain;
+            }
+            JSON_ERRORS.store(output.format == ListFormat::Json, Ordering::Relaxed);
+            // With a device selector or --rdesc-file the first positional
+            // argument is a byte, not the path
+            if device.has_selector() || rdesc_file.is_some() {
+                if let Some(path) = device.path.take() {
+                    bytes.insert(0, path.to_string_lossy().to_string());
+                }
+            }
+            let (path, rdesc) = match rdesc_file {
+                Some(file) => {
+                    let rdesc = read_rdesc_file(&file)?;
+                    (file, rdesc)
+                }
+                None => {
+                    let path = device.resolve()?;
+                    if cli.sandbox {
+                        sandbox::restrict(&path)?;
+                    }
+                    let rdesc = backend().report_descriptor(&path)?;
+                    (path, rdesc)
+                }
+            };
+            decode(&path, rdesc, report_id, &usage, &bytes, &output)
+        }
+        Commands::Set {
+            mut report,
+            report_type,
+            mut bytes,
+            mut device,
+            offset,
+            retry_wait,
+            select,
+            field,
+            byte,
+            u16,
+            u32,
+            byte_order,
+            radix,
+            from_file,
+            bit_offset,
+            bit_length,
+            usage,
+            value,
+            all_devices,
+            jobs,
+            force,
+            only_changed,
+            dry_run,
+            verify,
+        } => {
+            if let (Some(usage), Some(value)) = (usage, value) {
+                report.collection = device.merge_collection(report.collection)?;
+                let paths = match all_devices {
+                    true => devices_with_usage(&usage)?,
+                    false => vec![device.resolve_or_pick()?],
+                };
+                let config = config::Config::load(cli.config.as_deref())?;
+                if cli.sandbox {
+                    if !config.hooks.is_empty() {
+                        bail!("--sandbox cannot be used with write hooks");
+                    }
+                    let [path] = paths.as_slice() else {
+                        bail!("--sandbox cannot be used with --all-devices");
+                    };
+                    sandbox::restrict(path)?;
+                }
+                let assignments = [format!("{usage}={value}")];
+                let label = |p: &PathBuf| p.display().to_string();
+                return for_each_parallel(&paths, jobs as usize, label, |path| {
+                    let options = WriteOptions {
+                        backend: backend(),
+                        retry_wait,
+                        hooks: &config.hooks,
+                        protected: device_protected(&config, path),
+                        force,
+                        only_changed,
+                        dry_run,
+                        verify,
+                    };
+                    set_field(path, &report, &assignments, &options)
+                });
+            }
+            // With a device selector the first positional argument
+            // is a byte, not the path
+            if device.has_selector() {
+                if let Some(path) = device.path.take() {
+                    bytes.insert(0, path.to_string_lossy().to_string());
+                }
+            }
+            // The file is read before --sandbox restricts access. With
+            // --bit-offset the only value is an expression, not a byte.
+            let values = match (&from_file, bit_offset) {
+                (Some(_), _) if !bytes.is_empty() => {
+                    bail!("--from-file cannot be used with a byte list")
+                }
+                (Some(file), _) => read_file_or_stdin(file)?
+                    .into_iter()
+                    .map(|b| Some(MaskedByte::new(b)))
+                    .collect(),
+                (None, Some(_)) => Vec::new(),
+                (None, None) => parse_byte_list(&bytes, radix)?,
+            };
+            report.collection = device.merge_collection(report.collection)?;
+            let path = device.resolve_or_pick()?;
+            let config = config::Config::load(cli.config.as_deref())?;
+            if cli.sandbox {
+                if !config.hooks.is_empty() {
+                    bail!("--sandbox cannot be used with write hooks");
+                }
+                sandbox::restrict(&path)?;
+            }
+            let defaults = device_defaults(&config, &path);
+            if report.collection.is_none() {
+                report.report_id = report.report_id.or(defaults.and_then(|d| d.report_id));
+            }
+            let offset = offset.or(defaults.and_then(|d| d.offset)).unwrap_or(0);
+            let options = WriteOptions {
+                backend: backend(),
+                retry_wait,
+                hooks: &config.hooks,
+                protected: device_protected(&config, &path),
+                force,
+                only_changed,
+                dry_run,
+                verify,
+            };
+            let edits = ReportEdits {
+                select: &select,
+                fields: &field,
+                bytes: &byte,
+                u16: &u16,
+                u32: &u32,
+                byte_order,
+                bits: None,
+            };
+            if let Some(bit_offset) = bit_offset {
+                let [value] = bytes.as_slice() else {
+                    bail!("--bit-offset needs exactly one value");
+                };
+                let bits = bit_offset..bit_offset + bit_length as usize;
+                let edits = ReportEdits {
+                    bits: Some((bits, value)),
+                    ..edits
+                };
+                return set(&path, report_type, &report, &[], &edits, 0, &options);
+            }
+            set(
+                &path,
+                report_type,
+                &report,
+                &values,
+                &edits,
+                offset,
+                &options,
+            )
+        }
+        Commands::SetField {
+            mut device,
+            mut report,
+            retry_wait,
+            force,
+            only_changed,
+            dry_run,
+            verify,
+            assignments,
+        } => {
+            report.collection = device.merge_collection(report.collection)?;
+            let path = device.resolve()?;
+            let config = config::Config::load(cli.config.as_deref())?;
+            if cli.sandbox {
+                if !config.hooks.is_empty() {
+                    bail!("--sandbox cannot be used with write hooks");
+                }
+                sandbox::restrict(&path)?;
+            }
+            let options = WriteOptions {
+                backend: backend(),
+                retry_wait,
+                hooks: &config.hooks,
+                protected: device_protected(&config, &path),
+                force,
+                only_changed,
+                dry_run,
+                verify,
+            };
+            set_field(&path, &report, &assignments, &options)
+        }
+        Commands::SendOutput {
+            mut device,
+            mut report,
+            offset,
+            mut bytes,
+        } => {
+            // With a device selector the first positional argument
+            // is a byte, not the path
+            if device.has_selector() {
+                if let Some(path) = device.path.take() {
+                    bytes.insert(0, path.to_string_lossy().to_string());
+                }
+            }
+            report.collection = device.merge_collection(report.collection)?;
+            let path = device.resolve()?;
+            if cli.sandbox {
+                sandbox::restrict(&path)?;
+            }
+            send_output(&path, &report, &bytes, offset)
+        }
+        Commands::Get {
+            mut device,
+            mut report,
+            report_type,
+            no_report_id,
+            field,
+            porcelain,
+            samples,
+            interval,
+            exit_status,
+            save,
+            template,
+            format,
+            radix,
+        } => {
+            report.collection = device.merge_collection(report.collection)?;
+            let path = device.resolve()?;
+            if cli.sandbox {
+                if save.is_some() {
+                    bail!("--sandbox cannot be used with --save");
+                }
+                sandbox::restrict(&path)?;
+            }
+            let output = GetOutput {
+                no_report_id,
+                porcelain: porcelain.is_some(),
+                sampling: samples.map(|samples| Sampling { samples, interval }),
+                exit_status,
+                save,
+                template,
+                format,
+                radix,
+            };
+            get(backend(), &path, report_type, &report, &field, &output)
+        }
+        Commands::Encode {
+            mut device,
+            mut report,
+            no_report_id,
+            porcelain,
+            rdesc_file,
+            mut assignments,
+        } => {
+            // With a device selector or --rdesc-file the first positional
+            // argument is an assignment, not the path
+            if device.has_selector() || rdesc_file.is_some() {
+                if let Some(path) = device.path.take() {
+                    assignments.insert(0, path.to_string_lossy().to_string());
+                }
+            }
+            report.collection = device.merge_collection(report.collection)?;
+            let rdesc = match rdesc_file {
+                Some(file) => read_rdesc_file(&file)?,
+                None => {
+                    let path = device.resolve()?;
+                    if cli.sandbox {
+                        sandbox::restrict(&path)?;
+                    }
+                    backend().report_descriptor(&path)?
+                }
+            };
+            encode(
+                &rdesc,
+                &report,
+                &assignments,
+                no_report_id,
+                porcelain.is_some(),
+            )
+        }
+        Commands::Watch {
+            device,
+            filter,
+            interval,
+            duration,
+            notify,
+            show_last,
+            mqtt,
+            template,
+            radix,
+        } => {
+            let path = device.resolve()?;
+            // Connect before --sandbox restricts the files to resolve the
+            // broker's address with
+            let mqtt = mqtt
+                .as_ref()
+                .map(mqtt::MqttPublisher::connect)
+                .transpose()?;
+            if cli.sandbox {
+                if !notify.is_empty() {
+                    bail!("--sandbox cannot be used with --notify");
+                }
+                sandbox::restrict(&path)?;
+            }
+            let options = WatchOptions {
+                interval,
+                duration,
+                notify,
+                show_last: show_last.map(|n| n as usize),
+                mqtt,
+                template,
+                radix,
+            };
+            watch(backend(), &path, &filter, options)
+        }
+        Commands::Read {
+            device,
+            filter,
+            usage,
+            count,
+        } => {
+            let path = device.resolve()?;
+            if cli.sandbox {
+                sandbox::restrict(&path)?;
+            }
+            read_input(&path, &filter, &usage, count)
+        }
+        Commands::Resolve { identity } => resolve(&identity),
+        #[cfg(target_os = "linux")]
+        Commands::Doctor { device } => doctor(&device),
+        #[cfg(not(target_os = "linux"))]
+        Commands::Doctor { .. } => bail!("'doctor' is only available on Linux"),
+        Commands::Selftest {
+            mut device,
+            mut report,
+            force,
+        } => {
+            report.collection = device.merge_collection(report.collection)?;
+            let path = device.resolve()?;
+            let config = config::Config::load(cli.config.as_deref())?;
+            if cli.sandbox {
+                sandbox::restrict(&path)?;
+            }
+            let protected = if force {
+                Vec::new()
+            } else {
+                device_protected(&config, &path)
+            };
+            selftest(backend(), &path, &report, &protected)
+        }
+        Commands::Lint { device } => lint(&device.resolve()?),
+        Commands::Map { report_id, device } => map(&device.resolve()?, &report_id),
+        Commands::Edit {
+            device,
+            field,
+            report_id,
+            force,
+        } => {
+            let path = device.resolve()?;
+            let config = config::Config::load(cli.config.as_deref())?;
+            if cli.sandbox {
+                if !config.hooks.is_empty() {
+                    bail!("--sandbox cannot be used with write hooks");
+                }
+                sandbox::restrict(&path)?;
+            }
+            let protected = if force {
+                Vec::new()
+            } else {
+                device_protected(&config, &path)
+            };
+            let options = WriteOptions {
+                backend: backend(),
+                retry_wait: Duration::ZERO,
+                hooks: &config.hooks,
+                protected,
+                force,
+                only_changed: false,
+                dry_run: false,
+                verify: false,
+            };
+            match field {
+                Some(field) => edit(&path, &field, &report_id, &options),
+                None => tui::run(&path, &options),
+            }
+        }
+        Commands::Digitizer {
+            device,
+            report_rate,
+            latency_mode,
+            surface_switch,
+            button_switch,
+            force,
+            only_changed,
+            dry_run,
+        } => {
+            let path = device.resolve()?;
+            let config = config::Config::load(cli.config.as_deref())?;
+            let on_off = |s: Switch| s as u32;
+            let values = [
+                report_rate,
+                latency_mode.map(|m| m as u32),
+                surface_switch.map(on_off),
+                button_switch.map(on_off),
+            ];
+            if cli.sandbox {
+                if values.iter().any(Option::is_some) && !config.hooks.is_empty() {
+                    bail!("--sandbox cannot be used with write hooks");
+                }
+                sandbox::restrict(&path)?;
+            }
+            let protected = if force {
+                Vec::new()
+            } else {
+                device_protected(&config, &path)
+            };
+            let options = WriteOptions {
+                backend: backend(),
+                retry_wait: Duration::ZERO,
+                hooks: &config.hooks,
+                protected,
+                force,
+                only_changed,
+                dry_run,
+                verify: false,
+            };
+            digitizer(&path, &values, &options)
+        }
+        Commands::Stat => stat(),
+        Commands::Stats { device } => for_each_device(&device.resolve_or_all()?, stats),
+        #[cfg(not(target_os = "linux"))]
+        Commands::Record { .. } => bail!("'record' is only available on Linux"),
+        #[cfg(target_os = "linux")]
+        Commands::Record {
+            device,
+            output,
+            duration,
+            interval,
+        } => {
+            let path = device.resolve()?;
+            if interval.is_zero() {
+                bail!("The interval must not be zero");
+            }
+            // Open the output before the sandbox restricts file access
+            let mut output: Box<dyn std::io::Write> = match output {
+                Some(file) => Box::new(std::io::LineWriter::new(
+                    std::fs::File::create(&file)
+                        .with_context(|| format!("Unable to create {}", file.display()))?,
+                )),
+                None => Box::new(std::io::stdout()),
+            };
+            if cli.sandbox {
+                sandbox::restrict(&path)?;
+            }
+            record::record(backend(), &path, &mut output, duration, interval)
+        }
+        Commands::Backup { device, output } => {
+            let path = device.resolve()?;
+            // Open the output before the sandbox restricts file access
+            let mut output = match output {
+                Some(output) => {
+                    let file = std::fs::File::create(&output)
+                        .with_context(|| format!("Unable to create {}", output.display()))?;
+                    Some((output, file))
+                }
+                None => None,
+            };
+            if cli.sandbox {
+                sandbox::restrict(&path)?;
+            }
+            let snapshot = snapshot::Snapshot::take(backend(), &path)?;
+            let json = snapshot.to_json()?;
+            match &mut output {
+                Some((output, file)) => {
+                    std::io::Write::write_all(file, format!("{json}\n").as_bytes())?;
+                    eprintln!(
+                        "Saved {} Feature Report(s) to {}",
+                        snapshot.reports.len(),
+                        output.display()
+                    );
+                }
+                None => println!("{json}"),
+            }
+            Ok(())
+        }
+        Commands::Restore {
+            mut device,
+            mut file,
+            filter,
+            force,
+        } => {
+            // With a device selector the only positional argument is the
+            // snapshot, not the path
+            if device.has_selector() && file.is_none() {
+                file = device.path.take();
+            }
+            let file = file.context("Missing snapshot file")?;
+            let json = std::fs::read_to_string(&file)
+                .with_context(|| format!("Unable to read {}", file.display()))?;
+            let snapshot = snapshot::Snapshot::parse(&json)?;
+            let path = device.resolve()?;
+            let config = config::Config::load(cli.config.as_deref())?;
+            if cli.sandbox {
+                if !config.hooks.is_empty() {
+                    bail!("--sandbox cannot be used with write hooks");
+                }
+                sandbox::restrict(&path)?;
+            }
+            let options = WriteOptions {
+                backend: backend(),
+                retry_wait: Duration::ZERO,
+                hooks: &config.hooks,
+                protected: device_protected(&config, &path),
+                force,
+                only_changed: false,
+                dry_run: false,
+                verify: false,
+            };
+            restore(&path, &snapshot, &filter, &options)
+        }
+        Commands::Diff { first, second } => {
+            if cli.sandbox {
+                bail!("--sandbox is not supported for diff");
+            }
+            diff(backend(), &first, &second)
+        }
+        #[cfg(not(target_os = "linux"))]
+        Commands::Replay { .. } => bail!("'replay' is only available on Linux"),
+        #[cfg(target_os = "linux")]
+        Commands::Replay {
+            file,
+            name,
+            edit,
+            keep,
+        } => {
+            if cli.sandbox {
+                bail!("--sandbox is not supported for replay");
+            }
+            let text = std::fs::read_to_string(&file)
+                .with_context(|| format!("Unable to read {}", file.display()))?;
+            let mut recording = record::Recording::parse(&text)
+                .with_context(|| format!("Invalid recording {}", file.display()))?;
+            if let Some(edit) = edit {
+                let json = std::fs::read_to_string(&edit)
+                    .with_context(|| format!("Unable to read {}", edit.display()))?;
+                let edits = rdesc_edit::parse_edits(&json)
+                    .with_context(|| format!("Invalid edits in {}", edit.display()))?;
+                recording.rdesc = rdesc_edit::apply(&recording.rdesc, &edits)?;
+            }
+            record::replay(&recording, &name, keep)
+        }
+        Commands::Import {
+            file,
+            address,
+            rdesc,
+        } => {
+            let json = std::fs::read_to_string(&file)
+                .with_context(|| format!("Unable to read {}", file.display()))?;
+            let rdesc = rdesc
+                .map(|path| {
+                    std::fs::read(&path)
+                        .with_context(|| format!("Unable to read {}", path.display()))
+                })
+                .transpose()?;
+            let recording = capture::import_wireshark(&json, address, rdesc)
+                .with_context(|| format!("Unable to import {}", file.display()))?;
+            recording.write(&mut std::io::stdout())
+        }
+        #[cfg(not(target_os = "linux"))]
+        Commands::Driver { .. } => bail!("'driver' is only available on Linux"),
+        #[cfg(target_os = "linux")]
+        Commands::Driver { action } => {
+            if cli.sandbox {
+                bail!("--sandbox is not supported for driver");
+            }
+            driver_command(action)
+        }
+        Commands::Scene {
+            name,
+            only_changed,
+            jobs,
+        } => {
+            if cli.sandbox {
+                bail!("--sandbox is not supported for scenes with multiple devices");
+            }
+            let config = config::Config::load(cli.config.as_deref())?;
+            scene(&config, &name, only_changed, jobs as usize)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let rc = hid_feature();
+    match rc {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) if e.is::<FieldIsZero>() => ExitCode::FAILURE,
+        Err(e) => {
+            let code = if e.is::<PartialFailure>() { 2 } else { 1 };
+            if JSON_ERRORS.load(Ordering::Relaxed) {
+                eprintln!("{}", json_error(code, &e));
+            } else {
+                eprintln!("Error: {e:#}");
+            }
+            ExitCode::from(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A vendor device with the 3-byte Feature Report 1 (two 8-bit fields)
+    /// and the 2-byte Feature Report 2 (one bit and padding)
+    const RDESC: &[u8] = &[
+        0x06, 0x00, 0xff, // Usage Page (Vendor Defined Page 0xFF00)
+        0x09, 0x01, // Usage (Vendor Usage 0x01)
+        0xa1, 0x01, // Collection (Application)
+        0x85, 0x01, //   Report ID (1)
+        0x09, 0x02, //   Usage (Vendor Usage 0x02)
+        0x09, 0x03, //   Usage (Vendor Usage 0x03)
+        0x15, 0x00, //   Logical Minimum (0)
+        0x26, 0xff, 0x00, //   Logical Maximum (255)
+        0x75, 0x08, //   Report Size (8)
+        0x95, 0x02, //   Report Count (2)
+        0xb1, 0x02, //   Feature (Data,Var,Abs)
+        0x85, 0x02, //   Report ID (2)
+        0x09, 0x04, //   Usage (Vendor Usage 0x04)
+        0x25, 0x01, //   Logical Maximum (1)
+        0x75, 0x01, //   Report Size (1)
+        0x95, 0x01, //   Report Count (1)
+        0xb1, 0x02, //   Feature (Data,Var,Abs)
+        0x75, 0x07, //   Report Size (7)
+        0xb1, 0x03, //   Feature (Cnst,Var,Abs)
+        0xc0, // End Collection
+    ];
+
+    /// Returns a backend with one device at /dev/hidraw-mock
+    fn mock_device(rdesc: &[u8], reports: &[(u8, &[u8])]) -> (MockBackend, PathBuf) {
+        let path = PathBuf::from("/dev/hidraw-mock");
+        let backend = MockBackend::new();
+        backend.add_device(MockDevice {
+            info: HidrawDevice {
+                path: path.clone(),
+                name: "Mock Device".into(),
+                bus: 3,
+                vid: 0x1234,
+                pid: 0x5678,
+                phys: None,
+                serial: None,
+                port: None,
+            },
+            rdesc: rdesc.to_vec(),
+            reports: reports
+                .iter()
+                .map(|(id, bytes)| ((ReportType::Feature, *id), bytes.to_vec()))
+                .collect(),
+        });
+        (backend, path)
+    }
+
+    fn mock_backend() -> (MockBackend, PathBuf) {
+        mock_device(RDESC, &[(1, &[1, 0x10, 0x20]), (2, &[2, 0x01])])
+    }
+
+    fn feature_gets(backend: &MockBackend) -> Vec<Option<u8>> {
+        backend
+            .requests()
+            .into_iter()
+            .filter_map(|r| match r {
+                MockRequest::Get { report_id, .. } => Some(report_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn feature_sets(backend: &MockBackend) -> Vec<Vec<u8>> {
+        backend
+            .requests()
+            .into_iter()
+            .filter_map(|r| match r {
+                MockRequest::Set { bytes, .. } => Some(bytes),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[derive(Parser)]
+    struct ListArgs {
+        #[command(flatten)]
+        output: ListOutput,
+    }
+
+    fn list_output(args: &[&str]) -> ListOutput {
+        let args = std::iter::once("list").chain(args.iter().copied());
+        ListArgs::try_parse_from(args).unwrap().output
+    }
+
+    fn selection(report_id: u8) -> ReportSelection {
+        ReportSelection {
+            report_id: Some(report_id),
+            collection: None,
+            first: false,
+        }
+    }
+
+    fn write_options<'a>(
+        backend: &'a dyn HidBackend,
+        hooks: &'a config::Hooks,
+        protected: Vec<&'a config::Protected>,
+    ) -> WriteOptions<'a> {
+        WriteOptions {
+            backend,
+            retry_wait: Duration::ZERO,
+            hooks,
+            protected,
+            force: false,
+            only_changed: false,
+            dry_run: false,
+            verify: false,
+        }
+    }
+
+    fn list_feature(backend: &MockBackend, path: &Path, filter: &ReportFilter) -> Result<()> {
+        list(
+            path,
+            ReportType::Feature,
+            filter,
+            &None,
+            &None,
+            &list_output(&["--format", "json"]),
+            ListSource::Device(backend),
+        )
+    }
+
+    #[test]
+    fn test_list() {
+        let (backend, path) = mock_backend();
+        list_feature(&backend, &path, &filter(&[], &[])).unwrap();
+        assert_eq!(feature_gets(&backend), [Some(1), Some(2)]);
+
+        // Only the selected reports are fetched
+        let (backend, path) = mock_backend();
+        list_feature(&backend, &path, &filter(&[], &["1"])).unwrap();
+        assert_eq!(feature_gets(&backend), [Some(2)]);
+
+        // Nothing is fetched without values
+        let (backend, path) = mock_backend();
+        list(
+            &path,
+            ReportType::Feature,
+            &filter(&[], &[]),
+            &None,
+            &None,
+            &list_output(&[]),
+            ListSource::Descriptor(RDESC.to_vec()),
+        )
+        .unwrap();
+        assert!(backend.requests().is_empty());
+
+        // The device has no Input Reports, so there is nothing to fetch
+        let (backend, path) = mock_backend();
+        list(
+            &path,
+            ReportType::Input,
+            &filter(&[], &[]),
+            &None,
+            &None,
+            &list_output(&[]),
+            ListSource::Device(&backend),
+        )
+        .unwrap();
+        assert!(feature_gets(&backend).is_empty());
+
+        // An unknown device fails
+        assert!(list_feature(&MockBackend::new(), &path, &filter(&[], &[])).is_err());
+    }
+
+    #[test]
+    fn test_set() {
+        let hooks = config::Hooks::default();
+        let (backend, path) = mock_backend();
+        let bytes = [Some(MaskedByte::new(0x12))];
+        let options = write_options(&backend, &hooks, Vec::new());
+        let edits = ReportEdits::default();
+        set(
+            &path,
+            ReportType::Feature,
+            &selection(1),
+            &bytes,
+            &edits,
+            1,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(feature_sets(&backend), [vec![1, 0x12, 0x20]]);
+        assert_eq!(
+            backend.feature_report(&path, Some(1)),
+            Some(vec![1, 0x12, 0x20])
+        );
+
+        // Bits are counted from the start of the report, i.e. the Report ID
+        let edits = ReportEdits {
+            bits: Some((16..20, "0xf")),
+            ..Default::default()
+        };
+        set(
+            &path,
+            ReportType::Feature,
+            &selection(1),
+            &[],
+            &edits,
+            0,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            backend.feature_report(&path, Some(1)),
+            Some(vec![1, 0x12, 0x2f])
+        );
+
+        // A report that already has the values is not written
+        let (backend, path) = mock_backend();
+        let options = WriteOptions {
+            only_changed: true,
+            ..write_options(&backend, &hooks, Vec::new())
+        };
+        let bytes = [Some(MaskedByte::new(0x01))];
+        let edits = ReportEdits::default();
+        set(
+            &path,
+            ReportType::Feature,
+            &selection(2),
+            &bytes,
+            &edits,
+            1,
+            &options,
+        )
+        .unwrap();
+        assert!(feature_sets(&backend).is_empty());
+
+        // Nor is a dry run
+        let options = WriteOptions {
+            dry_run: true,
+            ..write_options(&backend, &hooks, Vec::new())
+        };
+        let bytes = [Some(MaskedByte::new(0x00))];
+        set(
+            &path,
+            ReportType::Feature,
+            &selection(2),
+            &bytes,
+            &edits,
+            1,
+            &options,
+        )
+        .unwrap();
+        assert!(feature_sets(&backend).is_empty());
+        assert_eq!(backend.feature_report(&path, Some(2)), Some(vec![2, 0x01]));
+
+        // Bytes outside of the report are refused
+        let options = write_options(&backend, &hooks, Vec::new());
+        let bytes = [Some(MaskedByte::new(0)), Some(MaskedByte::new(0))];
+        assert!(set(
+            &path,
+            ReportType::Feature,
+            &selection(2),
+            &bytes,
+            &edits,
+            1,
+            &options
+        )
+        .is_err());
+        assert!(feature_sets(&backend).is_empty());
+    }
+
+    #[test]
+    fn test_set_protected() {
+        let hooks = config::Hooks::default();
+        let protected = config::Protected {
+            device: None,
+            report_id: Some(1),
+            field: None,
+            reason: Some("testing".into()),
+        };
+        let (backend, path) = mock_backend();
+        let bytes = [Some(MaskedByte::new(0x12))];
+        let edits = ReportEdits::default();
+        let options = write_options(&backend, &hooks, vec![&protected]);
+        let error = set(
+            &path,
+            ReportType::Feature,
+            &selection(1),
+            &bytes,
+            &edits,
+            1,
+            &options,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("write-protected (testing)"));
+        assert!(feature_sets(&backend).is_empty());
+
+        // Other reports can still be written
+        set(
+            &path,
+            ReportType::Feature,
+            &selection(2),
+            &bytes,
+            &edits,
+            1,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(feature_sets(&backend), [vec![2, 0x12]]);
+
+        // And the protected one with --force
+        let options = WriteOptions {
+            force: true,
+            ..write_options(&backend, &hooks, vec![&protected])
+        };
+        set(
+            &path,
+            ReportType::Feature,
+            &selection(1),
+            &bytes,
+            &edits,
+            1,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            backend.feature_report(&path, Some(1)),
+            Some(vec![1, 0x12, 0x20])
+        );
+    }
+
+    #[test]
+    fn test_template_lines() {
+        let rdesc = ReportDescriptor::try_from(RDESC).unwrap();
+        let report = &rdesc.feature_reports()[0];
+        let template = "{report_id}:{byte_offset}={value}";
+        let lines = template_lines(
+            report,
+            None,
+            &[1, 0x10, 0x20],
+            |_| true,
+            template,
+            Radix::Dec,
+        )
+        .unwrap();
+        assert_eq!(lines, ["1:1=16", "1:2=32"]);
+
+        // Only the selected fields
+        let second = |f: &Field| f.bits().start == 16;
+        let lines =
+            template_lines(report, None, &[1, 0x10, 0x20], second, template, Radix::Bin).unwrap();
+        assert_eq!(lines, ["1:2=0b00100000"]);
+
+        // Only the changed fields
+        let previous: &[u8] = &[1, 0x10, 0x20];
+        let lines = template_lines(
+            report,
+            Some(previous),
+            &[1, 0x11, 0x20],
+            |_| true,
+            template,
+            Radix::Dec,
+        )
+        .unwrap();
+        assert_eq!(lines, ["1:1=17"]);
+
+        assert!(template_lines(report, None, previous, |_| true, "{nope}", Radix::Dec).is_err());
+    }
+
+    #[test]
+    fn test_env_lines() {
+        let rdesc = ReportDescriptor::try_from(RDESC).unwrap();
+        let report = &rdesc.feature_reports()[0];
+        let lines = env_lines(report, &[1, 0x10, 0x20], |_| true, Radix::Dec);
+        assert_eq!(
+            lines,
+            ["HID_VENDOR_USAGE_0X02=16", "HID_VENDOR_USAGE_0X03=32"]
+        );
+        let second = |f: &Field| f.bits().start == 16;
+        let lines = env_lines(report, &[1, 0x10, 0x20], second, Radix::Hex);
+        assert_eq!(lines, ["HID_VENDOR_USAGE_0X03=0x20"]);
+
+        // A repeated usage gets a suffix
+        let Field::Variable(var) = &report.fields()[0] else {
+            panic!("not a Variable field");
+        };
+        let mut names = EnvNames::default();
+        assert_eq!(names.line(&var.usage, "1"), "HID_VENDOR_USAGE_0X02=1");
+        assert_eq!(names.line(&var.usage, "2"), "HID_VENDOR_USAGE_0X02_2=2");
+    }
+
+    #[test]
+    fn test_json_error() {
+        let hooks = config::Hooks::default();
+        let protected = config::Protected {
+            device: None,
+            report_id: Some(2),
+            field: None,
+            reason: None,
+        };
+        let (backend, path) = mock_backend();
+        let options = write_options(&backend, &hooks, vec![&protected]);
+        let bytes = [Some(MaskedByte::new(0x12))];
+        let error = set(
+            &path,
+            ReportType::Feature,
+            &selection(2),
+            &bytes,
+            &ReportEdits::default(),
+            1,
+            &options,
+        )
+        .context("Unable to write")
+        .unwrap_err();
+        let json = json_error(1, &error);
+        assert_eq!(json["error"]["device"], "/dev/hidraw-mock");
+        assert_eq!(json["error"]["report_id"], 2);
+        // The device context does not show up in the message
+        let causes = json["error"]["causes"].as_array().unwrap();
+        assert_eq!(causes.len(), 2);
+        assert_eq!(json["error"]["message"], format!("{error:#}"));
+        assert!(json["error"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Unable to write: Report 2"));
+
+        let json = json_error(2, &anyhow::anyhow!("No device"));
+        assert_eq!(json["error"]["code"], 2);
+        assert!(json["error"]["device"].is_null());
+    }
+
+    #[test]
+    fn test_selftest() {
+        let (backend, path) = mock_backend();
+        selftest(&backend, &path, &selection(1), &[]).unwrap();
+        assert_eq!(feature_sets(&backend), [vec![1, 0x10, 0x20]]);
+
+        let protected = config::Protected {
+            device: None,
+            report_id: Some(2),
+            field: None,
+            reason: None,
+        };
+        assert!(selftest(&backend, &path, &selection(2), &[&protected]).is_err());
+        assert_eq!(feature_sets(&backend).len(), 1);
+    }
+
+    #[test]
+    fn test_restore() {
+        let hooks = config::Hooks::default();
+        let (backend, path) = mock_backend();
+        let snapshot = snapshot::Snapshot::take(&backend, &path).unwrap();
+        let options = write_options(&backend, &hooks, Vec::new());
+        let bytes = [Some(MaskedByte::new(0x55))];
+        let edits = ReportEdits::default();
+        set(
+            &path,
+            ReportType::Feature,
+            &selection(1),
+            &bytes,
+            &edits,
+            2,
+            &options,
+        )
+        .unwrap();
+        diff(&backend, &path, &path).unwrap();
+
+        restore(&path, &snapshot, &filter(&["1"], &[]), &options).unwrap();
+        assert_eq!(
+            backend.feature_report(&path, Some(1)),
+            Some(vec![1, 0x10, 0x20])
+        );
+        // Only the selected report is written
+        assert_eq!(
+            feature_sets(&backend),
+            [vec![1, 0x10, 0x55], vec![1, 0x10, 0x20]]
+        );
+    }
+
+    #[test]
+    fn test_digitizer() {
+        // Report 3 with the Surface Switch and Button Switch bits
+        const RDESC: &[u8] = &[
+            0x05, 0x0d, // Usage Page (Digitizers)
+            0x09, 0x0e, // Usage (Device Configuration)
+            0xa1, 0x01, // Collection (Application)
+            0x85, 0x03, //   Report ID (3)
+            0x09, 0x57, //   Usage (Surface Switch)
+            0x09, 0x58, //   Usage (Button Switch)
+            0x15, 0x00, //   Logical Minimum (0)
+            0x25, 0x01, //   Logical Maximum (1)
+            0x75, 0x01, //   Report Size (1)
+            0x95, 0x02, //   Report Count (2)
+            0xb1, 0x02, //   Feature (Data,Var,Abs)
+            0x75, 0x06, //   Report Size (6)
+            0x95, 0x01, //   Report Count (1)
+            0xb1, 0x03, //   Feature (Cnst,Var,Abs)
+            0xc0, // End Collection
+        ];
+        let (backend, path) = mock_device(RDESC, &[(3, &[3, 0x03])]);
+        let surface_off = [None, None, Some(0), None];
+
+        // The write goes through set, i.e. protection and hooks apply
+        let hooks = config::Hooks {
+            pre_write: vec!["false".into()],
+            ..Default::default()
+        };
+        let options = write_options(&backend, &hooks, Vec::new());
+        let error = digitizer(&path, &surface_off, &options).unwrap_err();
+        assert!(error.to_string().contains("Hook 'false' failed"));
+        let hooks = config::Hooks::default();
+        let protected = config::Protected {
+            device: None,
+            report_id: Some(3),
+            field: Some("Surface Switch".into()),
+            reason: None,
+        };
+        let options = write_options(&backend, &hooks, vec![&protected]);
+        assert!(digitizer(&path, &surface_off, &options).is_err());
+        let options = WriteOptions {
+            dry_run: true,
+            ..write_options(&backend, &hooks, Vec::new())
+        };
+        digitizer(&path, &surface_off, &options).unwrap();
+        assert!(feature_sets(&backend).is_empty());
+
+        // Only the Surface Switch bit is changed
+        let options = write_options(&backend, &hooks, Vec::new());
+        digitizer(&path, &surface_off, &options).unwrap();
+        assert_eq!(feature_sets(&backend), [vec![3, 0x02]]);
+    }
+
+    #[test]
+    fn test_watch_history() {
+        let rdesc = ReportDescriptor::try_from(RDESC).unwrap();
+        let reports: Vec<_> = rdesc.feature_reports().iter().collect();
+        let mut history = WatchHistory::new(&reports, 2);
+        assert_eq!(history.fields.len(), 3);
+
+        let secs = Duration::from_secs;
+        history.record(0, &[1, 0x10, 0x20], secs(0));
+        history.record(1, &[2, 0x01], secs(0));
+        // Unchanged values are not recorded again
+        history.record(0, &[1, 0x10, 0x20], secs(1));
+        history.record(0, &[1, 0x11, 0x20], secs(2));
+        // The oldest value is dropped
+        history.record(0, &[1, 0x12, 0x20], secs(3));
+        assert_eq!(
+            history.lines(),
+            [
+                "report 1 Vendor Defined Page FF00 / Vendor Usage 0x02: 17 (2.000), 18 (3.000)",
+                "report 1 Vendor Defined Page FF00 / Vendor Usage 0x03: 32 (0.000)",
+                "report 2 Vendor Defined Page FF00 / Vendor Usage 0x04: 1 (0.000)",
+            ]
+        );
+    }
+
+    fn filter(include: &[&str], exclude: &[&str]) -> ReportFilter {
+        let parse = |ranges: &[&str]| ranges.iter().map(|r| r.parse().unwrap()).collect();
+        ReportFilter {
+            include: parse(include),
+            exclude: parse(exclude),
+        }
+    }
+
+    #[test]
+    fn test_parse_byte() {
+        // A prefix wins over the radix
+        for radix in [Radix::Hex, Radix::Dec, Radix::Bin] {
+            assert_eq!(parse_byte("0x4a", radix).unwrap(), 0x4a);
+            assert_eq!(parse_byte("0b01001010", radix).unwrap(), 0x4a);
+            assert_eq!(parse_byte("0o112", radix).unwrap(), 0x4a);
+            assert_eq!(parse_byte("d:74", radix).unwrap(), 0x4a);
+        }
+        assert_eq!(parse_byte("4a", Radix::Hex).unwrap(), 0x4a);
+        assert_eq!(parse_byte("10", Radix::Hex).unwrap(), 0x10);
+        assert_eq!(parse_byte("10", Radix::Dec).unwrap(), 10);
+        assert_eq!(parse_byte("10", Radix::Bin).unwrap(), 2);
+        assert_eq!(parse_byte("0xff", Radix::Dec).unwrap(), 0xff);
+
+        assert!(parse_byte("4a", Radix::Dec).is_err());
+        assert!(parse_byte("2", Radix::Bin).is_err());
+        assert!(parse_byte("256", Radix::Dec).is_err());
+        assert!(parse_byte("0x100", Radix::Dec).is_err());
+        assert!(parse_byte("0x", Radix::Dec).is_err());
+        assert!(parse_byte("", Radix::Dec).is_err());
+        assert!(parse_byte("-1", Radix::Dec).is_err());
+        let err = parse_byte("0xzz", Radix::Dec).unwrap_err();
+        assert!(format!("{err:#}").contains("Invalid value '0xzz'"));
+    }
+
+    #[test]
+    fn test_parse_byte_list() {
+        let parse = |bytes: &[&str], radix| {
+            let bytes: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+            parse_byte_list(&bytes, radix)
+                .map(|list| list.iter().map(|b| b.map(|b| (b.value, b.mask))).collect())
+        };
+        let list: Vec<Option<(u8, u8)>> = parse(&["1", "0x20", "xx"], Radix::Dec).unwrap();
+        assert_eq!(list, [Some((1, 0xff)), Some((0x20, 0xff)), None]);
+        assert_eq!(parse(&["20"], Radix::Hex).unwrap(), [Some((0x20, 0xff))]);
+        assert!(parse(&[], Radix::Dec).unwrap().is_empty());
+
+        // Masks
+        assert_eq!(parse(&["80/c0"], Radix::Hex).unwrap(), [Some((0x80, 0xc0))]);
+        assert_eq!(
+            parse(&["0x80/0b11000000"], Radix::Dec).unwrap(),
+            [Some((0x80, 0xc0))]
+        );
+        let err = parse(&["0xc0/0x80"], Radix::Dec).unwrap_err();
+        assert!(format!("{err:#}").contains("bits outside of the mask"));
+        assert!(parse(&["xx/ff"], Radix::Hex).is_err());
+
+        // Multi-byte values, the digits are always hex
+        assert_eq!(
+            parse(&["le:1f40"], Radix::Dec).unwrap(),
+            [Some((0x40, 0xff)), Some((0x1f, 0xff))]
+        );
+        assert_eq!(
+            parse(&["be:1f40"], Radix::Dec).unwrap(),
+            [Some((0x1f, 0xff)), Some((0x40, 0xff))]
+        );
+        let list = parse(&["le:12345678", "be:12345678"], Radix::Dec).unwrap();
+        let bytes: Vec<u8> = list.iter().map(|b| b.unwrap().0).collect();
+        assert_eq!(bytes, [0x78, 0x56, 0x34, 0x12, 0x12, 0x34, 0x56, 0x78]);
+        let err = parse(&["le:1f4"], Radix::Dec).unwrap_err();
+        assert!(format!("{err:#}").contains("expected 4 or 8 hex digits"));
+        assert!(parse(&["le:123456"], Radix::Dec).is_err());
+        assert!(parse(&["be:zzzz"], Radix::Dec).is_err());
+
+        // Repeats
+        assert_eq!(parse(&["0*3"], Radix::Dec).unwrap(), [Some((0, 0xff)); 3]);
+        assert_eq!(parse(&["xx*2", "1"], Radix::Dec).unwrap().len(), 3);
+        assert_eq!(parse(&["le:0102*2"], Radix::Dec).unwrap().len(), 4);
+        assert!(parse(&["0*0"], Radix::Dec).unwrap().is_empty());
+        // The count is decimal whatever the radix
+        assert_eq!(parse(&["ff*10"], Radix::Hex).unwrap().len(), 10);
+        let err = parse(&["0*x"], Radix::Dec).unwrap_err();
+        assert!(format!("{err:#}").contains("Invalid repeat count in '0*x'"));
+
+        // The size limit
+        let max = format!("0*{MAX_REPORT_SIZE}");
+        assert_eq!(parse(&[&max], Radix::Dec).unwrap().len(), MAX_REPORT_SIZE);
+        let err = parse(&[&max, "0"], Radix::Dec).unwrap_err();
+        assert!(format!("{err:#}").contains("exceeds the maximum report size"));
+        assert!(parse(&["le:1234*99999999999999999999"], Radix::Dec).is_err());
+        assert!(parse(&[&format!("be:1234*{}", usize::MAX)], Radix::Dec).is_err());
+    }
+
+    #[test]
+    fn test_report_filter_matches() {
+        // An empty filter matches everything
+        let f = filter(&[], &[]);
+        assert!(f.matches(None));
+        assert!(f.matches(Some(1)));
+        assert!(f.matches(Some(255)));
+
+        let f = filter(&["4", "8-9"], &[]);
+        assert!(f.matches(Some(4)));
+        assert!(f.matches(Some(8)));
+        assert!(f.matches(Some(9)));
+        assert!(!f.matches(Some(5)));
+        assert!(!f.matches(Some(10)));
+        assert!(!f.matches(None));
+
+        let f = filter(&[], &["2-3"]);
+        assert!(f.matches(Some(1)));
+        assert!(!f.matches(Some(2)));
+        assert!(!f.matches(Some(3)));
+        assert!(f.matches(None));
+
+        // Exclusions win over inclusions
+        let f = filter(&["1-10"], &["5"]);
+        assert!(f.matches(Some(4)));
+        assert!(!f.matches(Some(5)));
+        assert!(f.matches(Some(6)));
+
+        // 0 selects reports without a Report ID
+        let f = filter(&["0"], &[]);
+        assert!(f.matches(None));
+        assert!(!f.matches(Some(1)));
+        assert!(!filter(&[], &["0"]).matches(None));
+    }
+
+    #[test]
+    fn test_report_id_range() {
+        assert!("9-4".parse::<ReportIdRange>().is_err());
+        assert!("256".parse::<ReportIdRange>().is_err());
+        assert!("a".parse::<ReportIdRange>().is_err());
+        let range: ReportIdRange = " 4 - 9 ".parse().unwrap();
+        assert_eq!((range.first, range.last), (4, 9));
+    }
+
+    #[test]
+    fn test_for_each_parallel() {
+        let items: Vec<u32> = (0..10).collect();
+        let running = std::sync::atomic::AtomicUsize::new(0);
+        let most = std::sync::atomic::AtomicUsize::new(0);
+        let done = std::sync::Mutex::new(Vec::new());
+        let result = for_each_parallel(
+            &items,
+            3,
+            |i| format!("item {i}"),
+            |i| {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(10));
+                done.lock().unwrap().push(*i);
+                running.fetch_sub(1, Ordering::SeqCst);
+                match i % 4 {
+                    0 => bail!("failed"),
+                    _ => Ok(()),
+                }
+            },
+        );
+
+        // Items 0, 4 and 8 failed, every item ran once
+        let error = result.unwrap_err();
+        let failure = error.downcast_ref::<PartialFailure>().unwrap();
+        assert_eq!((failure.failed, failure.total), (3, 10));
+        let mut done = done.into_inner().unwrap();
+        done.sort();
+        assert_eq!(done, items);
+        // Never more than the jobs at the same time
+        assert!(most.load(Ordering::SeqCst) <= 3);
+        assert!(most.load(Ordering::SeqCst) > 1);
+
+        let result = for_each_parallel(&items, 4, |i| i.to_string(), |_| bail!("failed"));
+        assert!(!result.unwrap_err().is::<PartialFailure>());
     }
 }
@@ -0,0 +1,375 @@
+// SPDX-License-Identifier: MIT
+
+//! Editing report descriptors to emulate corner-case devices, see
+//! `hid-feature replay --edit`.
+//!
+//! The edits are given as a JSON array and applied in order:
+//!
+//! ```json
+//! [
+//!   { "op": "add_field", "report_id": 9, "usage_page": 65280, "usage": 1,
+//!     "report_size": 13, "report_count": 100,
+//!     "logical_minimum": 0, "logical_maximum": 8191 },
+//!   { "op": "set_field", "report_id": 4, "index": 0,
+//!     "logical_minimum": -5, "logical_maximum": 300 },
+//!   { "op": "remove_field", "report_id": 2, "index": 1 }
+//! ]
+//! ```
+//!
+//! A field is identified by its report's `report_id` (omitted for a
+//! report without a Report ID) and the `index` of its Feature item within
+//! that report. Added fields are Feature items at the end of the last
+//! top-level collection, in a descriptor with Report IDs the `report_id` is
+//! required. Changes to a field are wrapped in Push/Pop so they
+//! do not affect the fields that follow it.
+
+use crate::items::{feature_items, parse};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+const MAIN_FEATURE: u8 = 0xb0;
+const MAIN_END_COLLECTION: u8 = 0xc0;
+const GLOBAL_USAGE_PAGE: u8 = 0x04;
+const GLOBAL_LOGICAL_MINIMUM: u8 = 0x14;
+const GLOBAL_LOGICAL_MAXIMUM: u8 = 0x24;
+const GLOBAL_REPORT_SIZE: u8 = 0x74;
+const GLOBAL_REPORT_ID: u8 = 0x84;
+const GLOBAL_REPORT_COUNT: u8 = 0x94;
+const GLOBAL_PUSH: u8 = 0xa4;
+const GLOBAL_POP: u8 = 0xb4;
+const LOCAL_USAGE: u8 = 0x08;
+
+/// Feature item data: Data, Variable, Absolute or, for padding, Constant
+const FEATURE_VARIABLE: u32 = 0x02;
+const FEATURE_CONSTANT: u32 = 0x01;
+
+/// One edit of the report descriptor, see the module documentation
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Edit {
+    /// Adds a Variable (or, if `constant`, a padding) Feature field
+    AddField {
+        report_id: Option<u8>,
+        #[serde(default)]
+        usage_page: u16,
+        #[serde(default)]
+        usage: u16,
+        report_size: u32,
+        report_count: u32,
+        #[serde(default)]
+        logical_minimum: i32,
+        #[serde(default)]
+        logical_maximum: i32,
+        #[serde(default)]
+        constant: bool,
+    },
+    /// Removes a Feature field, the report becomes smaller
+    RemoveField { report_id: Option<u8>, index: usize },
+    /// Changes the logical range, size or count of a Feature field
+    SetField {
+        report_id: Option<u8>,
+        index: usize,
+        logical_minimum: Option<i32>,
+        logical_maximum: Option<i32>,
+        report_size: Option<u32>,
+        report_count: Option<u32>,
+    },
+}
+
+/// Encodes a short item with the smallest data size for an unsigned value
+fn item(prefix: u8, value: u32) -> Vec<u8> {
+    match value {
+        0..=0xff => vec![prefix | 1, value as u8],
+        0x100..=0xffff => [vec![prefix | 2], (value as u16).to_le_bytes().to_vec()].concat(),
+        _ => [vec![prefix | 3], value.to_le_bytes().to_vec()].concat(),
+    }
+}
+
+/// Encodes a short item with the smallest data size for a signed value
+fn signed_item(prefix: u8, value: i32) -> Vec<u8> {
+    if let Ok(v) = i8::try_from(value) {
+        vec![prefix | 1, v as u8]
+    } else if let Ok(v) = i16::try_from(value) {
+        [vec![prefix | 2], v.to_le_bytes().to_vec()].concat()
+    } else {
+        [vec![prefix | 3], value.to_le_bytes().to_vec()].concat()
+    }
+}
+
+/// Returns the prefix byte of an item without its size bits
+fn prefix(bytes: &[u8]) -> u8 {
+    bytes[0] & 0xfc
+}
+
+/// Returns the offset and length of the index'th Feature item of the
+/// report and the offset of the first Local item before it
+fn find_field(rdesc: &[u8], report_id: Option<u8>, index: usize) -> Result<(usize, usize, usize)> {
+    let fields: Vec<_> = feature_items(rdesc)?
+        .into_iter()
+        .filter(|f| f.report_id == report_id)
+        .collect();
+    let name = match report_id {
+        Some(id) => format!("report {id}"),
+        None => "the report without Report ID".into(),
+    };
+    let Some(field) = fields.get(index) else {
+        bail!(
+            "{name} has {} Feature item(s), there is no index {index}",
+            fields.len()
+        );
+    };
+    let main = field.items.last().context("Missing Feature item")?;
+    // Local items apply to the next main item only
+    let locals = field
+        .items
+        .iter()
+        .find(|i| (i.bytes[0] >> 2) & 0x3 == 2)
+        .map_or(main.offset, |i| i.offset);
+    Ok((main.offset, main.bytes.len(), locals))
+}
+
+impl Edit {
+    fn apply(&self, rdesc: &[u8]) -> Result<Vec<u8>> {
+        let mut rdesc = rdesc.to_vec();
+        match self {
+            Edit::AddField {
+                report_id,
+                usage_page,
+                usage,
+                report_size,
+                report_count,
+                logical_minimum,
+                logical_maximum,
+                constant,
+            } => {
+                let parsed = parse(&rdesc)?;
+                // The pushed state would inherit the last Report ID and add
+                // the field to whichever report comes last
+                if report_id.is_none()
+                    && parsed.iter().any(|i| prefix(&i.bytes) == GLOBAL_REPORT_ID)
+                {
+                    bail!(
+                        "The report descriptor uses Report IDs, the added field needs a report_id"
+                    );
+                }
+                let end = parsed
+                    .iter()
+                    .rev()
+                    .find(|i| prefix(&i.bytes) == MAIN_END_COLLECTION)
+                    .map_or(rdesc.len(), |i| i.offset);
+                let mut items = vec![GLOBAL_PUSH];
+                if let Some(id) = report_id {
+                    items.extend(item(GLOBAL_REPORT_ID, *id as u32));
+                }
+                if !constant {
+                    items.extend(item(GLOBAL_USAGE_PAGE, *usage_page as u32));
+                    items.extend(item(LOCAL_USAGE, *usage as u32));
+                }
+                items.extend(signed_item(GLOBAL_LOGICAL_MINIMUM, *logical_minimum));
+                items.extend(signed_item(GLOBAL_LOGICAL_MAXIMUM, *logical_maximum));
+                items.extend(item(GLOBAL_REPORT_SIZE, *report_size));
+                items.extend(item(GLOBAL_REPORT_COUNT, *report_count));
+                let data = if *constant {
+                    FEATURE_CONSTANT
+                } else {
+                    FEATURE_VARIABLE
+                };
+                items.extend(item(MAIN_FEATURE, data));
+                items.push(GLOBAL_POP);
+                rdesc.splice(end..end, items);
+            }
+            Edit::RemoveField { report_id, index } => {
+                let (main, len, locals) = find_field(&rdesc, *report_id, *index)?;
+                // Keep any Global items between the Local items and the
+                // Feature item, they still apply to the fields that follow
+                let globals: Vec<u8> = parse(&rdesc[locals..main])?
+                    .into_iter()
+                    .filter(|i| (i.bytes[0] >> 2) & 0x3 == 1)
+                    .flat_map(|i| i.bytes)
+                    .collect();
+                rdesc.splice(locals..main + len, globals);
+            }
+            Edit::SetField {
+                report_id,
+                index,
+                logical_minimum,
+                logical_maximum,
+                report_size,
+                report_count,
+            } => {
+                let (main, len, _) = find_field(&rdesc, *report_id, *index)?;
+                let mut items = vec![GLOBAL_PUSH];
+                if let Some(min) = logical_minimum {
+                    items.extend(signed_item(GLOBAL_LOGICAL_MINIMUM, *min));
+                }
+                if let Some(max) = logical_maximum {
+                    items.extend(signed_item(GLOBAL_LOGICAL_MAXIMUM, *max));
+                }
+                if let Some(size) = report_size {
+                    items.extend(item(GLOBAL_REPORT_SIZE, *size));
+                }
+                if let Some(count) = report_count {
+                    items.extend(item(GLOBAL_REPORT_COUNT, *count));
+                }
+                rdesc.insert(main + len, GLOBAL_POP);
+                rdesc.splice(main..main, items);
+            }
+        }
+        Ok(rdesc)
+    }
+}
+
+/// Parses the edits from their JSON representation, see the module
+/// documentation
+pub fn parse_edits(json: &str) -> Result<Vec<Edit>> {
+    serde_json::from_str(json).context("Invalid report descriptor edits")
+}
+
+/// Applies the edits in order and returns the edited report descriptor
+pub fn apply(rdesc: &[u8], edits: &[Edit]) -> Result<Vec<u8>> {
+    edits
+        .iter()
+        .enumerate()
+        .try_fold(rdesc.to_vec(), |rdesc, (idx, edit)| {
+            edit.apply(&rdesc)
+                .with_context(|| format!("Edit {} ({edit:?}) failed", idx + 1))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hidreport::{Field, Report, ReportDescriptor};
+
+    /// Feature Report 1 with two 8-bit fields and Feature Report 2 with
+    /// one bit and padding, which uses the Logical Minimum of report 1
+    const RDESC: &[u8] = &[
+        0x06, 0x00, 0xff, // Usage Page (Vendor Defined Page 0xFF00)
+        0x09, 0x01, // Usage (Vendor Usage 0x01)
+        0xa1, 0x01, // Collection (Application)
+        0x85, 0x01, //   Report ID (1)
+        0x09, 0x02, //   Usage (Vendor Usage 0x02)
+        0x09, 0x03, //   Usage (Vendor Usage 0x03)
+        0x15, 0x00, //   Logical Minimum (0)
+        0x26, 0xff, 0x00, //   Logical Maximum (255)
+        0x75, 0x08, //   Report Size (8)
+        0x95, 0x02, //   Report Count (2)
+        0xb1, 0x02, //   Feature (Data,Var,Abs)
+        0x85, 0x02, //   Report ID (2)
+        0x09, 0x04, //   Usage (Vendor Usage 0x04)
+        0x25, 0x01, //   Logical Maximum (1)
+        0x75, 0x01, //   Report Size (1)
+        0x95, 0x01, //   Report Count (1)
+        0xb1, 0x02, //   Feature (Data,Var,Abs)
+        0x75, 0x07, //   Report Size (7)
+        0xb1, 0x03, //   Feature (Cnst,Var,Abs)
+        0xc0, // End Collection
+    ];
+
+    /// Applies the JSON edits to [RDESC] and parses the result
+    fn edited(json: &str) -> Result<ReportDescriptor> {
+        let rdesc = apply(RDESC, &parse_edits(json)?)?;
+        Ok(ReportDescriptor::try_from(&rdesc)?)
+    }
+
+    /// The size in bytes and the (logical minimum, logical maximum, bits) of
+    /// each Variable field of a Feature Report
+    type Layout = (usize, Vec<(i32, i32, usize)>);
+
+    fn layout(rdesc: &ReportDescriptor, report_id: u8) -> Option<Layout> {
+        let report = rdesc
+            .feature_reports()
+            .iter()
+            .find(|r| r.report_id().map(u8::from) == Some(report_id))?;
+        let fields = report
+            .fields()
+            .iter()
+            .filter_map(|f| match f {
+                Field::Variable(var) => Some((
+                    i32::from(var.logical_minimum),
+                    i32::from(var.logical_maximum),
+                    var.bits.len(),
+                )),
+                _ => None,
+            })
+            .collect();
+        Some((report.size_in_bytes(), fields))
+    }
+
+    #[test]
+    fn test_add_field() {
+        let rdesc = edited(
+            r#"[{ "op": "add_field", "report_id": 2, "usage_page": 65280, "usage": 5,
+                  "report_size": 16, "report_count": 1, "logical_minimum": -1,
+                  "logical_maximum": 1000 }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            layout(&rdesc, 2),
+            Some((4, vec![(0, 1, 1), (-1, 1000, 16)]))
+        );
+        assert_eq!(layout(&rdesc, 1), Some((3, vec![(0, 255, 8), (0, 255, 8)])));
+
+        // A new report
+        let rdesc = edited(
+            r#"[{ "op": "add_field", "report_id": 9, "report_size": 8, "report_count": 1,
+                  "constant": true }]"#,
+        )
+        .unwrap();
+        assert_eq!(layout(&rdesc, 9), Some((2, vec![])));
+
+        // Without its Report ID, the field would land in report 2
+        let error =
+            edited(r#"[{ "op": "add_field", "report_size": 8, "report_count": 1 }]"#).unwrap_err();
+        assert!(format!("{error:#}").contains("needs a report_id"));
+    }
+
+    #[test]
+    fn test_remove_field() {
+        let rdesc = edited(r#"[{ "op": "remove_field", "report_id": 2, "index": 0 }]"#).unwrap();
+        assert_eq!(layout(&rdesc, 2), Some((2, vec![])));
+
+        // Report 2 still has the Logical Minimum, Report Size and Count
+        // given before the removed Feature item
+        let rdesc = edited(r#"[{ "op": "remove_field", "report_id": 1, "index": 0 }]"#).unwrap();
+        assert_eq!(layout(&rdesc, 1), None);
+        assert_eq!(layout(&rdesc, 2), Some((2, vec![(0, 1, 1)])));
+
+        let error =
+            edited(r#"[{ "op": "remove_field", "report_id": 2, "index": 2 }]"#).unwrap_err();
+        assert!(format!("{error:#}").contains("there is no index 2"));
+        let error = edited(r#"[{ "op": "remove_field", "index": 0 }]"#).unwrap_err();
+        assert!(format!("{error:#}").contains("the report without Report ID"));
+    }
+
+    #[test]
+    fn test_set_field() {
+        let rdesc = edited(
+            r#"[{ "op": "set_field", "report_id": 1, "index": 0, "logical_minimum": -5,
+                  "logical_maximum": 300, "report_size": 16 }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            layout(&rdesc, 1),
+            Some((5, vec![(-5, 300, 16), (-5, 300, 16)]))
+        );
+        // The Pop restores the state for report 2
+        assert_eq!(layout(&rdesc, 2), Some((2, vec![(0, 1, 1)])));
+
+        // The edits apply in order, to the edited descriptor
+        let rdesc = edited(
+            r#"[{ "op": "set_field", "report_id": 2, "index": 0, "report_count": 8 },
+                { "op": "remove_field", "report_id": 2, "index": 1 }]"#,
+        )
+        .unwrap();
+        assert_eq!(layout(&rdesc, 2), Some((2, vec![(0, 1, 1); 8])));
+    }
+
+    #[test]
+    fn test_parse_edits() {
+        assert!(parse_edits("[]").unwrap().is_empty());
+        assert!(parse_edits(r#"[{ "op": "rename_field" }]"#).is_err());
+        assert!(parse_edits(r#"[{ "op": "remove_field", "index": 0, "size": 1 }]"#).is_err());
+        assert!(parse_edits("{").is_err());
+    }
+}
@@ -0,0 +1,488 @@
+// SPDX-License-Identifier: MIT
+
+//! Session captures as written by `hid-feature record`.
+//!
+//! A recording is a line-based text file. Empty lines and lines starting
+//! with `#` are comments, every other line is a record of the form
+//! `<type>: <fields>` where all bytes are space-separated hexadecimal
+//! values and timestamps are seconds since the start of the recording
+//! with microsecond precision:
+//!
+//! ```text
+//! # hid-feature recording v1
+//! # /dev/hidraw3
+//! R: 52 05 01 09 02 a1 01 ...
+//! F: 0.000012 4 3 04 01 78
+//! I: 0.731004 4 01 00 02 ff
+//! F: 2.000210 4 3 04 00 78
+//! ```
+//!
+//! - `R: <size> <bytes>`: the report descriptor, always the first record
+//! - `F: <timestamp> <report id> <size> <bytes>`: a Feature Report, as
+//!   fetched at the start of the recording and whenever it changed. The
+//!   bytes include the Report ID if the report has one, a report without
+//!   a Report ID has the report id 0.
+//! - `I: <timestamp> <size> <bytes>`: an Input Report as read from the
+//!   device, including the Report ID if any
+//!
+//! A recording can be replayed onto a virtual uhid device with
+//! `hid-feature replay`. Recording and replaying are only available on
+//! Linux, the format itself is not platform-specific.
+
+use crate::print_bytes;
+#[cfg(target_os = "linux")]
+use crate::uhid::{Request, UhidDevice, UHID_FEATURE_REPORT};
+#[cfg(target_os = "linux")]
+use crate::{HidBackend, MAX_REPORT_SIZE};
+use anyhow::{bail, Context, Result};
+#[cfg(target_os = "linux")]
+use hidreport::{Report, ReportDescriptor};
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+#[cfg(target_os = "linux")]
+use std::io::Read;
+use std::io::Write;
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, RawFd};
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::time::Instant;
+
+/// A timestamped record of a recording
+#[derive(Debug)]
+pub enum Event {
+    Feature {
+        time: Duration,
+        report_id: u8,
+        bytes: Vec<u8>,
+    },
+    Input {
+        time: Duration,
+        bytes: Vec<u8>,
+    },
+}
+
+impl Event {
+    pub fn time(&self) -> Duration {
+        match self {
+            Event::Feature { time, .. } | Event::Input { time, .. } => *time,
+        }
+    }
+}
+
+/// A parsed recording
+#[derive(Debug)]
+pub struct Recording {
+    pub rdesc: Vec<u8>,
+    pub events: Vec<Event>,
+}
+
+fn parse_bytes<'a>(fields: impl Iterator<Item = &'a str>) -> Result<Vec<u8>> {
+    let mut fields = fields;
+    let size: usize = fields.next().context("Missing size")?.parse()?;
+    let bytes = fields
+        .map(|b| u8::from_str_radix(b, 16))
+        .collect::<Result<Vec<u8>, _>>()?;
+    if bytes.len() != size {
+        bail!("Expected {size} bytes but got {}", bytes.len());
+    }
+    Ok(bytes)
+}
+
+fn parse_timestamp(field: Option<&str>) -> Result<Duration> {
+    let field = field.context("Missing timestamp")?;
+    field
+        .parse()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .with_context(|| format!("Invalid timestamp '{field}'"))
+}
+
+impl Recording {
+    /// Parses a recording in the format described in the module documentation
+    pub fn parse(text: &str) -> Result<Recording> {
+        let mut rdesc = None;
+        let mut events = Vec::new();
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parse_line = || -> Result<Option<Event>> {
+                let (kind, rest) = line.split_once(':').context("Missing record type")?;
+                let mut fields = rest.split_whitespace();
+                match kind {
+                    "R" => {
+                        rdesc = Some(parse_bytes(fields)?);
+                        Ok(None)
+                    }
+                    "F" => {
+                        let time = parse_timestamp(fields.next())?;
+                        let report_id = fields.next().context("Missing report id")?.parse()?;
+                        let bytes = parse_bytes(fields)?;
+                        Ok(Some(Event::Feature {
+                            time,
+                            report_id,
+                            bytes,
+                        }))
+                    }
+                    "I" => {
+                        let time = parse_timestamp(fields.next())?;
+                        let bytes = parse_bytes(fields)?;
+                        Ok(Some(Event::Input { time, bytes }))
+                    }
+                    _ => bail!("Unknown record type '{kind}'"),
+                }
+            };
+            if let Some(event) = parse_line().with_context(|| format!("Line {}", lineno + 1))? {
+                events.push(event);
+            }
+        }
+        let Some(rdesc) = rdesc else {
+            bail!("The recording does not contain a report descriptor");
+        };
+        Ok(Recording { rdesc, events })
+    }
+
+    /// Writes the recording in the format described in the module documentation
+    pub fn write(&self, output: &mut dyn Write) -> Result<()> {
+        writeln!(output, "# hid-feature recording v1")?;
+        writeln!(
+            output,
+            "R: {} {}",
+            self.rdesc.len(),
+            print_bytes(&self.rdesc)
+        )?;
+        for event in &self.events {
+            let time = format_time(event.time());
+            match event {
+                Event::Feature {
+                    report_id, bytes, ..
+                } => writeln!(
+                    output,
+                    "F: {time} {report_id} {} {}",
+                    bytes.len(),
+                    print_bytes(bytes)
+                )?,
+                Event::Input { bytes, .. } => {
+                    writeln!(output, "I: {time} {} {}", bytes.len(), print_bytes(bytes))?
+                }
+            }
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// Waits up to `timeout` for the file descriptor to become readable
+#[cfg(target_os = "linux")]
+fn poll_readable(fd: RawFd, timeout: Duration) -> Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+    let rc = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+    if rc < 0 {
+        let e = std::io::Error::last_os_error();
+        if e.kind() != std::io::ErrorKind::Interrupted {
+            return Err(e.into());
+        }
+    }
+    Ok(rc > 0)
+}
+
+fn format_time(time: Duration) -> String {
+    format!("{}.{:06}", time.as_secs(), time.subsec_micros())
+}
+
+#[cfg(target_os = "linux")]
+fn timestamp(start: Instant) -> String {
+    format_time(start.elapsed())
+}
+
+/// Fetches every Feature Report and writes those that differ from the
+/// previously fetched values.
+#[cfg(target_os = "linux")]
+fn record_feature_reports(
+    device: &dyn HidBackend,
+    path: &Path,
+    rdesc: &ReportDescriptor,
+    previous: &mut [Option<Vec<u8>>],
+    start: Instant,
+    output: &mut dyn Write,
+) -> Result<()> {
+    for (report, previous) in rdesc.feature_reports().iter().zip(previous) {
+        let rid = report.report_id().map_or(0, u8::from);
+        let report_size = report.size_in_bytes();
+        let bytes =
+            match device.get_feature_report(path, report.report_id().map(u8::from), report_size) {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("Warning: unable to fetch Feature Report {rid}: {e:#}");
+                    continue;
+                }
+            };
+        if previous.as_deref() != Some(&bytes) {
+            writeln!(
+                output,
+                "F: {} {rid} {report_size} {}",
+                timestamp(start),
+                print_bytes(&bytes)
+            )?;
+            *previous = Some(bytes);
+        }
+    }
+    Ok(())
+}
+
+/// Records the device's Feature Reports and Input Reports until the
+/// duration has passed (or forever). The Feature Reports are fetched
+/// again every `interval` and recorded if they changed.
+#[cfg(target_os = "linux")]
+pub fn record(
+    backend: &dyn HidBackend,
+    path: &Path,
+    output: &mut dyn Write,
+    duration: Option<Duration>,
+    interval: Duration,
+) -> Result<()> {
+    let rdesc_bytes = backend.report_descriptor(path)?;
+    let rdesc = ReportDescriptor::try_from(&rdesc_bytes)?;
+
+    let device = backend.open_device(path)?;
+    let mut input = std::fs::File::open(path)
+        .with_context(|| format!("Unable to open {} for reading", path.display()))?;
+
+    writeln!(output, "# hid-feature recording v1")?;
+    writeln!(output, "# {}", path.display())?;
+    writeln!(
+        output,
+        "R: {} {}",
+        rdesc_bytes.len(),
+        print_bytes(&rdesc_bytes)
+    )?;
+
+    let start = Instant::now();
+    let mut previous = vec![None; rdesc.feature_reports().len()];
+    record_feature_reports(&*device, path, &rdesc, &mut previous, start, output)?;
+    let mut next_fetch = start + interval;
+
+    let mut buf = vec![0u8; MAX_REPORT_SIZE];
+    loop {
+        let now = Instant::now();
+        let end = duration.map(|d| start + d);
+        if end.is_some_and(|end| now >= end) {
+            break;
+        }
+        let wakeup = end.map_or(next_fetch, |end| end.min(next_fetch));
+        if poll_readable(input.as_raw_fd(), wakeup.saturating_duration_since(now))? {
+            let size = input.read(&mut buf)?;
+            if size == 0 {
+                break;
+            }
+            writeln!(
+                output,
+                "I: {} {size} {}",
+                timestamp(start),
+                print_bytes(&buf[..size])
+            )?;
+        }
+
+        if Instant::now() >= next_fetch {
+            record_feature_reports(&*device, path, &rdesc, &mut previous, start, output)?;
+            next_fetch += interval;
+        }
+    }
+    output.flush()?;
+
+    Ok(())
+}
+
+/// Replays a recording onto a new uhid device with the recording's timing.
+///
+/// The virtual device answers Feature Report requests with the values
+/// recorded up to that point in time, a Feature Report written to the
+/// device replaces its recorded value. With `keep`, the device remains
+/// and keeps answering requests after the last event until interrupted.
+#[cfg(target_os = "linux")]
+pub fn replay(recording: &Recording, name: &str, keep: bool) -> Result<()> {
+    let mut device = UhidDevice::create(name, &recording.rdesc)?;
+    let mut features: HashMap<u8, Vec<u8>> = HashMap::new();
+
+    let start = Instant::now();
+    let mut events = recording.events.iter().peekable();
+    while let Some(event) = events.peek() {
+        let due = start + event.time();
+        let now = Instant::now();
+        if now < due {
+            if poll_readable(device.as_raw_fd(), due - now)? {
+                handle_request(&mut device, &mut features)?;
+            }
+            continue;
+        }
+        match event {
+            Event::Feature {
+                report_id, bytes, ..
+            } => {
+                features.insert(*report_id, bytes.clone());
+            }
+            Event::Input { bytes, .. } => device.input(bytes)?,
+        }
+        events.next();
+    }
+
+    if keep {
+        loop {
+            if poll_readable(device.as_raw_fd(), Duration::from_secs(1))? {
+                handle_request(&mut device, &mut features)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn handle_request(device: &mut UhidDevice, features: &mut HashMap<u8, Vec<u8>>) -> Result<()> {
+    match device.read_request()? {
+        Request::GetReport { id, rnum, rtype } => {
+            match features.get(&rnum).filter(|_| rtype == UHID_FEATURE_REPORT) {
+                // The reply always starts with the report number, for
+                // reports without a Report ID that is 0
+                Some(bytes) if rnum == 0 => {
+                    let data: Vec<u8> = std::iter::once(0).chain(bytes.iter().copied()).collect();
+                    device.get_report_reply(id, 0, &data)?
+                }
+                Some(bytes) => device.get_report_reply(id, 0, bytes)?,
+                None => device.get_report_reply(id, libc::EIO as u16, &[])?,
+            }
+        }
+        Request::SetReport {
+            id,
+            rnum,
+            rtype,
+            data,
+        } => {
+            if rtype == UHID_FEATURE_REPORT {
+                let bytes = if rnum == 0 {
+                    &data[1.min(data.len())..]
+                } else {
+                    &data[..]
+                };
+                features.insert(rnum, bytes.to_vec());
+            }
+            device.set_report_reply(id, 0)?
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORDING: &str = "\
+# hid-feature recording v1
+# /dev/hidraw3
+R: 6 05 01 09 02 a1 01
+
+F: 0.000012 4 3 04 01 78
+I: 0.731004 4 01 00 02 ff
+F: 2.000210 0 2 00 10
+";
+
+    fn error(text: &str) -> String {
+        format!("{:#}", Recording::parse(text).unwrap_err())
+    }
+
+    #[test]
+    fn test_parse() {
+        let recording = Recording::parse(RECORDING).unwrap();
+        assert_eq!(recording.rdesc, [0x05, 0x01, 0x09, 0x02, 0xa1, 0x01]);
+        assert_eq!(recording.events.len(), 3);
+        match &recording.events[0] {
+            Event::Feature {
+                time,
+                report_id,
+                bytes,
+            } => {
+                assert_eq!(*time, Duration::from_micros(12));
+                assert_eq!(*report_id, 4);
+                assert_eq!(bytes, &[0x04, 0x01, 0x78]);
+            }
+            event => panic!("Unexpected event {event:?}"),
+        }
+        match &recording.events[1] {
+            Event::Input { time, bytes } => {
+                assert_eq!(*time, Duration::from_micros(731004));
+                assert_eq!(bytes, &[0x01, 0x00, 0x02, 0xff]);
+            }
+            event => panic!("Unexpected event {event:?}"),
+        }
+        assert!(matches!(
+            &recording.events[2],
+            Event::Feature { report_id: 0, bytes, .. } if bytes == &[0x00, 0x10]
+        ));
+        assert_eq!(recording.events[2].time(), Duration::from_micros(2000210));
+
+        // Indented records and comments, and a recording without events
+        let recording = Recording::parse("  # comment\n  R: 1 c0  \n").unwrap();
+        assert_eq!(recording.rdesc, [0xc0]);
+        assert!(recording.events.is_empty());
+        let recording = Recording::parse("R: 0").unwrap();
+        assert!(recording.rdesc.is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(error("").contains("does not contain a report descriptor"));
+        assert!(error("F: 0.0 1 1 01").contains("does not contain a report descriptor"));
+        assert!(error("R: 1 c0\nX: 1").contains("Line 2: Unknown record type 'X'"));
+        assert!(error("R 1 c0").contains("Line 1: Missing record type"));
+        assert!(error("R:").contains("Missing size"));
+        assert!(error("R: 2 c0").contains("Expected 2 bytes but got 1"));
+        assert!(error("R: 1 c0 c0").contains("Expected 1 bytes but got 2"));
+        assert!(error("R: 1 zz").starts_with("Line 1"));
+        assert!(error("R: x c0").starts_with("Line 1"));
+
+        let line = |event: &str| error(&format!("R: 1 c0\n{event}"));
+        assert!(line("F:").contains("Line 2: Missing timestamp"));
+        assert!(line("F: 0.5").contains("Missing report id"));
+        assert!(line("F: 0.5 256 1 01").starts_with("Line 2"));
+        assert!(line("F: 0.5 1").contains("Missing size"));
+        assert!(line("I: 0.5").contains("Missing size"));
+        assert!(line("I: 0.5 1 100").starts_with("Line 2"));
+        for time in ["x", "-1", "nan", "inf", "1e30"] {
+            let e = line(&format!("I: {time} 1 01"));
+            assert!(e.contains(&format!("Invalid timestamp '{time}'")), "{e}");
+        }
+    }
+
+    #[test]
+    fn test_write() {
+        let recording = Recording::parse(RECORDING).unwrap();
+        let mut output = Vec::new();
+        recording.write(&mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert_eq!(
+            text,
+            "\
+# hid-feature recording v1
+R: 6 05 01 09 02 a1 01
+F: 0.000012 4 3 04 01 78
+I: 0.731004 4 01 00 02 ff
+F: 2.000210 0 2 00 10
+"
+        );
+        // The output parses back into the same recording
+        let parsed = Recording::parse(&text).unwrap();
+        let mut again = Vec::new();
+        parsed.write(&mut again).unwrap();
+        assert_eq!(String::from_utf8(again).unwrap(), text);
+    }
+}
@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: MIT
+
+//! Process hardening for --sandbox.
+//!
+//! Landlock restricts filesystem access to the device node and sysfs, seccomp
+//! restricts ioctls to those on hidraw nodes (and the few terminal ioctls
+//! needed for color detection and the editor).
+
+use anyhow::{bail, Context, Result};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+// linux/landlock.h, libc does not provide those
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+
+const LANDLOCK_ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const LANDLOCK_ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const LANDLOCK_ACCESS_FS_READ_DIR: u64 = 1 << 3;
+/// All access rights of Landlock ABI version 1, from EXECUTE to MAKE_SYM
+const LANDLOCK_ACCESS_FS_ABI_1: u64 = (1 << 13) - 1;
+
+#[repr(C)]
+struct LandlockRulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct LandlockPathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// Set in the syscall numbers of the x32 ABI on x86_64
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// The terminal ioctls allowed by [seccomp]: TCGETS for the color detection
+/// and the editor, TCSETSF to enter and leave raw mode, TIOCGWINSZ for the
+/// terminal size. Others like TIOCSTI could push input into the terminal.
+const TERMINAL_IOCTLS: [u32; 3] = [
+    libc::TCGETS as u32,
+    libc::TCSETSF as u32,
+    libc::TIOCGWINSZ as u32,
+];
+
+/// Offset of the lower 32 bits of the ioctl request in struct seccomp_data
+#[cfg(target_endian = "little")]
+const SECCOMP_DATA_IOCTL_REQUEST: u32 = 24;
+#[cfg(target_endian = "big")]
+const SECCOMP_DATA_IOCTL_REQUEST: u32 = 28;
+
+fn check(rc: libc::c_long, what: &str) -> Result<libc::c_long> {
+    if rc < 0 {
+        return Err(std::io::Error::last_os_error()).context(what.to_string());
+    }
+    Ok(rc)
+}
+
+fn add_path_rule(ruleset: libc::c_long, path: &Path, access: u64) -> Result<()> {
+    let cpath = CString::new(path.as_os_str().as_bytes())?;
+    let fd = unsafe { libc::open(cpath.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    check(
+        fd as libc::c_long,
+        &format!("Unable to open {}", path.display()),
+    )?;
+
+    let attr = LandlockPathBeneathAttr {
+        allowed_access: access,
+        parent_fd: fd,
+    };
+    let rc = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset,
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr as *const LandlockPathBeneathAttr,
+            0,
+        )
+    };
+    unsafe { libc::close(fd) };
+    check(
+        rc,
+        &format!("Unable to add Landlock rule for {}", path.display()),
+    )?;
+    Ok(())
+}
+
+/// Restricts filesystem access to read/write on the device node and
+/// read-only access to sysfs.
+fn landlock(device: &Path) -> Result<()> {
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<LandlockRulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    check(abi, "Landlock is not supported by this kernel")?;
+
+    let attr = LandlockRulesetAttr {
+        handled_access_fs: LANDLOCK_ACCESS_FS_ABI_1,
+    };
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const LandlockRulesetAttr,
+            std::mem::size_of::<LandlockRulesetAttr>(),
+            0,
+        )
+    };
+    let ruleset = check(ruleset, "Unable to create Landlock ruleset")?;
+
+    let result = add_path_rule(
+        ruleset,
+        device,
+        LANDLOCK_ACCESS_FS_READ_FILE | LANDLOCK_ACCESS_FS_WRITE_FILE,
+    )
+    .and_then(|_| {
+        add_path_rule(
+            ruleset,
+            Path::new("/sys"),
+            LANDLOCK_ACCESS_FS_READ_FILE | LANDLOCK_ACCESS_FS_READ_DIR,
+        )
+    })
+    .and_then(|_| {
+        let rc = unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) };
+        check(rc, "Unable to enforce Landlock ruleset").map(|_| ())
+    });
+    unsafe { libc::close(ruleset as libc::c_int) };
+    result
+}
+
+fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Restricts ioctls to the hidraw ('H') ioctl type and the terminal
+/// ioctls in [TERMINAL_IOCTLS], all other ioctls fail with EPERM.
+fn seccomp() -> Result<()> {
+    let Some(arch) = AUDIT_ARCH else {
+        bail!("seccomp filtering is not supported on this architecture");
+    };
+
+    let [tcgets, tcsetsf, tiocgwinsz] = TERMINAL_IOCTLS;
+    // The ioctl type is bits 8-15 of the request
+    let ioctl_type = |t: u8| (t as u32) << 8;
+    let mut filter = [
+        // Kill the process if the syscall is for a different architecture
+        bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 4),
+        bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, arch, 1, 0),
+        bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        // Kill the process for x32 syscall numbers, those would bypass the
+        // ioctl check below
+        bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 0),
+        bpf_jump(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            X32_SYSCALL_BIT,
+            0,
+            1,
+        ),
+        bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        // Allow anything but ioctl
+        bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            libc::SYS_ioctl as u32,
+            0,
+            7,
+        ),
+        // Allow the terminal ioctls and hidraw ioctls only
+        bpf_stmt(
+            libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+            SECCOMP_DATA_IOCTL_REQUEST,
+        ),
+        bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, tcgets, 5, 0),
+        bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, tcsetsf, 4, 0),
+        bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            tiocgwinsz,
+            3,
+            0,
+        ),
+        bpf_stmt(libc::BPF_ALU | libc::BPF_AND | libc::BPF_K, 0xff00),
+        bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            ioctl_type(b'H'),
+            1,
+            0,
+        ),
+        bpf_stmt(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+        ),
+        bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+    ];
+    let prog = libc::sock_fprog {
+        len: filter.len() as libc::c_ushort,
+        filter: filter.as_mut_ptr(),
+    };
+
+    let rc = unsafe {
+        libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    check(rc as libc::c_long, "Unable to install seccomp filter")?;
+    Ok(())
+}
+
+/// Restricts this process to accessing the given hidraw device node
+/// (and sysfs) and to hidraw ioctls. This cannot be undone.
+pub fn restrict(device: &Path) -> Result<()> {
+    let rc = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    check(rc as libc::c_long, "Unable to set no_new_privs")?;
+
+    landlock(device)?;
+    seccomp()?;
+    Ok(())
+}
@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: MIT
+
+//! Snapshots of a device's Feature Reports as written by
+//! `hid-feature backup`.
+//!
+//! A snapshot is a JSON object with the device it was taken from, the
+//! fingerprint of its report descriptor (see [rdesc_fingerprint]), the
+//! report descriptor itself and the bytes of each Feature Report in hex:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "device": "/dev/hidraw3",
+//!   "rdesc": "9c1f0a6b2d4e8f31",
+//!   "descriptor": "05 01 09 02 a1 01 ...",
+//!   "reports": [
+//!     { "report_id": 4, "bytes": "04 01 78" },
+//!     { "report_id": null, "bytes": "00 10" }
+//!   ]
+//! }
+//! ```
+//!
+//! As in a recording, the bytes include the Report ID if the report has
+//! one. A report without a Report ID has a `report_id` of `null`. The
+//! `descriptor` is optional, without it the reports cannot be decoded,
+//! e.g. by `hid-feature diff`.
+
+use crate::{rdesc_fingerprint, HidBackend};
+use anyhow::{bail, Context, Result};
+use hidreport::{Report, ReportDescriptor};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The snapshot format version, see the module documentation
+const VERSION: u32 = 1;
+
+/// Serializes bytes as space-separated hex values, see [crate::print_bytes]
+mod hex {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&crate::print_bytes(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        String::deserialize(deserializer)?
+            .split_whitespace()
+            .map(|b| u8::from_str_radix(b, 16).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+/// A Feature Report as fetched from the device
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedReport {
+    pub report_id: Option<u8>,
+    #[serde(with = "hex")]
+    pub bytes: Vec<u8>,
+}
+
+/// The Feature Reports of a device, see the module documentation
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub device: PathBuf,
+    /// The report descriptor fingerprint, see [rdesc_fingerprint]
+    pub rdesc: String,
+    /// The report descriptor, empty if the snapshot does not contain it
+    #[serde(default, with = "hex")]
+    pub descriptor: Vec<u8>,
+    pub reports: Vec<SavedReport>,
+}
+
+impl Snapshot {
+    /// Fetches every Feature Report of the device. A report that cannot be
+    /// fetched, e.g. a write-only one, is skipped with a warning.
+    pub fn take(backend: &dyn HidBackend, path: &Path) -> Result<Snapshot> {
+        let rdesc_bytes = backend.report_descriptor(path)?;
+        let rdesc = ReportDescriptor::try_from(&rdesc_bytes)?;
+        if rdesc.feature_reports().is_empty() {
+            bail!("This device does not have any Feature Reports");
+        }
+
+        let device = backend.open_device(path)?;
+        let mut reports = Vec::new();
+        for report in rdesc.feature_reports() {
+            let report_id = report.report_id().map(u8::from);
+            let rid = report_id.unwrap_or(0);
+            let bytes = match device.get_feature_report(path, report_id, report.size_in_bytes()) {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("Warning: unable to fetch Feature Report {rid}: {e:#}");
+                    continue;
+                }
+            };
+            reports.push(SavedReport { report_id, bytes });
+        }
+        Ok(Snapshot {
+            version: VERSION,
+            device: path.to_path_buf(),
+            rdesc: rdesc_fingerprint(&rdesc_bytes),
+            descriptor: rdesc_bytes,
+            reports,
+        })
+    }
+
+    /// Parses a snapshot in the format described in the module documentation
+    pub fn parse(json: &str) -> Result<Snapshot> {
+        let snapshot: Snapshot = serde_json::from_str(json).context("Invalid snapshot")?;
+        if snapshot.version != VERSION {
+            bail!(
+                "Unsupported snapshot version {}, expected {VERSION}",
+                snapshot.version
+            );
+        }
+        Ok(snapshot)
+    }
+
+    /// Returns the snapshot in the format described in the module
+    /// documentation
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = r#"{
+        "version": 1,
+        "device": "/dev/hidraw3",
+        "rdesc": "9c1f0a6b2d4e8f31",
+        "descriptor": "05 01 09 02 a1 01",
+        "reports": [
+            { "report_id": 4, "bytes": "04 01 78" },
+            { "report_id": null, "bytes": "00 10" }
+        ]
+    }"#;
+
+    fn error(json: &str) -> String {
+        format!("{:#}", Snapshot::parse(json).unwrap_err())
+    }
+
+    #[test]
+    fn test_parse() {
+        let snapshot = Snapshot::parse(SNAPSHOT).unwrap();
+        assert_eq!(snapshot.device, PathBuf::from("/dev/hidraw3"));
+        assert_eq!(snapshot.rdesc, "9c1f0a6b2d4e8f31");
+        assert_eq!(snapshot.descriptor, [0x05, 0x01, 0x09, 0x02, 0xa1, 0x01]);
+        assert_eq!(snapshot.reports.len(), 2);
+        assert_eq!(snapshot.reports[0].report_id, Some(4));
+        assert_eq!(snapshot.reports[0].bytes, [0x04, 0x01, 0x78]);
+        assert_eq!(snapshot.reports[1].report_id, None);
+        assert_eq!(snapshot.reports[1].bytes, [0x00, 0x10]);
+
+        // The descriptor is optional
+        let snapshot = Snapshot::parse(
+            r#"{ "version": 1, "device": "/dev/hidraw0", "rdesc": "0", "reports": [] }"#,
+        )
+        .unwrap();
+        assert!(snapshot.descriptor.is_empty());
+        assert!(snapshot.reports.is_empty());
+
+        // Any whitespace separates the bytes
+        let json = SNAPSHOT.replace("04 01 78", " 04\\t1   78 ");
+        let snapshot = Snapshot::parse(&json).unwrap();
+        assert_eq!(snapshot.reports[0].bytes, [0x04, 0x01, 0x78]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(error("").contains("Invalid snapshot"));
+        assert!(error("[]").contains("Invalid snapshot"));
+        assert!(error(&SNAPSHOT.replace("\"version\": 1", "\"version\": 2"))
+            .contains("Unsupported snapshot version 2, expected 1"));
+        assert!(error(&SNAPSHOT.replace("\"rdesc\"", "\"fingerprint\"")).contains("rdesc"));
+        assert!(error(&SNAPSHOT.replace("04 01 78", "04 01 zz")).contains("Invalid snapshot"));
+        assert!(error(&SNAPSHOT.replace("04 01 78", "04 100")).contains("Invalid snapshot"));
+        assert!(
+            error(&SNAPSHOT.replace("\"report_id\": 4", "\"report_id\": 256"))
+                .contains("Invalid snapshot")
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let snapshot = Snapshot::parse(SNAPSHOT).unwrap();
+        let json = snapshot.to_json().unwrap();
+        assert!(json.contains(r#""bytes": "04 01 78""#));
+        let parsed = Snapshot::parse(&json).unwrap();
+        assert_eq!(parsed.device, snapshot.device);
+        assert_eq!(parsed.rdesc, snapshot.rdesc);
+        assert_eq!(parsed.descriptor, snapshot.descriptor);
+        let reports = |s: &Snapshot| {
+            s.reports
+                .iter()
+                .map(|r| (r.report_id, r.bytes.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(reports(&parsed), reports(&snapshot));
+    }
+}
@@ -0,0 +1,513 @@
+// SPDX-License-Identifier: MIT
+
+//! The full-screen editor of `hid-feature edit` without --field.
+//!
+//! The editor lists every Variable field of the device's Feature Reports
+//! with its current value and how long ago it was fetched. Values are
+//! fetched again when they are older than [MAX_AGE] or on request, not for
+//! every redraw. Values are edited in place and validated against
+//! the field's logical range, modified reports are only written to the
+//! device on request. The writes go through `set`, i.e. protected reports
+//! and fields are refused and the hooks run as for any other write.
+//!
+//! The terminal is switched to raw mode with termios and drawn with plain
+//! ANSI escape sequences, the [Terminal] guard restores it on exit.
+//! [KeyInput] reads single key presses for commands that print line by
+//! line, e.g. `watch`.
+
+use anyhow::{bail, Context, Result};
+use hid_feature::*;
+use hidreport::*;
+use std::io::{IsTerminal, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Switches stdin into raw mode, returning the previous terminal
+/// attributes. ISIG is cleared too so Ctrl-C arrives as a key and the
+/// terminal is restored before exiting.
+fn enter_raw_mode() -> Result<libc::termios> {
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to query the terminal");
+    }
+    let mut raw = saved;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+    raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+    raw.c_cc[libc::VMIN] = 1;
+    raw.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to set up the terminal");
+    }
+    Ok(saved)
+}
+
+/// Puts the terminal into raw mode and the alternate screen, restoring both
+/// when dropped
+struct Terminal {
+    saved: libc::termios,
+}
+
+impl Terminal {
+    fn enter() -> Result<Terminal> {
+        if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+            bail!("The editor needs a terminal, use --field to edit a single field");
+        }
+        let saved = enter_raw_mode()?;
+        // Alternate screen, hidden cursor
+        print!("\x1b[?1049h\x1b[?25l");
+        std::io::stdout().flush()?;
+        Ok(Terminal { saved })
+    }
+
+    /// Returns the number of rows of the terminal
+    fn rows(&self) -> usize {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+            0 if size.ws_row > 0 => size.ws_row as usize,
+            _ => 24,
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.saved) };
+    }
+}
+
+/// How long a fetched report is shown before the editor fetches it again,
+/// some devices stall their input stream during a GET_REPORT
+const MAX_AGE: Duration = Duration::from_secs(5);
+
+/// Reads single key presses without echo until dropped. Unlike
+/// [Terminal], the output is left alone.
+pub(crate) struct KeyInput {
+    saved: libc::termios,
+}
+
+impl KeyInput {
+    /// Returns `None` if stdin is not a terminal
+    pub(crate) fn enable() -> Result<Option<KeyInput>> {
+        if !std::io::stdin().is_terminal() {
+            return Ok(None);
+        }
+        Ok(Some(KeyInput {
+            saved: enter_raw_mode()?,
+        }))
+    }
+
+    /// Waits up to `timeout` for a key press
+    pub(crate) fn wait(&self, timeout: Duration) -> Result<Option<Key>> {
+        let mut fd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+        match unsafe { libc::poll(&mut fd, 1, timeout) } {
+            n if n > 0 => Ok(Some(read_key()?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Drop for KeyInput {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.saved) };
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Enter,
+    Escape,
+    Backspace,
+    Quit,
+    Char(char),
+}
+
+/// Returns true if another byte is available on stdin within a few
+/// milliseconds, used to tell the Escape key from an escape sequence
+fn pending_input() -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut fd, 1, 50) > 0 }
+}
+
+fn read_key() -> Result<Key> {
+    let mut stdin = std::io::stdin().lock();
+    let mut byte = [0u8; 1];
+    let mut next = || -> Result<u8> {
+        stdin.read_exact(&mut byte)?;
+        Ok(byte[0])
+    };
+    let key = match next()? {
+        0x1b if !pending_input() => Key::Escape,
+        0x1b => match (next()?, next()?) {
+            (b'[', b'A') | (b'O', b'A') => Key::Up,
+            (b'[', b'B') | (b'O', b'B') => Key::Down,
+            (b'[', b'5') if next()? == b'~' => Key::PageUp,
+            (b'[', b'6') if next()? == b'~' => Key::PageDown,
+            _ => Key::Char('\0'),
+        },
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        // Ctrl-C, Ctrl-D
+        0x03 | 0x04 => Key::Quit,
+        // Ctrl-S
+        0x13 => Key::Char('w'),
+        b => Key::Char(b as char),
+    };
+    Ok(key)
+}
+
+/// A Feature Report as fetched from the device
+struct ReportState {
+    report_id: Option<u8>,
+    size: usize,
+    /// The report's bytes, including the Report ID if any
+    bytes: Vec<u8>,
+}
+
+/// A field as shown in one row of the editor
+struct Row {
+    /// Index into the [ReportState]s
+    report: usize,
+    name: String,
+    var: VariableField,
+    value: i32,
+    /// The new value, if modified but not yet written
+    pending: Option<i32>,
+}
+
+impl Row {
+    fn range(&self) -> (i32, i32) {
+        (
+            i32::from(self.var.logical_minimum),
+            i32::from(self.var.logical_maximum),
+        )
+    }
+}
+
+struct Editor<'a> {
+    path: &'a Path,
+    device: CachingBackend<Box<dyn HidBackend + 'a>>,
+    options: &'a super::WriteOptions<'a>,
+    reports: Vec<ReportState>,
+    rows: Vec<Row>,
+    selected: usize,
+    scroll: usize,
+    /// The value being typed, if any
+    input: Option<String>,
+    status: String,
+}
+
+impl Editor<'_> {
+    /// Fetches the reports that are older than [MAX_AGE] (or all reports
+    /// with `refresh`) and extracts the values of all rows. A modification
+    /// is kept unless the field now has the new value.
+    fn update(&mut self, refresh: bool) -> Result<()> {
+        for report in self.reports.iter_mut() {
+            let rid = report.report_id.unwrap_or(0);
+            let bytes = match refresh {
+                true => self.device.refresh(
+                    self.path,
+                    ReportType::Feature,
+                    report.report_id,
+                    report.size,
+                ),
+                false => self
+                    .device
+                    .get_feature_report(self.path, report.report_id, report.size),
+            };
+            report.bytes =
+                bytes.with_context(|| format!("Failed to fetch Feature Report {rid}"))?;
+        }
+        for row in self.rows.iter_mut() {
+            row.value = row.var.extract(&self.reports[row.report].bytes)?.into();
+            row.pending = row.pending.filter(|v| *v != row.value);
+        }
+        Ok(())
+    }
+
+    /// Fetches all reports and extracts the values of all rows, discarding
+    /// any modifications
+    fn reload(&mut self) -> Result<()> {
+        for row in self.rows.iter_mut() {
+            row.pending = None;
+        }
+        self.update(true)
+    }
+
+    /// Returns how long ago the report was fetched, e.g. "3s"
+    fn age(&self, report: &ReportState) -> String {
+        match self
+            .device
+            .cached(self.path, ReportType::Feature, report.report_id)
+        {
+            Some(cached) if cached.age() < Duration::from_secs(60) => {
+                format!("{}s", cached.age().as_secs())
+            }
+            Some(cached) => format!("{}m", cached.age().as_secs() / 60),
+            None => "-".into(),
+        }
+    }
+
+    fn modified(&self) -> usize {
+        self.rows.iter().filter(|r| r.pending.is_some()).count()
+    }
+
+    /// Writes every report with a modified field and re-reads all values.
+    /// Only the bits of the modified fields are written, see [super::set].
+    fn write(&mut self) -> Result<()> {
+        let mut written = 0;
+        for (idx, report) in self.reports.iter().enumerate() {
+            let pending = self
+                .rows
+                .iter()
+                .filter(|r| r.report == idx)
+                .filter_map(|r| Some((&r.var.bits, r.pending? as u32)));
+            let bytes = super::field_bytes(report.size, pending);
+            if bytes.iter().all(Option::is_none) {
+                continue;
+            }
+            let selection = super::ReportSelection {
+                report_id: report.report_id,
+                collection: None,
+                first: false,
+            };
+            super::set(
+                self.path,
+                ReportType::Feature,
+                &selection,
+                &bytes,
+                &super::ReportEdits::default(),
+                0,
+                self.options,
+            )
+            .with_context(|| {
+                format!(
+                    "Failed to write Feature Report {}",
+                    report.report_id.unwrap_or(0)
+                )
+            })?;
+            written += 1;
+        }
+        self.reload()?;
+        self.status = format!("Wrote {written} report(s)");
+        Ok(())
+    }
+
+    /// Validates the typed value and stores it as the selected row's
+    /// pending value
+    fn commit_input(&mut self, input: &str) {
+        let row = &mut self.rows[self.selected];
+        let (min, max) = row.range();
+        let value = match input.strip_prefix("0x") {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => input.parse::<i64>(),
+        };
+        match value {
+            Ok(v) if (min as i64..=max as i64).contains(&v) => {
+                let v = v as i32;
+                row.pending = (v != row.value).then_some(v);
+                self.status.clear();
+            }
+            Ok(v) => self.status = format!("Value {v} is outside the logical range {min}..={max}"),
+            Err(e) => self.status = format!("Invalid value '{input}': {e}"),
+        }
+    }
+
+    fn draw(&mut self, rows: usize) -> Result<()> {
+        // Title, header, blank line and status line
+        let visible = rows.saturating_sub(4).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + visible {
+            self.scroll = self.selected + 1 - visible;
+        }
+
+        let name_width = self
+            .rows
+            .iter()
+            .map(|r| r.name.len())
+            .max()
+            .unwrap_or(0)
+            .clamp(5, 50);
+        let mut out = String::from("\x1b[H\x1b[2J");
+        out += &format!(
+            "\x1b[1m{}\x1b[0m  ↑/↓ select, Enter edit, w write, r refresh, q quit\r\n",
+            self.path.display()
+        );
+        out += &format!(
+            "\x1b[4m{:>4}  {:<name_width$}  {:>12}  {:<20}  {:>4}\x1b[0m\r\n",
+            "ID", "Field", "Value", "Range", "Age"
+        );
+        for (idx, row) in self.rows.iter().enumerate().skip(self.scroll).take(visible) {
+            let report = &self.reports[row.report];
+            let rid = report.report_id.map_or("-".into(), |id| id.to_string());
+            let value = match (&self.input, row.pending) {
+                (Some(input), _) if idx == self.selected => format!("{input}_"),
+                (_, Some(pending)) => format!("{} → {pending}*", row.value),
+                _ => row.value.to_string(),
+            };
+            let (min, max) = row.range();
+            let mut name = row.name.clone();
+            if name.len() > name_width {
+                name.truncate(name.floor_char_boundary(name_width - 1));
+                name.push('…');
+            }
+            let line = format!(
+                "{rid:>4}  {name:<name_width$}  {value:>12}  {:<20}  {:>4}",
+                format!("{min}..={max}"),
+                self.age(report)
+            );
+            if idx == self.selected {
+                out += &format!("\x1b[7m{line}\x1b[0m\r\n");
+            } else {
+                out += &format!("{line}\r\n");
+            }
+        }
+        let modified = self.modified();
+        let status = if modified > 0 && self.status.is_empty() {
+            format!("{modified} unwritten change(s)")
+        } else {
+            self.status.clone()
+        };
+        out += &format!("\x1b[{rows};1H{status}");
+        print!("{out}");
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+
+    /// Handles a key press, returning false if the editor should exit
+    fn handle(&mut self, key: Key, page: usize) -> Result<bool> {
+        if let Some(mut input) = self.input.take() {
+            match key {
+                Key::Enter if input.is_empty() => {}
+                Key::Enter => self.commit_input(&input),
+                Key::Escape => {}
+                Key::Quit => return Ok(false),
+                Key::Backspace => {
+                    input.pop();
+                    self.input = Some(input);
+                }
+                Key::Char(c) if c.is_ascii_hexdigit() || c == '-' || c == 'x' => {
+                    input.push(c);
+                    self.input = Some(input);
+                }
+                _ => self.input = Some(input),
+            }
+            return Ok(true);
+        }
+
+        let last = self.rows.len() - 1;
+        match key {
+            Key::Up | Key::Char('k') => self.selected = self.selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.selected = (self.selected + 1).min(last),
+            Key::PageUp => self.selected = self.selected.saturating_sub(page),
+            Key::PageDown => self.selected = (self.selected + page).min(last),
+            Key::Enter => self.input = Some(String::new()),
+            Key::Char(c) if c.is_ascii_digit() || c == '-' => self.input = Some(c.to_string()),
+            Key::Backspace => self.rows[self.selected].pending = None,
+            Key::Char('w') => {
+                if let Err(e) = self.write() {
+                    self.status = format!("{e:#}");
+                }
+            }
+            Key::Char('r') => {
+                self.reload()?;
+                self.status = "Refreshed all reports".into();
+                return Ok(true);
+            }
+            Key::Char('q') | Key::Escape | Key::Quit => {
+                let modified = self.modified();
+                // Quitting with unwritten changes needs a second q
+                if modified == 0 || self.status.starts_with("Discard") {
+                    return Ok(false);
+                }
+                self.status =
+                    format!("Discard {modified} unwritten change(s)? Press q again to quit");
+                return Ok(true);
+            }
+            _ => {}
+        }
+        self.status.clear();
+        Ok(true)
+    }
+}
+
+/// Runs the editor on all Feature Reports of the device, writing them
+/// with the options
+pub(crate) fn run(path: &Path, options: &super::WriteOptions) -> Result<()> {
+    let backend = options.backend;
+    let rdesc = ReportDescriptor::try_from(&backend.report_descriptor(path)?)?;
+    let mut reports = Vec::new();
+    let mut rows = Vec::new();
+    for report in rdesc.feature_reports() {
+        let fields = report
+            .fields()
+            .iter()
+            .filter_map(|f| match f {
+                Field::Variable(var) => Some(var),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if fields.is_empty() {
+            continue;
+        }
+        for var in fields {
+            rows.push(Row {
+                report: reports.len(),
+                name: usage_name(&var.usage),
+                var: var.clone(),
+                value: 0,
+                pending: None,
+            });
+        }
+        reports.push(ReportState {
+            report_id: report.report_id().map(u8::from),
+            size: report.size_in_bytes(),
+            bytes: Vec::new(),
+        });
+    }
+    if rows.is_empty() {
+        bail!("This device does not have any Feature fields");
+    }
+
+    let mut editor = Editor {
+        path,
+        device: CachingBackend::new(backend.open_device(path)?, MAX_AGE),
+        options,
+        reports,
+        rows,
+        selected: 0,
+        scroll: 0,
+        input: None,
+        status: String::new(),
+    };
+    editor.reload()?;
+
+    let terminal = Terminal::enter()?;
+    loop {
+        let rows = terminal.rows();
+        if let Err(e) = editor.update(false) {
+            editor.status = format!("{e:#}");
+        }
+        editor.draw(rows)?;
+        let key = read_key()?;
+        if !editor.handle(key, rows.saturating_sub(4).max(1))? {
+            break;
+        }
+    }
+    Ok(())
+}
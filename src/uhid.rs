@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: MIT
+
+//! A minimal client for /dev/uhid to create virtual HID devices.
+//!
+//! The event layouts follow linux/uhid.h. Events are always written and
+//! read as the full `struct uhid_event`.
+
+use anyhow::{bail, Context, Result};
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+
+const UHID_DESTROY: u32 = 1;
+const UHID_START: u32 = 2;
+const UHID_STOP: u32 = 3;
+const UHID_OPEN: u32 = 4;
+const UHID_CLOSE: u32 = 5;
+const UHID_OUTPUT: u32 = 6;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+
+/// `rtype` of UHID_GET_REPORT and UHID_SET_REPORT
+pub const UHID_FEATURE_REPORT: u8 = 0;
+
+const UHID_DATA_MAX: usize = 4096;
+const HID_MAX_DESCRIPTOR_SIZE: usize = 4096;
+/// sizeof(struct uhid_event), the type plus the largest request (uhid_create2_req)
+const UHID_EVENT_SIZE: usize = 4 + 128 + 64 + 64 + 2 + 2 + 4 * 4 + HID_MAX_DESCRIPTOR_SIZE;
+
+const BUS_VIRTUAL: u16 = 0x06;
+
+/// An event sent by the kernel to the virtual device
+#[derive(Debug)]
+pub enum Request {
+    Start,
+    Stop,
+    Open,
+    Close,
+    Output,
+    GetReport {
+        id: u32,
+        rnum: u8,
+        rtype: u8,
+    },
+    SetReport {
+        id: u32,
+        rnum: u8,
+        rtype: u8,
+        data: Vec<u8>,
+    },
+    Other,
+}
+
+/// A virtual HID device, destroyed when dropped
+pub struct UhidDevice {
+    file: std::fs::File,
+}
+
+fn event(event_type: u32) -> Vec<u8> {
+    let mut buf = vec![0u8; UHID_EVENT_SIZE];
+    buf[0..4].copy_from_slice(&event_type.to_ne_bytes());
+    buf
+}
+
+impl UhidDevice {
+    /// Creates a virtual device with the given name and report descriptor
+    pub fn create(name: &str, rdesc: &[u8]) -> Result<UhidDevice> {
+        if rdesc.len() > HID_MAX_DESCRIPTOR_SIZE {
+            bail!("Report descriptor of {} bytes is too large", rdesc.len());
+        }
+        let file = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .open("/dev/uhid")
+            .context("Unable to open /dev/uhid")?;
+        let mut device = UhidDevice { file };
+
+        let mut buf = event(UHID_CREATE2);
+        let name = name.as_bytes();
+        let len = name.len().min(127);
+        buf[4..4 + len].copy_from_slice(&name[..len]);
+        buf[260..262].copy_from_slice(&(rdesc.len() as u16).to_ne_bytes());
+        buf[262..264].copy_from_slice(&BUS_VIRTUAL.to_ne_bytes());
+        buf[280..280 + rdesc.len()].copy_from_slice(rdesc);
+        device.write(&buf)?;
+
+        Ok(device)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.file
+            .write_all(buf)
+            .context("Unable to write to /dev/uhid")
+    }
+
+    /// Sends an Input Report, including the Report ID if any
+    pub fn input(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > UHID_DATA_MAX {
+            bail!("Input Report of {} bytes is too large", data.len());
+        }
+        let mut buf = event(UHID_INPUT2);
+        buf[4..6].copy_from_slice(&(data.len() as u16).to_ne_bytes());
+        buf[6..6 + data.len()].copy_from_slice(data);
+        self.write(&buf)
+    }
+
+    /// Replies to a [Request::GetReport], `err` is an errno or 0
+    pub fn get_report_reply(&mut self, id: u32, err: u16, data: &[u8]) -> Result<()> {
+        let data = &data[..data.len().min(UHID_DATA_MAX)];
+        let mut buf = event(UHID_GET_REPORT_REPLY);
+        buf[4..8].copy_from_slice(&id.to_ne_bytes());
+        buf[8..10].copy_from_slice(&err.to_ne_bytes());
+        buf[10..12].copy_from_slice(&(data.len() as u16).to_ne_bytes());
+        buf[12..12 + data.len()].copy_from_slice(data);
+        self.write(&buf)
+    }
+
+    /// Replies to a [Request::SetReport], `err` is an errno or 0
+    pub fn set_report_reply(&mut self, id: u32, err: u16) -> Result<()> {
+        let mut buf = event(UHID_SET_REPORT_REPLY);
+        buf[4..8].copy_from_slice(&id.to_ne_bytes());
+        buf[8..10].copy_from_slice(&err.to_ne_bytes());
+        self.write(&buf)
+    }
+
+    /// Reads the next request, blocking until one is available
+    pub fn read_request(&mut self) -> Result<Request> {
+        let mut buf = vec![0u8; UHID_EVENT_SIZE];
+        let size = self
+            .file
+            .read(&mut buf)
+            .context("Unable to read from /dev/uhid")?;
+        if size < 4 {
+            bail!("Short read of {size} bytes from /dev/uhid");
+        }
+        let u32_at = |i: usize| u32::from_ne_bytes(buf[i..i + 4].try_into().unwrap());
+        Ok(match u32_at(0) {
+            UHID_START => Request::Start,
+            UHID_STOP => Request::Stop,
+            UHID_OPEN => Request::Open,
+            UHID_CLOSE => Request::Close,
+            UHID_OUTPUT => Request::Output,
+            UHID_GET_REPORT => Request::GetReport {
+                id: u32_at(4),
+                rnum: buf[8],
+                rtype: buf[9],
+            },
+            UHID_SET_REPORT => {
+                let len = u16::from_ne_bytes([buf[10], buf[11]]) as usize;
+                Request::SetReport {
+                    id: u32_at(4),
+                    rnum: buf[8],
+                    rtype: buf[9],
+                    data: buf[12..12 + len.min(UHID_DATA_MAX)].to_vec(),
+                }
+            }
+            _ => Request::Other,
+        })
+    }
+}
+
+impl AsRawFd for UhidDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl Drop for UhidDevice {
+    fn drop(&mut self) {
+        let _ = self.write(&event(UHID_DESTROY));
+    }
+}